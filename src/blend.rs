@@ -0,0 +1,64 @@
+use vulkano::pipeline::graphics::color_blend::{
+    AttachmentBlend, BlendFactor, BlendOp, ColorBlendAttachmentState, ColorBlendState,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    // Overwrites the destination, blending disabled.
+    Opaque,
+    // Classic transparency with straight (not premultiplied) colors.
+    Alpha,
+    // Transparency where the shader has already multiplied the color by alpha.
+    // Unlike straight alpha this filters and composites correctly.
+    PremultipliedAlpha,
+    // Adds the color weighted by alpha, for glows and particles. The
+    // destination alpha is kept.
+    Additive,
+    // Darkens the destination by the source color, like tinted glass. The
+    // destination alpha is kept.
+    Multiply,
+}
+
+impl BlendMode {
+    pub fn attachment_blend(self) -> Option<AttachmentBlend> {
+        let (src_color_blend_factor, dst_color_blend_factor) = match self {
+            BlendMode::Opaque => return None,
+            BlendMode::Alpha => (BlendFactor::SrcAlpha, BlendFactor::OneMinusSrcAlpha),
+            BlendMode::PremultipliedAlpha => (BlendFactor::One, BlendFactor::OneMinusSrcAlpha),
+            BlendMode::Additive => (BlendFactor::SrcAlpha, BlendFactor::One),
+            BlendMode::Multiply => (BlendFactor::DstColor, BlendFactor::Zero),
+        };
+        // Both alpha modes accumulate coverage in the destination alpha, so a
+        // translucent result stays translucent when composited again later.
+        let (src_alpha_blend_factor, dst_alpha_blend_factor) = match self {
+            BlendMode::Alpha | BlendMode::PremultipliedAlpha => {
+                (BlendFactor::One, BlendFactor::OneMinusSrcAlpha)
+            }
+            _ => (BlendFactor::Zero, BlendFactor::One),
+        };
+
+        Some(AttachmentBlend {
+            src_color_blend_factor,
+            dst_color_blend_factor,
+            color_blend_op: BlendOp::Add,
+            src_alpha_blend_factor,
+            dst_alpha_blend_factor,
+            alpha_blend_op: BlendOp::Add,
+        })
+    }
+
+    pub fn attachment_state(self) -> ColorBlendAttachmentState {
+        ColorBlendAttachmentState {
+            blend: self.attachment_blend(),
+            ..Default::default()
+        }
+    }
+}
+
+// One mode per color attachment of the subpass, in attachment order.
+pub fn color_blend_state(modes: &[BlendMode]) -> ColorBlendState {
+    ColorBlendState {
+        attachments: modes.iter().map(|mode| mode.attachment_state()).collect(),
+        ..Default::default()
+    }
+}
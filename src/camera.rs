@@ -0,0 +1,119 @@
+use std::f32::consts::FRAC_PI_2;
+
+use glam::{Mat4, Vec3};
+use vulkano::buffer::BufferContents;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Projection {
+    Perspective { fov_y: f32 },
+    // `height` is the size of the visible area in world units.
+    Orthographic { height: f32 },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    pub position: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    pub projection: Projection,
+    pub aspect: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+// Matches the `Transforms` uniform block used by the 3D lessons.
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+pub struct Transforms {
+    pub model: [[f32; 4]; 4],
+    pub view: [[f32; 4]; 4],
+    pub projection: [[f32; 4]; 4],
+}
+
+impl Camera {
+    pub fn perspective(fov_y: f32, aspect: f32, near: f32, far: f32) -> Self {
+        Camera {
+            position: Vec3::new(0.0, 0.0, 3.0),
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            projection: Projection::Perspective { fov_y },
+            aspect,
+            near,
+            far,
+        }
+    }
+
+    pub fn orthographic(height: f32, aspect: f32, near: f32, far: f32) -> Self {
+        Camera {
+            projection: Projection::Orthographic { height },
+            ..Camera::perspective(0.0, aspect, near, far)
+        }
+    }
+
+    pub fn look_at(mut self, position: Vec3, target: Vec3) -> Self {
+        self.position = position;
+        self.target = target;
+        self
+    }
+
+    // Rotates the camera around its target, keeping the distance. Pitch is
+    // clamped just short of the poles so the view never flips over.
+    pub fn orbit(&mut self, yaw: f32, pitch: f32) {
+        let offset = self.position - self.target;
+        let radius = offset.length();
+        let current_yaw = offset.x.atan2(offset.z);
+        let current_pitch = (offset.y / radius).asin();
+
+        let yaw = current_yaw + yaw;
+        let pitch = (current_pitch + pitch).clamp(-FRAC_PI_2 + 0.01, FRAC_PI_2 - 0.01);
+        self.position = self.target
+            + radius
+                * Vec3::new(
+                    pitch.cos() * yaw.sin(),
+                    pitch.sin(),
+                    pitch.cos() * yaw.cos(),
+                );
+    }
+
+    // Moves the camera towards (factor < 1) or away from (factor > 1) its
+    // target.
+    pub fn dolly(&mut self, factor: f32) {
+        self.position = self.target + (self.position - self.target) * factor;
+    }
+
+    pub fn view(&self) -> Mat4 {
+        Mat4::look_at_rh(self.position, self.target, self.up)
+    }
+
+    // glam already produces the [0, 1] depth range Vulkan expects, but clip
+    // space y points down in Vulkan so it has to be flipped.
+    pub fn projection(&self) -> Mat4 {
+        let mut projection = match self.projection {
+            Projection::Perspective { fov_y } => {
+                Mat4::perspective_rh(fov_y, self.aspect, self.near, self.far)
+            }
+            Projection::Orthographic { height } => {
+                let half_height = height * 0.5;
+                let half_width = half_height * self.aspect;
+                Mat4::orthographic_rh(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    self.near,
+                    self.far,
+                )
+            }
+        };
+        projection.y_axis.y *= -1.0;
+        projection
+    }
+
+    pub fn transforms(&self, model: Mat4) -> Transforms {
+        Transforms {
+            model: model.to_cols_array_2d(),
+            view: self.view().to_cols_array_2d(),
+            projection: self.projection().to_cols_array_2d(),
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, RgbaImage};
+
+pub fn save_gif(frames: &[RgbaImage], fps: u32, path: impl AsRef<Path>) {
+    let file = File::create(path).expect("failed to create gif file");
+    let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), 10);
+    encoder.set_repeat(Repeat::Infinite).unwrap();
+
+    let delay = Delay::from_numer_denom_ms(1000, fps);
+    encoder
+        .encode_frames(
+            frames
+                .iter()
+                .map(|frame| Frame::from_parts(frame.clone(), 0, 0, delay)),
+        )
+        .expect("failed to encode gif");
+}
+
+pub fn save_png_sequence(frames: &[RgbaImage], dir: impl AsRef<Path>) {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir).expect("failed to create frame directory");
+    for (n, frame) in frames.iter().enumerate() {
+        frame
+            .save(dir.join(format!("frame_{n:04}.png")))
+            .expect("failed to save frame");
+    }
+}
+
+// Writes an uncompressed YUV4MPEG2 stream (4:4:4, BT.601 studio range) that
+// ffmpeg and most players can read directly.
+pub fn save_y4m(frames: &[RgbaImage], fps: u32, path: impl AsRef<Path>) {
+    let Some(first) = frames.first() else {
+        return;
+    };
+    let (width, height) = first.dimensions();
+
+    let file = File::create(path).expect("failed to create y4m file");
+    let mut out = BufWriter::new(file);
+    writeln!(out, "YUV4MPEG2 W{width} H{height} F{fps}:1 Ip A1:1 C444").unwrap();
+
+    let plane_len = (width * height) as usize;
+    let mut planes = vec![0u8; plane_len * 3];
+    for frame in frames {
+        assert_eq!(frame.dimensions(), (width, height), "frame size changed");
+        for (i, pixel) in frame.pixels().enumerate() {
+            let [r, g, b, _] = pixel.0.map(|c| c as f32);
+            let y = 16.0 + 0.257 * r + 0.504 * g + 0.098 * b;
+            let u = 128.0 - 0.148 * r - 0.291 * g + 0.439 * b;
+            let v = 128.0 + 0.439 * r - 0.368 * g - 0.071 * b;
+            planes[i] = y.round() as u8;
+            planes[plane_len + i] = u.round() as u8;
+            planes[plane_len * 2 + i] = v.round() as u8;
+        }
+        out.write_all(b"FRAME\n").unwrap();
+        out.write_all(&planes).unwrap();
+    }
+    out.flush().unwrap();
+}
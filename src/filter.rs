@@ -0,0 +1,528 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use image::RgbaImage;
+use vulkano::buffer::{BufferContents, BufferUsage};
+use vulkano::command_buffer::allocator::{
+    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CopyBufferToImageInfo};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::device::{DeviceOwned, Queue};
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageUsage};
+use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::{ComputePipeline, Pipeline, PipelineBindPoint};
+
+use crate::texture;
+use crate::util;
+
+// Filters work on the stored 8 bit values as they are, without converting
+// from sRGB first. That is what most image editors do too.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFilter {
+    // Separable, a horizontal and a vertical pass.
+    GaussianBlur { sigma: f32 },
+    // Gradient magnitude of the luminance, multiplied by `scale`.
+    Sobel { scale: f32 },
+    Sharpen { amount: f32 },
+    Grayscale,
+    // `brightness` is added, `contrast` scales around mid gray.
+    BrightnessContrast { brightness: f32, contrast: f32 },
+    // Per channel median of the (2 * radius + 1)^2 neighbourhood.
+    Median { radius: u32 },
+}
+
+// Largest median radius, the shader sorts the window in a fixed size array.
+const MAX_MEDIAN_RADIUS: u32 = 3;
+
+// Largest blur sigma, the shader loops over 3 * sigma texels on each side.
+const MAX_BLUR_SIGMA: f32 = 32.0;
+
+// Parses the command line form `name[:arg[,arg]]`, e.g. `blur:2.5`,
+// `sobel:2`, `sharpen:0.8`, `grayscale`, `brightness:0.1`, `contrast:1.5`,
+// `bc:0.1,1.5` or `median:2`. Missing arguments get a default.
+impl FromStr for ImageFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, args) = s.split_once(':').unwrap_or((s, ""));
+        let args = args
+            .split(',')
+            .filter(|a| !a.is_empty())
+            .map(|a| a.parse::<f32>().map_err(|e| format!("{s}: {e}")))
+            .collect::<Result<Vec<_>, _>>()?;
+        let arg = |i: usize, default: f32| args.get(i).copied().unwrap_or(default);
+
+        let filter = match name {
+            "blur" => ImageFilter::GaussianBlur { sigma: arg(0, 2.0) },
+            "sobel" => ImageFilter::Sobel { scale: arg(0, 1.0) },
+            "sharpen" => ImageFilter::Sharpen {
+                amount: arg(0, 1.0),
+            },
+            "grayscale" => ImageFilter::Grayscale,
+            "brightness" => ImageFilter::BrightnessContrast {
+                brightness: arg(0, 0.1),
+                contrast: 1.0,
+            },
+            "contrast" => ImageFilter::BrightnessContrast {
+                brightness: 0.0,
+                contrast: arg(0, 1.5),
+            },
+            "bc" => ImageFilter::BrightnessContrast {
+                brightness: arg(0, 0.0),
+                contrast: arg(1, 1.0),
+            },
+            "median" => {
+                let radius = arg(0, 1.0);
+                if radius < 0.0 || radius.fract() != 0.0 {
+                    return Err(format!("{s}: radius must be a whole number"));
+                }
+                ImageFilter::Median {
+                    radius: radius as u32,
+                }
+            }
+            _ => return Err(format!("unknown filter {name}")),
+        };
+
+        match filter {
+            ImageFilter::GaussianBlur { sigma } if !sigma.is_finite() || sigma <= 0.0 => {
+                Err(format!("{s}: sigma must be positive"))
+            }
+            ImageFilter::GaussianBlur { sigma } if sigma > MAX_BLUR_SIGMA => {
+                Err(format!("{s}: sigma can be at most {MAX_BLUR_SIGMA}"))
+            }
+            ImageFilter::Median { radius } if radius > MAX_MEDIAN_RADIUS => {
+                Err(format!("{s}: radius can be at most {MAX_MEDIAN_RADIUS}"))
+            }
+            filter => Ok(filter),
+        }
+    }
+}
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct BlurParams {
+    direction: [i32; 2],
+    radius: i32,
+    sigma: f32,
+}
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct SobelParams {
+    scale: f32,
+}
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct SharpenParams {
+    amount: f32,
+}
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct AdjustParams {
+    grayscale: u32,
+    brightness: f32,
+    contrast: f32,
+}
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct MedianParams {
+    radius: i32,
+}
+
+mod blur {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0, rgba8) uniform readonly image2D src;
+            layout(set = 0, binding = 1, rgba8) uniform writeonly image2D dst;
+
+            layout(push_constant) uniform Params {
+                ivec2 direction;
+                int radius;
+                float sigma;
+            } params;
+
+            void main() {
+                ivec2 p = ivec2(gl_GlobalInvocationID.xy);
+                ivec2 size = imageSize(dst);
+                if (any(greaterThanEqual(p, size))) {
+                    return;
+                }
+
+                // Edge pixels are repeated past the border.
+                vec4 sum = vec4(0.0);
+                float weights = 0.0;
+                for (int i = -params.radius; i <= params.radius; i++) {
+                    float w = exp(-float(i * i) / (2.0 * params.sigma * params.sigma));
+                    ivec2 q = clamp(p + i * params.direction, ivec2(0), size - 1);
+                    sum += w * imageLoad(src, q);
+                    weights += w;
+                }
+                imageStore(dst, p, sum / weights);
+            }
+        ",
+    }
+}
+
+mod sobel {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0, rgba8) uniform readonly image2D src;
+            layout(set = 0, binding = 1, rgba8) uniform writeonly image2D dst;
+
+            layout(push_constant) uniform Params {
+                float scale;
+            } params;
+
+            float luminance(ivec2 p, ivec2 size) {
+                vec3 c = imageLoad(src, clamp(p, ivec2(0), size - 1)).rgb;
+                return dot(c, vec3(0.299, 0.587, 0.114));
+            }
+
+            void main() {
+                ivec2 p = ivec2(gl_GlobalInvocationID.xy);
+                ivec2 size = imageSize(dst);
+                if (any(greaterThanEqual(p, size))) {
+                    return;
+                }
+
+                float tl = luminance(p + ivec2(-1, -1), size);
+                float t = luminance(p + ivec2(0, -1), size);
+                float tr = luminance(p + ivec2(1, -1), size);
+                float l = luminance(p + ivec2(-1, 0), size);
+                float r = luminance(p + ivec2(1, 0), size);
+                float bl = luminance(p + ivec2(-1, 1), size);
+                float b = luminance(p + ivec2(0, 1), size);
+                float br = luminance(p + ivec2(1, 1), size);
+
+                float gx = (tr + 2.0 * r + br) - (tl + 2.0 * l + bl);
+                float gy = (bl + 2.0 * b + br) - (tl + 2.0 * t + tr);
+                float g = params.scale * length(vec2(gx, gy));
+                imageStore(dst, p, vec4(vec3(g), imageLoad(src, p).a));
+            }
+        ",
+    }
+}
+
+mod sharpen {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0, rgba8) uniform readonly image2D src;
+            layout(set = 0, binding = 1, rgba8) uniform writeonly image2D dst;
+
+            layout(push_constant) uniform Params {
+                float amount;
+            } params;
+
+            vec4 fetch(ivec2 p, ivec2 size) {
+                return imageLoad(src, clamp(p, ivec2(0), size - 1));
+            }
+
+            void main() {
+                ivec2 p = ivec2(gl_GlobalInvocationID.xy);
+                ivec2 size = imageSize(dst);
+                if (any(greaterThanEqual(p, size))) {
+                    return;
+                }
+
+                // The center minus the 4-neighbour Laplacian.
+                vec4 c = fetch(p, size);
+                vec4 neighbours = fetch(p + ivec2(1, 0), size) + fetch(p + ivec2(-1, 0), size)
+                    + fetch(p + ivec2(0, 1), size) + fetch(p + ivec2(0, -1), size);
+                vec3 rgb = c.rgb + params.amount * (4.0 * c.rgb - neighbours.rgb);
+                imageStore(dst, p, vec4(clamp(rgb, 0.0, 1.0), c.a));
+            }
+        ",
+    }
+}
+
+mod adjust {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0, rgba8) uniform readonly image2D src;
+            layout(set = 0, binding = 1, rgba8) uniform writeonly image2D dst;
+
+            layout(push_constant) uniform Params {
+                uint grayscale;
+                float brightness;
+                float contrast;
+            } params;
+
+            void main() {
+                ivec2 p = ivec2(gl_GlobalInvocationID.xy);
+                if (any(greaterThanEqual(p, imageSize(dst)))) {
+                    return;
+                }
+
+                vec4 c = imageLoad(src, p);
+                if (params.grayscale != 0) {
+                    c.rgb = vec3(dot(c.rgb, vec3(0.299, 0.587, 0.114)));
+                }
+                c.rgb = (c.rgb - 0.5) * params.contrast + 0.5 + params.brightness;
+                imageStore(dst, p, vec4(clamp(c.rgb, 0.0, 1.0), c.a));
+            }
+        ",
+    }
+}
+
+mod median {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0, rgba8) uniform readonly image2D src;
+            layout(set = 0, binding = 1, rgba8) uniform writeonly image2D dst;
+
+            layout(push_constant) uniform Params {
+                int radius;
+            } params;
+
+            // Room for a window of radius 3.
+            const int MAX_WINDOW = 49;
+
+            void main() {
+                ivec2 p = ivec2(gl_GlobalInvocationID.xy);
+                ivec2 size = imageSize(dst);
+                if (any(greaterThanEqual(p, size))) {
+                    return;
+                }
+
+                vec4 window[MAX_WINDOW];
+                int n = 0;
+                for (int y = -params.radius; y <= params.radius; y++) {
+                    for (int x = -params.radius; x <= params.radius; x++) {
+                        window[n] = imageLoad(src, clamp(p + ivec2(x, y), ivec2(0), size - 1));
+                        n++;
+                    }
+                }
+
+                // Insertion sort of every color channel on its own, the
+                // window is small.
+                vec4 result = vec4(0.0, 0.0, 0.0, imageLoad(src, p).a);
+                for (int c = 0; c < 3; c++) {
+                    float values[MAX_WINDOW];
+                    for (int i = 0; i < n; i++) {
+                        float v = window[i][c];
+                        int j = i;
+                        while (j > 0 && values[j - 1] > v) {
+                            values[j] = values[j - 1];
+                            j--;
+                        }
+                        values[j] = v;
+                    }
+                    result[c] = values[n / 2];
+                }
+                imageStore(dst, p, result);
+            }
+        ",
+    }
+}
+
+// Runs chains of filters over RGBA8 images. The image ping-pongs between two
+// storage images, so a chain is a single submission however long it is.
+pub struct FilterChain {
+    queue: Arc<Queue>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    descriptor_set_allocator: StandardDescriptorSetAllocator,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+    blur: Arc<ComputePipeline>,
+    sobel: Arc<ComputePipeline>,
+    sharpen: Arc<ComputePipeline>,
+    adjust: Arc<ComputePipeline>,
+    median: Arc<ComputePipeline>,
+}
+
+impl FilterChain {
+    pub fn new(queue: Arc<Queue>, memory_allocator: Arc<StandardMemoryAllocator>) -> Self {
+        let device = queue.device().clone();
+        let pipeline = |shader| util::create_compute_pipeline(device.clone(), shader);
+        let blur = pipeline(blur::load(device.clone()).unwrap());
+        let sobel = pipeline(sobel::load(device.clone()).unwrap());
+        let sharpen = pipeline(sharpen::load(device.clone()).unwrap());
+        let adjust = pipeline(adjust::load(device.clone()).unwrap());
+        let median = pipeline(median::load(device.clone()).unwrap());
+
+        let descriptor_set_allocator =
+            StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+        let command_buffer_allocator = StandardCommandBufferAllocator::new(
+            device.clone(),
+            StandardCommandBufferAllocatorCreateInfo::default(),
+        );
+
+        FilterChain {
+            queue,
+            memory_allocator,
+            descriptor_set_allocator,
+            command_buffer_allocator,
+            blur,
+            sobel,
+            sharpen,
+            adjust,
+            median,
+        }
+    }
+
+    pub fn apply(&self, input: &RgbaImage, filters: &[ImageFilter]) -> RgbaImage {
+        let extent = [input.width(), input.height()];
+        let image = || {
+            util::create_render_target(
+                &self.memory_allocator,
+                Format::R8G8B8A8_UNORM,
+                extent,
+                ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST,
+            )
+        };
+        let mut images = [image(), image()];
+
+        let staging_buffer = util::create_buffer(
+            input.as_raw().iter().copied(),
+            &self.memory_allocator,
+            BufferUsage::TRANSFER_SRC,
+            MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+        );
+
+        let mut builder = util::begin_one_time(&self.command_buffer_allocator, &self.queue);
+        builder
+            .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(
+                staging_buffer,
+                images[0].clone(),
+            ))
+            .unwrap();
+
+        for &filter in filters {
+            match filter {
+                ImageFilter::GaussianBlur { sigma } => {
+                    assert!(
+                        sigma > 0.0 && sigma <= MAX_BLUR_SIGMA,
+                        "blur sigma must be in (0, {MAX_BLUR_SIGMA}], got {sigma}"
+                    );
+                    let radius = (sigma * 3.0).ceil() as i32;
+                    for direction in [[1, 0], [0, 1]] {
+                        let params = BlurParams {
+                            direction,
+                            radius,
+                            sigma,
+                        };
+                        self.record_pass(&mut builder, &self.blur, &mut images, params);
+                    }
+                }
+                ImageFilter::Sobel { scale } => {
+                    let params = SobelParams { scale };
+                    self.record_pass(&mut builder, &self.sobel, &mut images, params);
+                }
+                ImageFilter::Sharpen { amount } => {
+                    let params = SharpenParams { amount };
+                    self.record_pass(&mut builder, &self.sharpen, &mut images, params);
+                }
+                ImageFilter::Grayscale => {
+                    let params = AdjustParams {
+                        grayscale: 1,
+                        brightness: 0.0,
+                        contrast: 1.0,
+                    };
+                    self.record_pass(&mut builder, &self.adjust, &mut images, params);
+                }
+                ImageFilter::BrightnessContrast {
+                    brightness,
+                    contrast,
+                } => {
+                    let params = AdjustParams {
+                        grayscale: 0,
+                        brightness,
+                        contrast,
+                    };
+                    self.record_pass(&mut builder, &self.adjust, &mut images, params);
+                }
+                ImageFilter::Median { radius } => {
+                    assert!(
+                        radius <= MAX_MEDIAN_RADIUS,
+                        "median radius can be at most {MAX_MEDIAN_RADIUS}, got {radius}"
+                    );
+                    let params = MedianParams {
+                        radius: radius as i32,
+                    };
+                    self.record_pass(&mut builder, &self.median, &mut images, params);
+                }
+            }
+        }
+        let output = images[0].clone();
+
+        util::submit_and_wait(builder, &self.queue);
+
+        texture::read_mip_level(
+            &output,
+            0,
+            &self.memory_allocator,
+            &self.command_buffer_allocator,
+            &self.queue,
+        )
+    }
+
+    // Reads images[0], writes images[1] and swaps them, so the result of the
+    // last pass is always in images[0].
+    fn record_pass<L, P: BufferContents>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        pipeline: &Arc<ComputePipeline>,
+        images: &mut [Arc<Image>; 2],
+        push_constants: P,
+    ) {
+        let view = |image: &Arc<Image>| ImageView::new_default(image.clone()).unwrap();
+        let set = PersistentDescriptorSet::new(
+            &self.descriptor_set_allocator,
+            pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::image_view(0, view(&images[0])),
+                WriteDescriptorSet::image_view(1, view(&images[1])),
+            ],
+            [],
+        )
+        .unwrap();
+
+        let extent = images[1].extent();
+        builder
+            .bind_pipeline_compute(pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                pipeline.layout().clone(),
+                0,
+                set,
+            )
+            .unwrap()
+            .push_constants(pipeline.layout().clone(), 0, push_constants)
+            .unwrap()
+            .dispatch([extent[0].div_ceil(16), extent[1].div_ceil(16), 1])
+            .unwrap();
+        images.swap(0, 1);
+    }
+}
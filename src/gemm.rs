@@ -0,0 +1,395 @@
+use std::sync::Arc;
+
+use vulkano::buffer::{BufferContents, Subbuffer};
+use vulkano::command_buffer::allocator::{
+    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CommandBufferUsage, PrimaryAutoCommandBuffer,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::device::{DeviceOwned, Queue};
+use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::{ComputePipeline, Pipeline, PipelineBindPoint};
+use vulkano::sync::{self, GpuFuture};
+
+use crate::util;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GemmKernel {
+    // One invocation per output element reading straight from the buffers.
+    Naive,
+    // 16x16 tiles of both inputs are staged in shared memory.
+    Tiled,
+    // 64x64 output tiles where every invocation accumulates a 4x4 block in
+    // registers.
+    RegisterBlocked,
+}
+
+impl GemmKernel {
+    pub const ALL: [GemmKernel; 3] = [
+        GemmKernel::Naive,
+        GemmKernel::Tiled,
+        GemmKernel::RegisterBlocked,
+    ];
+
+    // Width and height of the output tile one workgroup computes.
+    fn tile_size(self) -> u32 {
+        match self {
+            GemmKernel::Naive | GemmKernel::Tiled => 16,
+            GemmKernel::RegisterBlocked => 64,
+        }
+    }
+}
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct Params {
+    m: u32,
+    n: u32,
+    k: u32,
+}
+
+mod naive {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0) readonly buffer A {
+                float data[];
+            } a;
+
+            layout(set = 0, binding = 1) readonly buffer B {
+                float data[];
+            } b;
+
+            layout(set = 0, binding = 2) buffer C {
+                float data[];
+            } c;
+
+            layout(push_constant) uniform Params {
+                uint m;
+                uint n;
+                uint k;
+            } params;
+
+            void main() {
+                uint col = gl_GlobalInvocationID.x;
+                uint row = gl_GlobalInvocationID.y;
+                if (row >= params.m || col >= params.n) {
+                    return;
+                }
+
+                float acc = 0.0;
+                for (uint i = 0; i < params.k; i++) {
+                    acc += a.data[row * params.k + i] * b.data[i * params.n + col];
+                }
+                c.data[row * params.n + col] = acc;
+            }
+        ",
+    }
+}
+
+mod tiled {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0) readonly buffer A {
+                float data[];
+            } a;
+
+            layout(set = 0, binding = 1) readonly buffer B {
+                float data[];
+            } b;
+
+            layout(set = 0, binding = 2) buffer C {
+                float data[];
+            } c;
+
+            layout(push_constant) uniform Params {
+                uint m;
+                uint n;
+                uint k;
+            } params;
+
+            const uint TILE = 16;
+
+            shared float tile_a[TILE * TILE];
+            shared float tile_b[TILE * TILE];
+
+            void main() {
+                uint tx = gl_LocalInvocationID.x;
+                uint ty = gl_LocalInvocationID.y;
+                uint col = gl_WorkGroupID.x * TILE + tx;
+                uint row = gl_WorkGroupID.y * TILE + ty;
+
+                // Out of range invocations still take part in loading the
+                // tiles, so nobody returns before the barriers.
+                float acc = 0.0;
+                for (uint t = 0; t < params.k; t += TILE) {
+                    tile_a[ty * TILE + tx] = row < params.m && t + tx < params.k
+                        ? a.data[row * params.k + t + tx] : 0.0;
+                    tile_b[ty * TILE + tx] = t + ty < params.k && col < params.n
+                        ? b.data[(t + ty) * params.n + col] : 0.0;
+                    barrier();
+
+                    for (uint i = 0; i < TILE; i++) {
+                        acc += tile_a[ty * TILE + i] * tile_b[i * TILE + tx];
+                    }
+                    barrier();
+                }
+
+                if (row < params.m && col < params.n) {
+                    c.data[row * params.n + col] = acc;
+                }
+            }
+        ",
+    }
+}
+
+mod register_blocked {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0) readonly buffer A {
+                float data[];
+            } a;
+
+            layout(set = 0, binding = 1) readonly buffer B {
+                float data[];
+            } b;
+
+            layout(set = 0, binding = 2) buffer C {
+                float data[];
+            } c;
+
+            layout(push_constant) uniform Params {
+                uint m;
+                uint n;
+                uint k;
+            } params;
+
+            // A workgroup computes a BM x BN block of C, walking k in steps of
+            // BK. Each invocation owns TM x TN outputs spaced 16 apart, which
+            // keeps neighbouring invocations on neighbouring shared memory.
+            const uint BM = 64;
+            const uint BN = 64;
+            const uint BK = 16;
+            const uint TM = 4;
+            const uint TN = 4;
+            const uint THREADS = 256;
+
+            // A is stored transposed so both tiles are read along a row.
+            shared float tile_a[BK * BM];
+            shared float tile_b[BK * BN];
+
+            void main() {
+                uint tx = gl_LocalInvocationID.x;
+                uint ty = gl_LocalInvocationID.y;
+                uint lid = gl_LocalInvocationIndex;
+                uint row0 = gl_WorkGroupID.y * BM;
+                uint col0 = gl_WorkGroupID.x * BN;
+
+                float acc[TM * TN];
+                for (uint i = 0; i < TM * TN; i++) {
+                    acc[i] = 0.0;
+                }
+
+                float ra[TM];
+                float rb[TN];
+                for (uint t = 0; t < params.k; t += BK) {
+                    for (uint l = 0; l < BM * BK / THREADS; l++) {
+                        uint idx = lid + l * THREADS;
+                        uint r = idx / BK;
+                        uint kk = idx % BK;
+                        tile_a[kk * BM + r] = row0 + r < params.m && t + kk < params.k
+                            ? a.data[(row0 + r) * params.k + t + kk] : 0.0;
+                    }
+                    for (uint l = 0; l < BK * BN / THREADS; l++) {
+                        uint idx = lid + l * THREADS;
+                        uint kk = idx / BN;
+                        uint cc = idx % BN;
+                        tile_b[kk * BN + cc] = t + kk < params.k && col0 + cc < params.n
+                            ? b.data[(t + kk) * params.n + col0 + cc] : 0.0;
+                    }
+                    barrier();
+
+                    for (uint kk = 0; kk < BK; kk++) {
+                        for (uint i = 0; i < TM; i++) {
+                            ra[i] = tile_a[kk * BM + ty + i * 16];
+                        }
+                        for (uint j = 0; j < TN; j++) {
+                            rb[j] = tile_b[kk * BN + tx + j * 16];
+                        }
+                        for (uint i = 0; i < TM; i++) {
+                            for (uint j = 0; j < TN; j++) {
+                                acc[i * TN + j] += ra[i] * rb[j];
+                            }
+                        }
+                    }
+                    barrier();
+                }
+
+                for (uint i = 0; i < TM; i++) {
+                    uint row = row0 + ty + i * 16;
+                    for (uint j = 0; j < TN; j++) {
+                        uint col = col0 + tx + j * 16;
+                        if (row < params.m && col < params.n) {
+                            c.data[row * params.n + col] = acc[i * TN + j];
+                        }
+                    }
+                }
+            }
+        ",
+    }
+}
+
+// Multiplies row-major f32 matrices, C = A * B with A being m x k and B being
+// k x n. All kernels are built up front so they can be picked per call.
+pub struct Gemm {
+    queue: Arc<Queue>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    descriptor_set_allocator: StandardDescriptorSetAllocator,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+    naive: Arc<ComputePipeline>,
+    tiled: Arc<ComputePipeline>,
+    register_blocked: Arc<ComputePipeline>,
+}
+
+impl Gemm {
+    pub fn new(queue: Arc<Queue>, memory_allocator: Arc<StandardMemoryAllocator>) -> Self {
+        let device = queue.device().clone();
+        let pipeline = |shader| util::create_compute_pipeline(device.clone(), shader);
+        let naive = pipeline(naive::load(device.clone()).unwrap());
+        let tiled = pipeline(tiled::load(device.clone()).unwrap());
+        let register_blocked = pipeline(register_blocked::load(device.clone()).unwrap());
+
+        let descriptor_set_allocator =
+            StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+        let command_buffer_allocator = StandardCommandBufferAllocator::new(
+            device.clone(),
+            StandardCommandBufferAllocatorCreateInfo::default(),
+        );
+
+        Gemm {
+            queue,
+            memory_allocator,
+            descriptor_set_allocator,
+            command_buffer_allocator,
+            naive,
+            tiled,
+            register_blocked,
+        }
+    }
+
+    // `a` and `b` need STORAGE_BUFFER usage. Returns the m x n product.
+    pub fn multiply(
+        &self,
+        kernel: GemmKernel,
+        a: &Subbuffer<[f32]>,
+        b: &Subbuffer<[f32]>,
+        m: u32,
+        k: u32,
+        n: u32,
+    ) -> Subbuffer<[f32]> {
+        let (command_buffer, c) = self.prepare(kernel, a, b, m, k, n);
+        self.run(&command_buffer);
+        c
+    }
+
+    // Allocates the product and records the dispatch that fills it, without
+    // submitting. `run` can then repeat it with nothing allocated in between,
+    // e.g. for timing the kernel alone.
+    pub fn prepare(
+        &self,
+        kernel: GemmKernel,
+        a: &Subbuffer<[f32]>,
+        b: &Subbuffer<[f32]>,
+        m: u32,
+        k: u32,
+        n: u32,
+    ) -> (Arc<PrimaryAutoCommandBuffer>, Subbuffer<[f32]>) {
+        assert_eq!(a.len(), m as u64 * k as u64, "A is not {m}x{k}");
+        assert_eq!(b.len(), k as u64 * n as u64, "B is not {k}x{n}");
+
+        let pipeline = match kernel {
+            GemmKernel::Naive => &self.naive,
+            GemmKernel::Tiled => &self.tiled,
+            GemmKernel::RegisterBlocked => &self.register_blocked,
+        };
+
+        let c = util::create_storage_buffer::<f32>(
+            &self.memory_allocator,
+            (m as u64 * n as u64).max(1),
+            MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+        );
+
+        let tile = kernel.tile_size();
+        let workgroups = [n.div_ceil(tile), m.div_ceil(tile), 1];
+        let max_workgroups = self
+            .queue
+            .device()
+            .physical_device()
+            .properties()
+            .max_compute_work_group_count;
+        assert!(
+            workgroups[0] <= max_workgroups[0] && workgroups[1] <= max_workgroups[1],
+            "{m}x{n} is too large for one dispatch"
+        );
+
+        let descriptor_set = util::create_storage_descriptor_set(
+            pipeline,
+            [
+                a.as_bytes().clone(),
+                b.as_bytes().clone(),
+                c.as_bytes().clone(),
+            ],
+            &self.descriptor_set_allocator,
+        );
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.queue.queue_family_index(),
+            CommandBufferUsage::MultipleSubmit,
+        )
+        .unwrap();
+        builder
+            .bind_pipeline_compute(pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                pipeline.layout().clone(),
+                0,
+                descriptor_set,
+            )
+            .unwrap()
+            .push_constants(pipeline.layout().clone(), 0, Params { m, n, k })
+            .unwrap()
+            .dispatch(workgroups)
+            .unwrap();
+
+        (builder.build().unwrap(), c)
+    }
+
+    // Submits a prepared product and waits for it.
+    pub fn run(&self, command_buffer: &Arc<PrimaryAutoCommandBuffer>) {
+        sync::now(self.queue.device().clone())
+            .then_execute(self.queue.clone(), command_buffer.clone())
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+    }
+}
@@ -0,0 +1,358 @@
+use std::sync::Arc;
+
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::allocator::{
+    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CopyImageToBufferInfo};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::device::{DeviceOwned, Queue};
+use vulkano::format::Format;
+use vulkano::image::Image;
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::ComputePipeline;
+
+use crate::util;
+
+// Must match the workgroup size in the shaders.
+const WORKGROUP_SIZE: u32 = 256;
+// The histogram shader strides over the input, more workgroups than this
+// only add contention on the global counters.
+const MAX_WORKGROUPS: u32 = 1024;
+
+// 256 bins for every channel of interleaved 8 bit data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    pub channels: Vec<[u32; 256]>,
+}
+
+impl Histogram {
+    pub fn total(&self, channel: usize) -> u64 {
+        self.channels[channel].iter().map(|&c| c as u64).sum()
+    }
+
+    pub fn mean(&self, channel: usize) -> f64 {
+        let sum: u64 = self.channels[channel]
+            .iter()
+            .enumerate()
+            .map(|(value, &count)| value as u64 * count as u64)
+            .sum();
+        sum as f64 / self.total(channel) as f64
+    }
+
+    pub fn min(&self, channel: usize) -> u8 {
+        self.percentile(channel, 0.0)
+    }
+
+    pub fn max(&self, channel: usize) -> u8 {
+        self.percentile(channel, 100.0)
+    }
+
+    // Nearest rank: the smallest value with at least `p` percent of the
+    // samples at or below it.
+    pub fn percentile(&self, channel: usize, p: f64) -> u8 {
+        let rank = (p / 100.0 * self.total(channel) as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (value, &count) in self.channels[channel].iter().enumerate() {
+            seen += count as u64;
+            if seen >= rank {
+                return value as u8;
+            }
+        }
+        panic!("empty histogram");
+    }
+
+    // Maps every value through the normalized cumulative distribution, so
+    // the output spreads evenly over 0..=255.
+    pub fn equalization_lut(&self, channel: usize) -> [u8; 256] {
+        let total = self.total(channel);
+        let mut cdf = [0u64; 256];
+        let mut sum = 0;
+        for (value, &count) in self.channels[channel].iter().enumerate() {
+            sum += count as u64;
+            cdf[value] = sum;
+        }
+
+        let cdf_min = cdf.iter().copied().find(|&c| c > 0).unwrap_or(0);
+        if total == cdf_min {
+            // A single value, there is nothing to spread.
+            return std::array::from_fn(|value| value as u8);
+        }
+        std::array::from_fn(|value| {
+            let scaled = cdf[value].saturating_sub(cdf_min) as f64 / (total - cdf_min) as f64;
+            (scaled * 255.0).round() as u8
+        })
+    }
+}
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct Params {
+    words: u32,
+    channels: u32,
+}
+
+mod count {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 256, local_size_y = 1, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0) readonly buffer Input {
+                uint data[];
+            } src;
+
+            // channel * 256 + value, zeroed before the dispatch.
+            layout(set = 0, binding = 1) buffer Bins {
+                uint counts[];
+            } bins;
+
+            layout(push_constant) uniform Params {
+                uint words;
+                uint channels;
+            } params;
+
+            const uint BINS = 4 * 256;
+
+            shared uint local_counts[BINS];
+
+            void main() {
+                uint lid = gl_LocalInvocationID.x;
+                for (uint i = lid; i < BINS; i += gl_WorkGroupSize.x) {
+                    local_counts[i] = 0;
+                }
+                barrier();
+
+                // Most increments only contend within the workgroup, the
+                // global counters are touched once per bin at the end.
+                uint stride = gl_NumWorkGroups.x * gl_WorkGroupSize.x;
+                for (uint w = gl_GlobalInvocationID.x; w < params.words; w += stride) {
+                    uint word = src.data[w];
+                    for (uint k = 0; k < 4; k++) {
+                        uint channel = (4 * w + k) % params.channels;
+                        uint value = (word >> (8 * k)) & 0xffu;
+                        atomicAdd(local_counts[channel * 256 + value], 1);
+                    }
+                }
+                barrier();
+
+                for (uint i = lid; i < BINS; i += gl_WorkGroupSize.x) {
+                    if (local_counts[i] != 0) {
+                        atomicAdd(bins.counts[i], local_counts[i]);
+                    }
+                }
+            }
+        ",
+    }
+}
+
+mod remap {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 256, local_size_y = 1, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0) readonly buffer Input {
+                uint data[];
+            } src;
+
+            layout(set = 0, binding = 1) buffer Output {
+                uint data[];
+            } dst;
+
+            // channel * 256 + value, like the bins.
+            layout(set = 0, binding = 2) readonly buffer Lut {
+                uint table[];
+            } lut;
+
+            layout(push_constant) uniform Params {
+                uint words;
+                uint channels;
+            } params;
+
+            void main() {
+                uint block = gl_WorkGroupID.y * gl_NumWorkGroups.x + gl_WorkGroupID.x;
+                uint w = block * gl_WorkGroupSize.x + gl_LocalInvocationID.x;
+                if (w >= params.words) {
+                    return;
+                }
+
+                uint word = src.data[w];
+                uint result = 0;
+                for (uint k = 0; k < 4; k++) {
+                    uint channel = (4 * w + k) % params.channels;
+                    uint value = (word >> (8 * k)) & 0xffu;
+                    result |= lut.table[channel * 256 + value] << (8 * k);
+                }
+                dst.data[w] = result;
+            }
+        ",
+    }
+}
+
+// Per channel histograms of interleaved 8 bit data, either in a buffer or in
+// an RGBA8 image.
+pub struct HistogramCounter {
+    queue: Arc<Queue>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    descriptor_set_allocator: StandardDescriptorSetAllocator,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+    count: Arc<ComputePipeline>,
+    remap: Arc<ComputePipeline>,
+}
+
+impl HistogramCounter {
+    pub fn new(queue: Arc<Queue>, memory_allocator: Arc<StandardMemoryAllocator>) -> Self {
+        let device = queue.device().clone();
+        let pipeline = |shader| util::create_compute_pipeline(device.clone(), shader);
+        let count = pipeline(count::load(device.clone()).unwrap());
+        let remap = pipeline(remap::load(device.clone()).unwrap());
+
+        let descriptor_set_allocator =
+            StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+        let command_buffer_allocator = StandardCommandBufferAllocator::new(
+            device.clone(),
+            StandardCommandBufferAllocatorCreateInfo::default(),
+        );
+
+        HistogramCounter {
+            queue,
+            memory_allocator,
+            descriptor_set_allocator,
+            command_buffer_allocator,
+            count,
+            remap,
+        }
+    }
+
+    // `bytes` holds `channels` interleaved channels and needs STORAGE_BUFFER
+    // usage. The shader reads whole words, so its length has to be a
+    // multiple of 4.
+    pub fn of_bytes(&self, bytes: &Subbuffer<[u8]>, channels: u32) -> Histogram {
+        let mut builder = util::begin_one_time(&self.command_buffer_allocator, &self.queue);
+        let bins = self.record_count(&mut builder, bytes.clone(), channels);
+        util::submit_and_wait(builder, &self.queue);
+        Self::read_bins(&bins, channels)
+    }
+
+    // The image has to be RGBA8 with TRANSFER_SRC usage.
+    pub fn of_image(&self, image: &Arc<Image>) -> Histogram {
+        assert!(
+            matches!(
+                image.format(),
+                Format::R8G8B8A8_UNORM | Format::R8G8B8A8_SRGB
+            ),
+            "expected an RGBA8 image, got {:?}",
+            image.format()
+        );
+        let [width, height, _] = image.extent();
+        let pixels = Buffer::new_slice::<u8>(
+            self.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                ..Default::default()
+            },
+            width as u64 * height as u64 * 4,
+        )
+        .expect("failed to create buffer");
+
+        let mut builder = util::begin_one_time(&self.command_buffer_allocator, &self.queue);
+        builder
+            .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+                image.clone(),
+                pixels.clone(),
+            ))
+            .unwrap();
+        let bins = self.record_count(&mut builder, pixels, 4);
+        util::submit_and_wait(builder, &self.queue);
+        Self::read_bins(&bins, 4)
+    }
+
+    // Histogram equalization of every channel but alpha. Returns the remapped
+    // bytes together with the histogram of the input.
+    pub fn equalize(&self, bytes: &Subbuffer<[u8]>, channels: u32) -> (Subbuffer<[u8]>, Histogram) {
+        let histogram = self.of_bytes(bytes, channels);
+        let table: Vec<u32> = (0..channels as usize)
+            .flat_map(|channel| {
+                let lut = if channels == 4 && channel == 3 {
+                    std::array::from_fn(|value| value as u8)
+                } else {
+                    histogram.equalization_lut(channel)
+                };
+                lut.map(u32::from)
+            })
+            .collect();
+        let lut = util::create_buffer(
+            table,
+            &self.memory_allocator,
+            BufferUsage::STORAGE_BUFFER,
+            MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+        );
+        let output = util::create_storage_buffer::<u8>(
+            &self.memory_allocator,
+            bytes.len(),
+            MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+        );
+
+        let words = (bytes.len() / 4) as u32;
+        let mut builder = util::begin_one_time(&self.command_buffer_allocator, &self.queue);
+        util::dispatch_storage(
+            &mut builder,
+            &self.remap,
+            [bytes.clone(), output.clone(), lut.into_bytes()],
+            Params { words, channels },
+            words.div_ceil(WORKGROUP_SIZE),
+            &self.descriptor_set_allocator,
+        );
+        util::submit_and_wait(builder, &self.queue);
+
+        (output, histogram)
+    }
+
+    fn record_count<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        bytes: Subbuffer<[u8]>,
+        channels: u32,
+    ) -> Subbuffer<[u32]> {
+        assert!((1..=4).contains(&channels), "1 to 4 channels are supported");
+        assert_eq!(bytes.len() % 4, 0, "the length must be a multiple of 4");
+
+        let bins = util::create_buffer(
+            (0..4 * 256).map(|_| 0u32),
+            &self.memory_allocator,
+            BufferUsage::STORAGE_BUFFER,
+            MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+        );
+
+        let words = (bytes.len() / 4) as u32;
+        util::dispatch_storage(
+            builder,
+            &self.count,
+            [bytes, bins.as_bytes().clone()],
+            Params { words, channels },
+            words.div_ceil(WORKGROUP_SIZE).min(MAX_WORKGROUPS),
+            &self.descriptor_set_allocator,
+        );
+        bins
+    }
+
+    fn read_bins(bins: &Subbuffer<[u32]>, channels: u32) -> Histogram {
+        let bins = bins.read().unwrap();
+        Histogram {
+            channels: bins
+                .chunks_exact(256)
+                .take(channels as usize)
+                .map(|chunk| chunk.try_into().unwrap())
+                .collect(),
+        }
+    }
+}
@@ -1,4 +1,28 @@
+pub mod blending;
 pub mod buffer_creation;
+pub mod camera;
 pub mod compute_pipeline;
+pub mod deferred_shading;
+pub mod depth_buffer;
+pub mod dynamic_viewport;
+pub mod fluid;
+pub mod game_of_life;
 pub mod graphics_pipeline;
+pub mod image_filters;
+pub mod image_statistics;
+pub mod indexed_drawing;
+pub mod instancing;
+#[cfg(feature = "window")]
+pub mod live_preview;
+pub mod mandelbrot;
+pub mod matrix_multiply;
+pub mod mipmaps;
+pub mod model_loading;
+pub mod n_body;
+pub mod prefix_sum;
+pub mod radix_sort;
+pub mod ray_tracing;
+pub mod reduction;
+pub mod shadow_mapping;
+pub mod textured_quad;
 pub mod using_images;
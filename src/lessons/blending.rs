@@ -0,0 +1,396 @@
+use std::sync::Arc;
+
+use image::{ImageBuffer, Rgba};
+use vulkano::buffer::{BufferContents, BufferUsage};
+use vulkano::command_buffer::allocator::{
+    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CommandBufferUsage, CopyImageToBufferInfo, RenderPassBeginInfo,
+    SubpassBeginInfo, SubpassContents, SubpassEndInfo,
+};
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::ImageUsage;
+use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::depth_stencil::{CompareOp, DepthState, DepthStencilState};
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::RasterizationState;
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{
+    GraphicsPipeline, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo,
+};
+use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, Subpass};
+use vulkano::sync::{self, GpuFuture};
+
+use crate::blend::{self, BlendMode};
+use crate::mesh::{Mesh, MeshData, MeshVertex};
+use crate::util;
+
+const CLEAR_COLOR: [f32; 4] = [0.3, 0.3, 0.3, 1.0];
+
+// Matches the push constant block shared by both shaders.
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct Quad {
+    color: [f32; 4],
+    offset: [f32; 2],
+    size: [f32; 2],
+    depth: f32,
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec3 position;
+
+            layout(push_constant) uniform Quad {
+                vec4 color;
+                vec2 offset;
+                vec2 size;
+                float depth;
+            } quad;
+
+            void main() {
+                gl_Position = vec4(position.xy * quad.size + quad.offset, quad.depth, 1.0);
+            }
+        ",
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 460
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(push_constant) uniform Quad {
+                vec4 color;
+                vec2 offset;
+                vec2 size;
+                float depth;
+            } quad;
+
+            void main() {
+                f_color = quad.color;
+            }
+        ",
+    }
+}
+
+// The fixed function blend equations of `BlendMode`, evaluated on the CPU to
+// check the rendered image.
+fn blend_reference(mode: BlendMode, src: [f32; 4], dst: [f32; 4]) -> [f32; 4] {
+    let mut out = [0.0; 4];
+    for i in 0..3 {
+        out[i] = match mode {
+            BlendMode::Opaque => src[i],
+            BlendMode::Alpha => src[i] * src[3] + dst[i] * (1.0 - src[3]),
+            BlendMode::PremultipliedAlpha => src[i] + dst[i] * (1.0 - src[3]),
+            BlendMode::Additive => src[i] * src[3] + dst[i],
+            BlendMode::Multiply => src[i] * dst[i],
+        }
+        .min(1.0);
+    }
+    out[3] = match mode {
+        BlendMode::Opaque => src[3],
+        BlendMode::Alpha | BlendMode::PremultipliedAlpha => src[3] + dst[3] * (1.0 - src[3]),
+        BlendMode::Additive | BlendMode::Multiply => dst[3],
+    };
+    out
+}
+
+// Every mode expects a differently prepared color for the same "translucent
+// red": premultiplied alpha wants the color scaled by alpha up front, and
+// multiply has no alpha of its own, so the color is faded towards white.
+fn prepare_color(mode: BlendMode, color: [f32; 4]) -> [f32; 4] {
+    let [r, g, b, a] = color;
+    match mode {
+        BlendMode::PremultipliedAlpha => [r * a, g * a, b * a, a],
+        BlendMode::Multiply => [1.0 - a + r * a, 1.0 - a + g * a, 1.0 - a + b * a, a],
+        _ => color,
+    }
+}
+
+pub fn blending() {
+    let mut vk_device = util::create_device();
+    let queue = vk_device.queues.next().unwrap();
+    let device = vk_device.device.clone();
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+
+    let image = util::create_image(
+        &memory_allocator,
+        ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+        MemoryTypeFilter::PREFER_DEVICE,
+    );
+
+    let depth_format = util::find_depth_format(device.physical_device());
+    let depth_image = util::create_depth_image(&memory_allocator, depth_format, [1024, 1024]);
+
+    let buf = util::create_buffer(
+        (0..1024 * 1024 * 4).map(|_| 0u8),
+        &memory_allocator,
+        BufferUsage::TRANSFER_DST,
+        MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+    );
+
+    let quad = Mesh::new(MeshData::quad(), &memory_allocator);
+
+    let render_pass = vulkano::single_pass_renderpass!(
+        device.clone(),
+        attachments: {
+            color: {
+                format: Format::R8G8B8A8_UNORM,
+                samples: 1,
+                load_op: Clear,
+                store_op: Store,
+            },
+            depth: {
+                format: depth_format,
+                samples: 1,
+                load_op: Clear,
+                store_op: DontCare,
+            },
+        },
+        pass: {
+            color: [color],
+            depth_stencil: {depth},
+        },
+    )
+    .unwrap();
+
+    let view = ImageView::new_default(image.clone()).unwrap();
+    let depth_view = ImageView::new_default(depth_image).unwrap();
+    let framebuffer = Framebuffer::new(
+        render_pass.clone(),
+        FramebufferCreateInfo {
+            attachments: vec![view, depth_view],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let vs = vs::load(device.clone()).expect("failed to create shader module");
+    let fs = fs::load(device.clone()).expect("failed to create shader module");
+
+    let viewport = Viewport {
+        offset: [0.0, 0.0],
+        extent: [1024.0, 1024.0],
+        depth_range: 0.0..=1.0,
+    };
+    let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+
+    // The pipelines only differ in their blend and depth state.
+    let create_pipeline = |mode: BlendMode| {
+        let vs = vs.entry_point("main").unwrap();
+        let fs = fs.entry_point("main").unwrap();
+
+        let vertex_input_state = MeshVertex::per_vertex()
+            .definition(&vs.info().input_interface)
+            .unwrap();
+
+        let stages = [
+            PipelineShaderStageCreateInfo::new(vs),
+            PipelineShaderStageCreateInfo::new(fs),
+        ];
+
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+                .into_pipeline_layout_create_info(device.clone())
+                .unwrap(),
+        )
+        .unwrap();
+
+        // Translucent geometry is still hidden behind opaque geometry, but it
+        // must not write depth or it would hide whatever is drawn behind it
+        // afterwards.
+        let depth = DepthState {
+            write_enable: mode == BlendMode::Opaque,
+            compare_op: CompareOp::Less,
+        };
+
+        GraphicsPipeline::new(
+            device.clone(),
+            None,
+            GraphicsPipelineCreateInfo {
+                stages: stages.into_iter().collect(),
+                vertex_input_state: Some(vertex_input_state),
+                input_assembly_state: Some(InputAssemblyState::default()),
+                viewport_state: Some(ViewportState {
+                    viewports: [viewport.clone()].into_iter().collect(),
+                    ..Default::default()
+                }),
+                rasterization_state: Some(RasterizationState::default()),
+                multisample_state: Some(MultisampleState::default()),
+                depth_stencil_state: Some(DepthStencilState {
+                    depth: Some(depth),
+                    ..Default::default()
+                }),
+                color_blend_state: Some(blend::color_blend_state(&[mode])),
+                subpass: Some(subpass.clone().into()),
+                ..GraphicsPipelineCreateInfo::layout(layout)
+            },
+        )
+        .unwrap()
+    };
+
+    // Each quarter of the image shows one blend mode.
+    let panels = [
+        (BlendMode::Alpha, [-0.5, -0.5]),
+        (BlendMode::PremultipliedAlpha, [0.5, -0.5]),
+        (BlendMode::Additive, [-0.5, 0.5]),
+        (BlendMode::Multiply, [0.5, 0.5]),
+    ];
+
+    // In panel coordinates. An opaque bar cuts through the middle at depth
+    // 0.5, so the red quad is partially hidden behind it.
+    let bar = Quad {
+        color: [0.9, 0.9, 0.9, 1.0],
+        offset: [0.0, 0.0],
+        size: [1.8, 0.1],
+        depth: 0.5,
+    };
+    // Deliberately not in back-to-front order.
+    let translucent = [
+        Quad {
+            color: [0.0, 1.0, 0.0, 0.5],
+            offset: [0.0, 0.1],
+            size: [0.9, 0.9],
+            depth: 0.4,
+        },
+        Quad {
+            color: [1.0, 0.0, 0.0, 0.5],
+            offset: [-0.25, -0.2],
+            size: [0.9, 0.9],
+            depth: 0.8,
+        },
+        Quad {
+            color: [0.0, 0.0, 1.0, 0.5],
+            offset: [0.25, -0.2],
+            size: [0.9, 0.9],
+            depth: 0.2,
+        },
+    ];
+    let mut sorted = translucent;
+    sorted.sort_by(|a, b| b.depth.total_cmp(&a.depth));
+
+    let to_panel = |quad: &Quad, panel_offset: [f32; 2]| Quad {
+        offset: [
+            panel_offset[0] + quad.offset[0] * 0.5,
+            panel_offset[1] + quad.offset[1] * 0.5,
+        ],
+        size: [quad.size[0] * 0.5, quad.size[1] * 0.5],
+        ..*quad
+    };
+
+    let command_buffer_allocator = StandardCommandBufferAllocator::new(
+        device.clone(),
+        StandardCommandBufferAllocatorCreateInfo::default(),
+    );
+
+    let mut builder = AutoCommandBufferBuilder::primary(
+        &command_buffer_allocator,
+        queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+
+    builder
+        .begin_render_pass(
+            RenderPassBeginInfo {
+                clear_values: vec![Some(CLEAR_COLOR.into()), Some(1.0.into())],
+                ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
+            },
+            SubpassBeginInfo {
+                contents: SubpassContents::Inline,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+    // All opaque geometry first, then the translucent geometry from the back
+    // to the front.
+    let opaque = create_pipeline(BlendMode::Opaque);
+    builder.bind_pipeline_graphics(opaque.clone()).unwrap();
+    for (_, panel_offset) in panels {
+        builder
+            .push_constants(opaque.layout().clone(), 0, to_panel(&bar, panel_offset))
+            .unwrap();
+        quad.draw(&mut builder);
+    }
+
+    for (mode, panel_offset) in panels {
+        let pipeline = create_pipeline(mode);
+        builder.bind_pipeline_graphics(pipeline.clone()).unwrap();
+        for translucent_quad in &sorted {
+            let quad_constants = Quad {
+                color: prepare_color(mode, translucent_quad.color),
+                ..to_panel(translucent_quad, panel_offset)
+            };
+            builder
+                .push_constants(pipeline.layout().clone(), 0, quad_constants)
+                .unwrap();
+            quad.draw(&mut builder);
+        }
+    }
+
+    builder
+        .end_render_pass(SubpassEndInfo::default())
+        .unwrap()
+        .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(image, buf.clone()))
+        .unwrap();
+
+    let command_buffer = builder.build().unwrap();
+
+    let future = sync::now(device.clone())
+        .then_execute(queue.clone(), command_buffer)
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap();
+    future.wait(None).unwrap();
+
+    let buffer_content = buf.read().unwrap();
+    let image = ImageBuffer::<Rgba<u8>, _>::from_raw(1024, 1024, &buffer_content[..]).unwrap();
+    image.save("blending.png").unwrap();
+
+    // Check a spot covered by all three quads (but not the bar) against the
+    // same blends done on the CPU. In the alpha panel the result depends on
+    // the order, so it is also compared with the unsorted order.
+    let point = [0.0, -0.2];
+    for (mode, panel_offset) in panels {
+        let composite = |quads: &[Quad]| {
+            quads.iter().fold(CLEAR_COLOR, |dst, quad| {
+                blend_reference(mode, prepare_color(mode, quad.color), dst)
+            })
+        };
+        let expected = composite(&sorted);
+
+        let x = ((panel_offset[0] + point[0] * 0.5 + 1.0) * 512.0) as u32;
+        let y = ((panel_offset[1] + point[1] * 0.5 + 1.0) * 512.0) as u32;
+        let pixel = image.get_pixel(x, y);
+        for channel in 0..4 {
+            let expected = (expected[channel] * 255.0).round() as i32;
+            assert!(
+                (pixel[channel] as i32 - expected).abs() <= 2,
+                "{mode:?}: got {pixel:?}, expected {expected} in channel {channel}"
+            );
+        }
+
+        if mode == BlendMode::Alpha {
+            let unsorted = composite(&translucent);
+            assert!((0..3).any(|channel| (unsorted[channel] - expected[channel]).abs() > 0.05));
+        }
+    }
+
+    println!("Blending successful!");
+}
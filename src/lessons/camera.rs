@@ -0,0 +1,322 @@
+use std::f32::consts::{FRAC_PI_4, TAU};
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3};
+use image::{ImageBuffer, RgbaImage};
+use vulkano::buffer::allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo};
+use vulkano::buffer::BufferUsage;
+use vulkano::command_buffer::allocator::{
+    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CommandBufferUsage, CopyImageToBufferInfo, RenderPassBeginInfo,
+    SubpassBeginInfo, SubpassContents, SubpassEndInfo,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::ImageUsage;
+use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::color_blend::{ColorBlendAttachmentState, ColorBlendState};
+use vulkano::pipeline::graphics::depth_stencil::{DepthState, DepthStencilState};
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::{CullMode, RasterizationState};
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{
+    GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo,
+};
+use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, Subpass};
+use vulkano::sync::{self, GpuFuture};
+
+use crate::camera::{Camera, Transforms};
+use crate::mesh::{Mesh, MeshData, MeshVertex};
+use crate::{export, util};
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec3 position;
+            layout(location = 1) in vec3 normal;
+            layout(location = 2) in vec2 uv;
+
+            layout(location = 0) out vec3 v_normal;
+            layout(location = 1) out vec2 v_uv;
+
+            layout(set = 0, binding = 0) uniform Transforms {
+                mat4 model;
+                mat4 view;
+                mat4 projection;
+            } transforms;
+
+            void main() {
+                v_normal = mat3(transforms.model) * normal;
+                v_uv = uv;
+                gl_Position = transforms.projection * transforms.view * transforms.model * vec4(position, 1.0);
+            }
+        ",
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec3 v_normal;
+            layout(location = 1) in vec2 v_uv;
+
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                float checker = mod(floor(v_uv.x * 4.0) + floor(v_uv.y * 4.0), 2.0);
+                vec3 base = mix(vec3(0.9, 0.6, 0.2), vec3(0.3, 0.5, 0.9), checker);
+
+                vec3 light = normalize(vec3(0.4, 0.8, 0.6));
+                float diffuse = max(dot(normalize(v_normal), light), 0.0);
+                f_color = vec4(base * (0.2 + 0.8 * diffuse), 1.0);
+            }
+        ",
+    }
+}
+
+pub fn camera() {
+    let mut vk_device = util::create_device();
+    let queue = vk_device.queues.next().unwrap();
+    let device = vk_device.device.clone();
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+
+    let image = util::create_image(
+        &memory_allocator,
+        ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+        MemoryTypeFilter::PREFER_DEVICE,
+    );
+
+    let depth_format = util::find_depth_format(device.physical_device());
+    let depth_image = util::create_depth_image(&memory_allocator, depth_format, [1024, 1024]);
+
+    let buf = util::create_buffer(
+        (0..1024 * 1024 * 4).map(|_| 0u8),
+        &memory_allocator,
+        BufferUsage::TRANSFER_DST,
+        MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+    );
+
+    let mesh = Mesh::new(MeshData::cube(), &memory_allocator);
+
+    // Every frame gets its own small uniform buffer out of a shared arena, so
+    // writing the next frame's matrices never touches memory the GPU might
+    // still be reading.
+    let uniform_buffer_allocator = SubbufferAllocator::new(
+        memory_allocator.clone(),
+        SubbufferAllocatorCreateInfo {
+            buffer_usage: BufferUsage::UNIFORM_BUFFER,
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+    );
+
+    let render_pass = vulkano::single_pass_renderpass!(
+        device.clone(),
+        attachments: {
+            color: {
+                format: Format::R8G8B8A8_UNORM,
+                samples: 1,
+                load_op: Clear,
+                store_op: Store,
+            },
+            depth: {
+                format: depth_format,
+                samples: 1,
+                load_op: Clear,
+                store_op: DontCare,
+            },
+        },
+        pass: {
+            color: [color],
+            depth_stencil: {depth},
+        },
+    )
+    .unwrap();
+
+    let view = ImageView::new_default(image.clone()).unwrap();
+    let depth_view = ImageView::new_default(depth_image).unwrap();
+    let framebuffer = Framebuffer::new(
+        render_pass.clone(),
+        FramebufferCreateInfo {
+            attachments: vec![view, depth_view],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let vs = vs::load(device.clone()).expect("failed to create shader module");
+    let fs = fs::load(device.clone()).expect("failed to create shader module");
+
+    let viewport = Viewport {
+        offset: [0.0, 0.0],
+        extent: [1024.0, 1024.0],
+        depth_range: 0.0..=1.0,
+    };
+    let pipeline = {
+        let vs = vs.entry_point("main").unwrap();
+        let fs = fs.entry_point("main").unwrap();
+
+        let vertex_input_state = MeshVertex::per_vertex()
+            .definition(&vs.info().input_interface)
+            .unwrap();
+
+        let stages = [
+            PipelineShaderStageCreateInfo::new(vs),
+            PipelineShaderStageCreateInfo::new(fs),
+        ];
+
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+                .into_pipeline_layout_create_info(device.clone())
+                .unwrap(),
+        )
+        .unwrap();
+
+        let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+
+        GraphicsPipeline::new(
+            device.clone(),
+            None,
+            GraphicsPipelineCreateInfo {
+                stages: stages.into_iter().collect(),
+                vertex_input_state: Some(vertex_input_state),
+                input_assembly_state: Some(InputAssemblyState::default()),
+                viewport_state: Some(ViewportState {
+                    viewports: [viewport].into_iter().collect(),
+                    ..Default::default()
+                }),
+                // Now that the geometry goes through a real projection the
+                // back faces can be skipped.
+                rasterization_state: Some(RasterizationState {
+                    cull_mode: CullMode::Back,
+                    ..Default::default()
+                }),
+                multisample_state: Some(MultisampleState::default()),
+                depth_stencil_state: Some(DepthStencilState {
+                    depth: Some(DepthState::simple()),
+                    ..Default::default()
+                }),
+                color_blend_state: Some(ColorBlendState::with_attachment_states(
+                    subpass.num_color_attachments(),
+                    ColorBlendAttachmentState::default(),
+                )),
+                subpass: Some(subpass.into()),
+                ..GraphicsPipelineCreateInfo::layout(layout)
+            },
+        )
+        .unwrap()
+    };
+
+    let command_buffer_allocator = StandardCommandBufferAllocator::new(
+        device.clone(),
+        StandardCommandBufferAllocatorCreateInfo::default(),
+    );
+    let descriptor_set_allocator =
+        StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+
+    let render = |camera: &Camera, model: Mat4| -> RgbaImage {
+        let uniform_buffer = uniform_buffer_allocator
+            .allocate_sized::<Transforms>()
+            .unwrap();
+        *uniform_buffer.write().unwrap() = camera.transforms(model);
+
+        let set = PersistentDescriptorSet::new(
+            &descriptor_set_allocator,
+            pipeline.layout().set_layouts()[0].clone(),
+            [WriteDescriptorSet::buffer(0, uniform_buffer)],
+            [],
+        )
+        .unwrap();
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &command_buffer_allocator,
+            queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![Some([0.1, 0.1, 0.1, 1.0].into()), Some(1.0.into())],
+                    ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
+                },
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .bind_pipeline_graphics(pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                pipeline.layout().clone(),
+                0,
+                set,
+            )
+            .unwrap();
+        mesh.draw(&mut builder);
+        builder
+            .end_render_pass(SubpassEndInfo::default())
+            .unwrap()
+            .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+                image.clone(),
+                buf.clone(),
+            ))
+            .unwrap();
+
+        let command_buffer = builder.build().unwrap();
+        let future = sync::now(device.clone())
+            .then_execute(queue.clone(), command_buffer)
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap();
+        future.wait(None).unwrap();
+
+        let buffer_content = buf.read().unwrap();
+        ImageBuffer::from_raw(1024, 1024, buffer_content.to_vec()).unwrap()
+    };
+
+    // Orbit the camera around the cube and move in slowly, while the cube spins
+    // on its own axis.
+    let mut camera = Camera::perspective(FRAC_PI_4, 1.0, 0.1, 100.0)
+        .look_at(Vec3::new(0.0, 1.5, 3.0), Vec3::ZERO);
+    let frame_count = 24;
+    let frames: Vec<RgbaImage> = (0..frame_count)
+        .map(|n| {
+            let t = n as f32 / frame_count as f32;
+            let model = Mat4::from_rotation_y(t * TAU) * Mat4::from_rotation_x(0.3);
+            let frame = render(&camera, model);
+            camera.orbit(TAU / frame_count as f32 * 0.25, 0.0);
+            camera.dolly(0.98);
+            frame
+        })
+        .collect();
+    frames[0].save("camera_perspective.png").unwrap();
+    export::save_gif(&frames, 12, "camera_orbit.gif");
+
+    let orthographic =
+        Camera::orthographic(2.5, 1.0, 0.1, 100.0).look_at(Vec3::new(2.0, 2.0, 2.0), Vec3::ZERO);
+    render(&orthographic, Mat4::IDENTITY)
+        .save("camera_orthographic.png")
+        .unwrap();
+
+    println!("Camera and uniform buffers successful!");
+}
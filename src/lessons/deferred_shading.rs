@@ -0,0 +1,650 @@
+use std::f32::consts::FRAC_PI_4;
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3};
+use image::{ImageBuffer, Rgba, RgbaImage};
+use vulkano::buffer::allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo};
+use vulkano::buffer::{BufferContents, BufferUsage};
+use vulkano::command_buffer::allocator::{
+    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CommandBufferUsage, CopyImageToBufferInfo, RenderPassBeginInfo,
+    SubpassBeginInfo, SubpassContents, SubpassEndInfo,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::ImageUsage;
+use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::color_blend::ColorBlendState;
+use vulkano::pipeline::graphics::depth_stencil::{DepthState, DepthStencilState};
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::{CullMode, RasterizationState};
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition, VertexInputState};
+use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{
+    GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo,
+};
+use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, Subpass};
+use vulkano::sync::{self, GpuFuture};
+
+use crate::blend::{self, BlendMode};
+use crate::camera::{Camera, Transforms};
+use crate::mesh::{Mesh, MeshData, MeshVertex};
+use crate::util;
+
+// Layouts of the lighting uniform block, 16 byte aligned like std140 wants.
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct PointLight {
+    // xyz is the position, w the radius the light fades out at.
+    position: [f32; 4],
+    color: [f32; 4],
+}
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct Lights {
+    lights: [PointLight; 4],
+    camera_position: [f32; 4],
+}
+
+mod gbuffer_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec3 position;
+            layout(location = 1) in vec3 normal;
+            layout(location = 2) in vec2 uv;
+
+            layout(location = 0) out vec3 v_position;
+            layout(location = 1) out vec3 v_normal;
+            layout(location = 2) out vec2 v_uv;
+
+            layout(set = 0, binding = 0) uniform Transforms {
+                mat4 model;
+                mat4 view;
+                mat4 projection;
+            } transforms;
+
+            void main() {
+                vec4 world = transforms.model * vec4(position, 1.0);
+                v_position = world.xyz;
+                v_normal = mat3(transforms.model) * normal;
+                v_uv = uv;
+                gl_Position = transforms.projection * transforms.view * world;
+            }
+        ",
+    }
+}
+
+mod gbuffer_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec3 v_position;
+            layout(location = 1) in vec3 v_normal;
+            layout(location = 2) in vec2 v_uv;
+
+            // One output per G-buffer attachment.
+            layout(location = 0) out vec4 f_albedo;
+            layout(location = 1) out vec4 f_normal;
+            layout(location = 2) out vec4 f_position;
+
+            layout(push_constant) uniform Material {
+                vec4 color;
+            } material;
+
+            void main() {
+                float checker = mod(floor(v_uv.x * 8.0) + floor(v_uv.y * 8.0), 2.0);
+                f_albedo = vec4(material.color.rgb * (0.75 + 0.25 * checker), 1.0);
+                f_normal = vec4(normalize(v_normal), 0.0);
+                f_position = vec4(v_position, 1.0);
+            }
+        ",
+    }
+}
+
+mod lighting_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 460
+
+            // A single triangle covering the whole screen, no vertex buffer
+            // needed.
+            void main() {
+                vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+                gl_Position = vec4(uv * 2.0 - 1.0, 0.0, 1.0);
+            }
+        ",
+    }
+}
+
+mod lighting_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 460
+
+            // The G-buffer written by the first subpass, read back at the
+            // same pixel.
+            layout(input_attachment_index = 0, set = 0, binding = 0) uniform subpassInput u_albedo;
+            layout(input_attachment_index = 1, set = 0, binding = 1) uniform subpassInput u_normal;
+            layout(input_attachment_index = 2, set = 0, binding = 2) uniform subpassInput u_position;
+
+            struct PointLight {
+                vec4 position;
+                vec4 color;
+            };
+
+            layout(set = 0, binding = 3) uniform Lights {
+                PointLight lights[4];
+                vec4 camera_position;
+            } scene;
+
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                vec4 albedo = subpassLoad(u_albedo);
+                // Nothing was drawn here.
+                if (albedo.a == 0.0) {
+                    f_color = vec4(0.05, 0.05, 0.1, 1.0);
+                    return;
+                }
+
+                vec3 normal = normalize(subpassLoad(u_normal).xyz);
+                vec3 position = subpassLoad(u_position).xyz;
+                vec3 to_camera = normalize(scene.camera_position.xyz - position);
+
+                vec3 color = albedo.rgb * 0.05;
+                for (int i = 0; i < 4; i++) {
+                    vec3 to_light = scene.lights[i].position.xyz - position;
+                    float distance = length(to_light);
+                    to_light /= distance;
+
+                    float radius = scene.lights[i].position.w;
+                    float attenuation = pow(clamp(1.0 - distance / radius, 0.0, 1.0), 2.0);
+
+                    float diffuse = max(dot(normal, to_light), 0.0);
+                    vec3 halfway = normalize(to_light + to_camera);
+                    float specular = pow(max(dot(normal, halfway), 0.0), 32.0) * step(0.0, diffuse);
+
+                    color += (albedo.rgb * diffuse + vec3(0.3) * specular) * scene.lights[i].color.rgb * attenuation;
+                }
+                f_color = vec4(color, 1.0);
+            }
+        ",
+    }
+}
+
+// Maps a float target to 8 bits for viewing, `range` is mapped to [0, 1].
+fn float_target_to_rgba8(data: &[f32], range: (f32, f32)) -> RgbaImage {
+    let (min, max) = range;
+    let pixels = data
+        .chunks(4)
+        .flat_map(|texel| {
+            let covered = texel[3] != 0.0 || texel[0..3].iter().any(|&v| v != 0.0);
+            let mut out = [0u8; 4];
+            for channel in 0..3 {
+                let v = ((texel[channel] - min) / (max - min)).clamp(0.0, 1.0);
+                out[channel] = if covered {
+                    (v * 255.0).round() as u8
+                } else {
+                    0
+                };
+            }
+            out[3] = 255;
+            out
+        })
+        .collect();
+    ImageBuffer::from_raw(1024, 1024, pixels).unwrap()
+}
+
+pub fn deferred_shading() {
+    let mut vk_device = util::create_device();
+    let queue = vk_device.queues.next().unwrap();
+    let device = vk_device.device.clone();
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+
+    // The G-buffer targets are read as input attachments by the lighting
+    // subpass. Usually they would never leave the GPU, here they are also
+    // copied out to be saved.
+    let gbuffer_usage =
+        ImageUsage::COLOR_ATTACHMENT | ImageUsage::INPUT_ATTACHMENT | ImageUsage::TRANSFER_SRC;
+    let albedo_format = Format::R8G8B8A8_UNORM;
+    let float_format = Format::R32G32B32A32_SFLOAT;
+    let albedo = util::create_render_target(
+        &memory_allocator,
+        albedo_format,
+        [1024, 1024],
+        gbuffer_usage,
+    );
+    let normal =
+        util::create_render_target(&memory_allocator, float_format, [1024, 1024], gbuffer_usage);
+    let position =
+        util::create_render_target(&memory_allocator, float_format, [1024, 1024], gbuffer_usage);
+    let image = util::create_image(
+        &memory_allocator,
+        ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+        MemoryTypeFilter::PREFER_DEVICE,
+    );
+
+    let depth_format = util::find_depth_format(device.physical_device());
+    let depth_image = util::create_depth_image(&memory_allocator, depth_format, [1024, 1024]);
+
+    let buf = util::create_buffer(
+        (0..1024 * 1024 * 4).map(|_| 0u8),
+        &memory_allocator,
+        BufferUsage::TRANSFER_DST,
+        MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+    );
+    let albedo_buf = util::create_buffer(
+        (0..1024 * 1024 * 4).map(|_| 0u8),
+        &memory_allocator,
+        BufferUsage::TRANSFER_DST,
+        MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+    );
+    let normal_buf = util::create_buffer(
+        (0..1024 * 1024 * 4).map(|_| 0f32),
+        &memory_allocator,
+        BufferUsage::TRANSFER_DST,
+        MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+    );
+    let position_buf = util::create_buffer(
+        (0..1024 * 1024 * 4).map(|_| 0f32),
+        &memory_allocator,
+        BufferUsage::TRANSFER_DST,
+        MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+    );
+
+    // Subpass 0 fills the G-buffer, subpass 1 reads it back pixel by pixel
+    // and writes the lit result.
+    let render_pass = vulkano::ordered_passes_renderpass!(
+        device.clone(),
+        attachments: {
+            final_color: {
+                format: Format::R8G8B8A8_UNORM,
+                samples: 1,
+                load_op: Clear,
+                store_op: Store,
+            },
+            albedo: {
+                format: albedo_format,
+                samples: 1,
+                load_op: Clear,
+                store_op: Store,
+            },
+            normal: {
+                format: float_format,
+                samples: 1,
+                load_op: Clear,
+                store_op: Store,
+            },
+            position: {
+                format: float_format,
+                samples: 1,
+                load_op: Clear,
+                store_op: Store,
+            },
+            depth: {
+                format: depth_format,
+                samples: 1,
+                load_op: Clear,
+                store_op: DontCare,
+            },
+        },
+        passes: [
+            {
+                color: [albedo, normal, position],
+                depth_stencil: {depth},
+                input: [],
+            },
+            {
+                color: [final_color],
+                depth_stencil: {},
+                input: [albedo, normal, position],
+            },
+        ],
+    )
+    .unwrap();
+
+    let albedo_view = ImageView::new_default(albedo.clone()).unwrap();
+    let normal_view = ImageView::new_default(normal.clone()).unwrap();
+    let position_view = ImageView::new_default(position.clone()).unwrap();
+    let framebuffer = Framebuffer::new(
+        render_pass.clone(),
+        FramebufferCreateInfo {
+            attachments: vec![
+                ImageView::new_default(image.clone()).unwrap(),
+                albedo_view.clone(),
+                normal_view.clone(),
+                position_view.clone(),
+                ImageView::new_default(depth_image).unwrap(),
+            ],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let viewport = Viewport {
+        offset: [0.0, 0.0],
+        extent: [1024.0, 1024.0],
+        depth_range: 0.0..=1.0,
+    };
+    let gbuffer_subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+    let lighting_subpass = Subpass::from(render_pass.clone(), 1).unwrap();
+
+    let gbuffer_pipeline = {
+        let vs = gbuffer_vs::load(device.clone()).expect("failed to create shader module");
+        let fs = gbuffer_fs::load(device.clone()).expect("failed to create shader module");
+        let vs = vs.entry_point("main").unwrap();
+        let fs = fs.entry_point("main").unwrap();
+
+        let vertex_input_state = MeshVertex::per_vertex()
+            .definition(&vs.info().input_interface)
+            .unwrap();
+
+        let stages = [
+            PipelineShaderStageCreateInfo::new(vs),
+            PipelineShaderStageCreateInfo::new(fs),
+        ];
+
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+                .into_pipeline_layout_create_info(device.clone())
+                .unwrap(),
+        )
+        .unwrap();
+
+        GraphicsPipeline::new(
+            device.clone(),
+            None,
+            GraphicsPipelineCreateInfo {
+                stages: stages.into_iter().collect(),
+                vertex_input_state: Some(vertex_input_state),
+                input_assembly_state: Some(InputAssemblyState::default()),
+                viewport_state: Some(ViewportState {
+                    viewports: [viewport.clone()].into_iter().collect(),
+                    ..Default::default()
+                }),
+                rasterization_state: Some(RasterizationState {
+                    cull_mode: CullMode::Back,
+                    ..Default::default()
+                }),
+                multisample_state: Some(MultisampleState::default()),
+                depth_stencil_state: Some(DepthStencilState {
+                    depth: Some(DepthState::simple()),
+                    ..Default::default()
+                }),
+                // One blend state per G-buffer attachment.
+                color_blend_state: Some(blend::color_blend_state(&[BlendMode::Opaque; 3])),
+                subpass: Some(gbuffer_subpass.into()),
+                ..GraphicsPipelineCreateInfo::layout(layout)
+            },
+        )
+        .unwrap()
+    };
+
+    let lighting_pipeline = {
+        let vs = lighting_vs::load(device.clone()).expect("failed to create shader module");
+        let fs = lighting_fs::load(device.clone()).expect("failed to create shader module");
+        let vs = vs.entry_point("main").unwrap();
+        let fs = fs.entry_point("main").unwrap();
+
+        let stages = [
+            PipelineShaderStageCreateInfo::new(vs),
+            PipelineShaderStageCreateInfo::new(fs),
+        ];
+
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+                .into_pipeline_layout_create_info(device.clone())
+                .unwrap(),
+        )
+        .unwrap();
+
+        GraphicsPipeline::new(
+            device.clone(),
+            None,
+            GraphicsPipelineCreateInfo {
+                stages: stages.into_iter().collect(),
+                vertex_input_state: Some(VertexInputState::default()),
+                input_assembly_state: Some(InputAssemblyState::default()),
+                viewport_state: Some(ViewportState {
+                    viewports: [viewport].into_iter().collect(),
+                    ..Default::default()
+                }),
+                rasterization_state: Some(RasterizationState::default()),
+                multisample_state: Some(MultisampleState::default()),
+                color_blend_state: Some(ColorBlendState::with_attachment_states(
+                    lighting_subpass.num_color_attachments(),
+                    Default::default(),
+                )),
+                subpass: Some(lighting_subpass.into()),
+                ..GraphicsPipelineCreateInfo::layout(layout)
+            },
+        )
+        .unwrap()
+    };
+
+    // A floor with a few shapes standing on it.
+    let floor = Mesh::new(MeshData::grid(8, 8), &memory_allocator);
+    let sphere = Mesh::new(MeshData::uv_sphere(32, 16), &memory_allocator);
+    let cube = Mesh::new(MeshData::cube(), &memory_allocator);
+    let cylinder = Mesh::new(MeshData::cylinder(32), &memory_allocator);
+    let objects = [
+        (
+            &floor,
+            Mat4::from_translation(Vec3::new(0.0, -0.5, 0.0)) * Mat4::from_scale(Vec3::splat(6.0)),
+            [0.8, 0.8, 0.8, 1.0],
+        ),
+        (
+            &sphere,
+            Mat4::from_translation(Vec3::new(-1.1, 0.0, 0.2)),
+            [0.9, 0.3, 0.2, 1.0],
+        ),
+        (
+            &cube,
+            Mat4::from_translation(Vec3::new(0.9, 0.0, 0.3)) * Mat4::from_rotation_y(0.6),
+            [0.2, 0.6, 0.9, 1.0],
+        ),
+        (
+            &cylinder,
+            Mat4::from_translation(Vec3::new(0.0, 0.0, -1.2)),
+            [0.3, 0.8, 0.3, 1.0],
+        ),
+    ];
+
+    let camera = Camera::perspective(FRAC_PI_4, 1.0, 0.1, 100.0)
+        .look_at(Vec3::new(0.0, 2.5, 4.5), Vec3::ZERO);
+    let point_light = |position: [f32; 3], radius: f32, color: [f32; 3]| PointLight {
+        position: [position[0], position[1], position[2], radius],
+        color: [color[0], color[1], color[2], 1.0],
+    };
+    let lights = Lights {
+        lights: [
+            point_light([-2.0, 1.5, 1.5], 6.0, [1.0, 0.9, 0.8]),
+            point_light([2.0, 1.0, 1.0], 5.0, [0.4, 0.6, 1.0]),
+            point_light([0.0, 2.0, -2.5], 5.0, [1.0, 0.5, 0.3]),
+            point_light([0.0, 0.2, 1.0], 2.0, [0.8, 0.8, 0.8]),
+        ],
+        camera_position: camera.position.extend(1.0).to_array(),
+    };
+
+    let uniform_buffer_allocator = SubbufferAllocator::new(
+        memory_allocator.clone(),
+        SubbufferAllocatorCreateInfo {
+            buffer_usage: BufferUsage::UNIFORM_BUFFER,
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+    );
+    let descriptor_set_allocator =
+        StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+
+    let lights_buffer = uniform_buffer_allocator.allocate_sized::<Lights>().unwrap();
+    *lights_buffer.write().unwrap() = lights;
+    let lighting_set = PersistentDescriptorSet::new(
+        &descriptor_set_allocator,
+        lighting_pipeline.layout().set_layouts()[0].clone(),
+        [
+            WriteDescriptorSet::image_view(0, albedo_view),
+            WriteDescriptorSet::image_view(1, normal_view),
+            WriteDescriptorSet::image_view(2, position_view),
+            WriteDescriptorSet::buffer(3, lights_buffer),
+        ],
+        [],
+    )
+    .unwrap();
+
+    let command_buffer_allocator = StandardCommandBufferAllocator::new(
+        device.clone(),
+        StandardCommandBufferAllocatorCreateInfo::default(),
+    );
+
+    let mut builder = AutoCommandBufferBuilder::primary(
+        &command_buffer_allocator,
+        queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+
+    builder
+        .begin_render_pass(
+            RenderPassBeginInfo {
+                clear_values: vec![
+                    Some([0.0, 0.0, 0.0, 1.0].into()),
+                    Some([0.0, 0.0, 0.0, 0.0].into()),
+                    Some([0.0, 0.0, 0.0, 0.0].into()),
+                    Some([0.0, 0.0, 0.0, 0.0].into()),
+                    Some(1.0.into()),
+                ],
+                ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
+            },
+            SubpassBeginInfo {
+                contents: SubpassContents::Inline,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .bind_pipeline_graphics(gbuffer_pipeline.clone())
+        .unwrap();
+
+    for (mesh, model, color) in objects {
+        let uniform_buffer = uniform_buffer_allocator
+            .allocate_sized::<Transforms>()
+            .unwrap();
+        *uniform_buffer.write().unwrap() = camera.transforms(model);
+        let set = PersistentDescriptorSet::new(
+            &descriptor_set_allocator,
+            gbuffer_pipeline.layout().set_layouts()[0].clone(),
+            [WriteDescriptorSet::buffer(0, uniform_buffer)],
+            [],
+        )
+        .unwrap();
+
+        builder
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                gbuffer_pipeline.layout().clone(),
+                0,
+                set,
+            )
+            .unwrap()
+            .push_constants(gbuffer_pipeline.layout().clone(), 0, color)
+            .unwrap();
+        mesh.draw(&mut builder);
+    }
+
+    builder
+        .next_subpass(
+            SubpassEndInfo::default(),
+            SubpassBeginInfo {
+                contents: SubpassContents::Inline,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .bind_pipeline_graphics(lighting_pipeline.clone())
+        .unwrap()
+        .bind_descriptor_sets(
+            PipelineBindPoint::Graphics,
+            lighting_pipeline.layout().clone(),
+            0,
+            lighting_set,
+        )
+        .unwrap()
+        .draw(3, 1, 0, 0)
+        .unwrap()
+        .end_render_pass(SubpassEndInfo::default())
+        .unwrap()
+        .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(image, buf.clone()))
+        .unwrap()
+        .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+            albedo,
+            albedo_buf.clone(),
+        ))
+        .unwrap()
+        .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+            normal,
+            normal_buf.clone(),
+        ))
+        .unwrap()
+        .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+            position,
+            position_buf.clone(),
+        ))
+        .unwrap();
+
+    let command_buffer = builder.build().unwrap();
+
+    let future = sync::now(device.clone())
+        .then_execute(queue.clone(), command_buffer)
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap();
+    future.wait(None).unwrap();
+
+    let albedo_content = albedo_buf.read().unwrap();
+    let normal_content = normal_buf.read().unwrap();
+    let position_content = position_buf.read().unwrap();
+
+    // The middle of the image shows the scene, the top corner only the
+    // background.
+    let center = (512 * 1024 + 512) * 4;
+    assert_eq!(albedo_content[center + 3], 255);
+    assert_eq!(albedo_content[3], 0);
+    let n = &normal_content[center..center + 3];
+    let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    assert!((length - 1.0).abs() < 0.01);
+
+    ImageBuffer::<Rgba<u8>, _>::from_raw(1024, 1024, &albedo_content[..])
+        .unwrap()
+        .save("deferred_albedo.png")
+        .unwrap();
+    float_target_to_rgba8(&normal_content, (-1.0, 1.0))
+        .save("deferred_normal.png")
+        .unwrap();
+    float_target_to_rgba8(&position_content, (-3.0, 3.0))
+        .save("deferred_position.png")
+        .unwrap();
+
+    let buffer_content = buf.read().unwrap();
+    let image = ImageBuffer::<Rgba<u8>, _>::from_raw(1024, 1024, &buffer_content[..]).unwrap();
+    image.save("deferred_lit.png").unwrap();
+
+    println!("Deferred shading successful!");
+}
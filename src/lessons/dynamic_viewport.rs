@@ -0,0 +1,383 @@
+use std::f32::consts::FRAC_PI_4;
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3};
+use image::{ImageBuffer, Rgba, RgbaImage};
+use vulkano::buffer::allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo};
+use vulkano::buffer::BufferUsage;
+use vulkano::command_buffer::allocator::{
+    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, ClearAttachment, ClearRect, CommandBufferUsage,
+    CopyImageToBufferInfo, RenderPassBeginInfo, SubpassBeginInfo, SubpassContents, SubpassEndInfo,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::format::Format;
+use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::color_blend::{ColorBlendAttachmentState, ColorBlendState};
+use vulkano::pipeline::graphics::depth_stencil::{DepthState, DepthStencilState};
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::{CullMode, RasterizationState};
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::pipeline::graphics::viewport::{Scissor, Viewport, ViewportState};
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{
+    DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+    PipelineShaderStageCreateInfo,
+};
+use vulkano::render_pass::Subpass;
+use vulkano::sync::{self, GpuFuture};
+
+use crate::camera::{Camera, Transforms};
+use crate::mesh::{Mesh, MeshData, MeshVertex};
+use crate::render_target::RenderTarget;
+use crate::util;
+
+const BACKGROUND: [f32; 4] = [0.1, 0.1, 0.1, 1.0];
+const INSET_BACKGROUND: [f32; 4] = [0.2, 0.2, 0.4, 1.0];
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec3 position;
+            layout(location = 1) in vec3 normal;
+            layout(location = 2) in vec2 uv;
+
+            layout(location = 0) out vec3 v_normal;
+            layout(location = 1) out vec2 v_uv;
+
+            layout(set = 0, binding = 0) uniform Transforms {
+                mat4 model;
+                mat4 view;
+                mat4 projection;
+            } transforms;
+
+            void main() {
+                v_normal = mat3(transforms.model) * normal;
+                v_uv = uv;
+                gl_Position = transforms.projection * transforms.view * transforms.model * vec4(position, 1.0);
+            }
+        ",
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec3 v_normal;
+            layout(location = 1) in vec2 v_uv;
+
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                float checker = mod(floor(v_uv.x * 4.0) + floor(v_uv.y * 4.0), 2.0);
+                vec3 base = mix(vec3(0.9, 0.6, 0.2), vec3(0.3, 0.5, 0.9), checker);
+
+                vec3 light = normalize(vec3(0.4, 0.8, 0.6));
+                float diffuse = max(dot(normalize(v_normal), light), 0.0);
+                f_color = vec4(base * (0.2 + 0.8 * diffuse), 1.0);
+            }
+        ",
+    }
+}
+
+pub fn dynamic_viewport() {
+    let mut vk_device = util::create_device();
+    let queue = vk_device.queues.next().unwrap();
+    let device = vk_device.device.clone();
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+
+    let depth_format = util::find_depth_format(device.physical_device());
+    let render_pass = vulkano::single_pass_renderpass!(
+        device.clone(),
+        attachments: {
+            color: {
+                format: Format::R8G8B8A8_UNORM,
+                samples: 1,
+                load_op: Clear,
+                store_op: Store,
+            },
+            depth: {
+                format: depth_format,
+                samples: 1,
+                load_op: Clear,
+                store_op: DontCare,
+            },
+        },
+        pass: {
+            color: [color],
+            depth_stencil: {depth},
+        },
+    )
+    .unwrap();
+
+    let mut target = RenderTarget::new(render_pass.clone(), memory_allocator.clone(), [1024, 1024]);
+
+    let vs = vs::load(device.clone()).expect("failed to create shader module");
+    let fs = fs::load(device.clone()).expect("failed to create shader module");
+
+    // Built once. The viewport and scissor are left out of the pipeline and
+    // set while recording instead, so the same pipeline works for any size.
+    let pipeline = {
+        let vs = vs.entry_point("main").unwrap();
+        let fs = fs.entry_point("main").unwrap();
+
+        let vertex_input_state = MeshVertex::per_vertex()
+            .definition(&vs.info().input_interface)
+            .unwrap();
+
+        let stages = [
+            PipelineShaderStageCreateInfo::new(vs),
+            PipelineShaderStageCreateInfo::new(fs),
+        ];
+
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+                .into_pipeline_layout_create_info(device.clone())
+                .unwrap(),
+        )
+        .unwrap();
+
+        let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+
+        GraphicsPipeline::new(
+            device.clone(),
+            None,
+            GraphicsPipelineCreateInfo {
+                stages: stages.into_iter().collect(),
+                vertex_input_state: Some(vertex_input_state),
+                input_assembly_state: Some(InputAssemblyState::default()),
+                // One viewport and one scissor, the values are ignored.
+                viewport_state: Some(ViewportState::default()),
+                rasterization_state: Some(RasterizationState {
+                    cull_mode: CullMode::Back,
+                    ..Default::default()
+                }),
+                multisample_state: Some(MultisampleState::default()),
+                depth_stencil_state: Some(DepthStencilState {
+                    depth: Some(DepthState::simple()),
+                    ..Default::default()
+                }),
+                color_blend_state: Some(ColorBlendState::with_attachment_states(
+                    subpass.num_color_attachments(),
+                    ColorBlendAttachmentState::default(),
+                )),
+                dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
+                    .into_iter()
+                    .collect(),
+                subpass: Some(subpass.into()),
+                ..GraphicsPipelineCreateInfo::layout(layout)
+            },
+        )
+        .unwrap()
+    };
+
+    let mesh = Mesh::new(MeshData::cube(), &memory_allocator);
+    let model = Mat4::from_rotation_y(0.6) * Mat4::from_rotation_x(0.3);
+
+    let uniform_buffer_allocator = SubbufferAllocator::new(
+        memory_allocator.clone(),
+        SubbufferAllocatorCreateInfo {
+            buffer_usage: BufferUsage::UNIFORM_BUFFER,
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+    );
+    let command_buffer_allocator = StandardCommandBufferAllocator::new(
+        device.clone(),
+        StandardCommandBufferAllocatorCreateInfo::default(),
+    );
+    let descriptor_set_allocator =
+        StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+
+    let transforms_set = |camera: &Camera| {
+        let uniform_buffer = uniform_buffer_allocator
+            .allocate_sized::<Transforms>()
+            .unwrap();
+        *uniform_buffer.write().unwrap() = camera.transforms(model);
+        PersistentDescriptorSet::new(
+            &descriptor_set_allocator,
+            pipeline.layout().set_layouts()[0].clone(),
+            [WriteDescriptorSet::buffer(0, uniform_buffer)],
+            [],
+        )
+        .unwrap()
+    };
+
+    let render = |target: &RenderTarget| -> RgbaImage {
+        let [width, height] = target.extent();
+        let buf = util::create_buffer(
+            (0..width * height * 4).map(|_| 0u8),
+            &memory_allocator,
+            BufferUsage::TRANSFER_DST,
+            MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+        );
+
+        // The projection has to follow the shape of the target, or the cube
+        // gets stretched.
+        let camera = Camera::perspective(FRAC_PI_4, target.aspect_ratio(), 0.1, 100.0)
+            .look_at(Vec3::new(0.0, 1.0, 3.5), Vec3::ZERO);
+
+        // A quarter sized inset in the top right corner, looking straight
+        // down.
+        let inset_extent = [width / 4, height / 4];
+        let inset_offset = [width - inset_extent[0] - 16, 16];
+        let inset_camera = Camera::orthographic(
+            2.5,
+            inset_extent[0] as f32 / inset_extent[1] as f32,
+            0.1,
+            100.0,
+        )
+        .look_at(Vec3::new(0.0, 5.0, 0.01), Vec3::ZERO);
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &command_buffer_allocator,
+            queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![Some(BACKGROUND.into()), Some(1.0.into())],
+                    ..RenderPassBeginInfo::framebuffer(target.framebuffer().clone())
+                },
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .bind_pipeline_graphics(pipeline.clone())
+            .unwrap();
+        target.set_full_viewport(&mut builder);
+        builder
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                pipeline.layout().clone(),
+                0,
+                transforms_set(&camera),
+            )
+            .unwrap();
+        mesh.draw(&mut builder);
+
+        // The inset gets its own background and depth, then the viewport
+        // maps the scene into it and the scissor keeps it inside.
+        builder
+            .clear_attachments(
+                [
+                    ClearAttachment::Color {
+                        color_attachment: 0,
+                        clear_value: INSET_BACKGROUND.into(),
+                    },
+                    ClearAttachment::Depth(1.0),
+                ]
+                .into_iter()
+                .collect(),
+                [ClearRect {
+                    offset: inset_offset,
+                    extent: inset_extent,
+                    array_layers: 0..1,
+                }]
+                .into_iter()
+                .collect(),
+            )
+            .unwrap()
+            .set_viewport(
+                0,
+                [Viewport {
+                    offset: [inset_offset[0] as f32, inset_offset[1] as f32],
+                    extent: [inset_extent[0] as f32, inset_extent[1] as f32],
+                    depth_range: 0.0..=1.0,
+                }]
+                .into_iter()
+                .collect(),
+            )
+            .unwrap()
+            .set_scissor(
+                0,
+                [Scissor {
+                    offset: inset_offset,
+                    extent: inset_extent,
+                }]
+                .into_iter()
+                .collect(),
+            )
+            .unwrap()
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                pipeline.layout().clone(),
+                0,
+                transforms_set(&inset_camera),
+            )
+            .unwrap();
+        mesh.draw(&mut builder);
+
+        builder
+            .end_render_pass(SubpassEndInfo::default())
+            .unwrap()
+            .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+                target.attachment(0).clone(),
+                buf.clone(),
+            ))
+            .unwrap();
+
+        let command_buffer = builder.build().unwrap();
+        let future = sync::now(device.clone())
+            .then_execute(queue.clone(), command_buffer)
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap();
+        future.wait(None).unwrap();
+
+        let buffer_content = buf.read().unwrap();
+        let image: RgbaImage =
+            ImageBuffer::from_raw(width, height, buffer_content.to_vec()).unwrap();
+
+        // The cube sits in the middle of both views, the inset's corner only
+        // shows its background.
+        let to_rgba8 = |color: [f32; 4]| Rgba(color.map(|c| (c * 255.0).round() as u8));
+        assert_ne!(
+            *image.get_pixel(width / 2, height / 2),
+            to_rgba8(BACKGROUND)
+        );
+        assert_eq!(
+            *image.get_pixel(inset_offset[0] + 1, inset_offset[1] + 1),
+            to_rgba8(INSET_BACKGROUND)
+        );
+        assert_ne!(
+            *image.get_pixel(
+                inset_offset[0] + inset_extent[0] / 2,
+                inset_offset[1] + inset_extent[1] / 2,
+            ),
+            to_rgba8(INSET_BACKGROUND)
+        );
+        image
+    };
+
+    // Same render pass and pipeline for every size, only the images are
+    // recreated.
+    for extent in [[1024, 1024], [1280, 720], [480, 800]] {
+        target.resize(extent);
+        let image = render(&target);
+        assert_eq!(image.dimensions(), (extent[0], extent[1]));
+        image
+            .save(format!("dynamic_viewport_{}x{}.png", extent[0], extent[1]))
+            .unwrap();
+    }
+
+    println!("Dynamic viewport and resizable render target successful!");
+}
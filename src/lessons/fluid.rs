@@ -0,0 +1,615 @@
+use std::sync::Arc;
+
+use image::RgbaImage;
+use vulkano::buffer::{BufferContents, BufferUsage};
+use vulkano::command_buffer::allocator::{
+    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, ClearColorImageInfo, CopyImageToBufferInfo, PrimaryAutoCommandBuffer,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::device::{DeviceOwned, Queue};
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::ImageUsage;
+use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::{ComputePipeline, Pipeline, PipelineBindPoint};
+
+use crate::{export, texture, util};
+
+const JACOBI_ITERATIONS: u32 = 60;
+
+// Velocities and dye share a format, so one advection pipeline moves both.
+const FIELD_FORMAT: Format = Format::R32G32B32A32_SFLOAT;
+const SCALAR_FORMAT: Format = Format::R32_SFLOAT;
+
+mod advect {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0, rgba32f) uniform readonly image2D velocity;
+            layout(set = 0, binding = 1, rgba32f) uniform readonly image2D src;
+            layout(set = 0, binding = 2, rgba32f) uniform writeonly image2D dst;
+
+            layout(push_constant) uniform Params {
+                float dt;
+                float dissipation;
+            } params;
+
+            // Storage images can't be sampled, so the bilinear filtering is
+            // done by hand.
+            vec4 bilinear(vec2 pos, ivec2 size) {
+                pos = clamp(pos, vec2(0.0), vec2(size - 1));
+                ivec2 i = ivec2(floor(pos));
+                ivec2 j = min(i + 1, size - 1);
+                vec2 f = pos - vec2(i);
+                vec4 bottom = mix(imageLoad(src, i), imageLoad(src, ivec2(j.x, i.y)), f.x);
+                vec4 top = mix(imageLoad(src, ivec2(i.x, j.y)), imageLoad(src, j), f.x);
+                return mix(bottom, top, f.y);
+            }
+
+            void main() {
+                ivec2 p = ivec2(gl_GlobalInvocationID.xy);
+                ivec2 size = imageSize(dst);
+                if (any(greaterThanEqual(p, size))) {
+                    return;
+                }
+
+                // Semi-Lagrangian: fetch whatever the flow carries here from
+                // where it was dt ago. Velocities are in cells per second.
+                vec2 back = vec2(p) - params.dt * imageLoad(velocity, p).xy;
+                imageStore(dst, p, params.dissipation * bilinear(back, size));
+            }
+        ",
+    }
+}
+
+mod splat {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0, rgba32f) uniform image2D field;
+
+            layout(push_constant) uniform Params {
+                vec4 value;
+                vec2 center;
+                float radius;
+            } params;
+
+            void main() {
+                ivec2 p = ivec2(gl_GlobalInvocationID.xy);
+                if (any(greaterThanEqual(p, imageSize(field)))) {
+                    return;
+                }
+
+                vec2 d = vec2(p) - params.center;
+                float weight = exp(-dot(d, d) / (params.radius * params.radius));
+                imageStore(field, p, imageLoad(field, p) + weight * params.value);
+            }
+        ",
+    }
+}
+
+mod divergence {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0, rgba32f) uniform readonly image2D velocity;
+            layout(set = 0, binding = 1, r32f) uniform writeonly image2D divergence;
+
+            // The borders are solid walls, nothing flows through them.
+            vec2 fetch(ivec2 p, ivec2 size) {
+                if (any(lessThan(p, ivec2(0))) || any(greaterThanEqual(p, size))) {
+                    return vec2(0.0);
+                }
+                return imageLoad(velocity, p).xy;
+            }
+
+            void main() {
+                ivec2 p = ivec2(gl_GlobalInvocationID.xy);
+                ivec2 size = imageSize(velocity);
+                if (any(greaterThanEqual(p, size))) {
+                    return;
+                }
+
+                float d = 0.5 * (fetch(p + ivec2(1, 0), size).x - fetch(p - ivec2(1, 0), size).x
+                    + fetch(p + ivec2(0, 1), size).y - fetch(p - ivec2(0, 1), size).y);
+                imageStore(divergence, p, vec4(d));
+            }
+        ",
+    }
+}
+
+mod jacobi {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0, r32f) uniform readonly image2D pressure;
+            layout(set = 0, binding = 1, r32f) uniform readonly image2D divergence;
+            layout(set = 0, binding = 2, r32f) uniform writeonly image2D dst;
+
+            // Clamping gives a zero pressure gradient across the walls.
+            float fetch(ivec2 p, ivec2 size) {
+                return imageLoad(pressure, clamp(p, ivec2(0), size - 1)).r;
+            }
+
+            void main() {
+                ivec2 p = ivec2(gl_GlobalInvocationID.xy);
+                ivec2 size = imageSize(dst);
+                if (any(greaterThanEqual(p, size))) {
+                    return;
+                }
+
+                // One iteration towards the Poisson equation lap(p) = div(v).
+                float neighbours = fetch(p + ivec2(1, 0), size) + fetch(p - ivec2(1, 0), size)
+                    + fetch(p + ivec2(0, 1), size) + fetch(p - ivec2(0, 1), size);
+                float p_new = 0.25 * (neighbours - imageLoad(divergence, p).r);
+                imageStore(dst, p, vec4(p_new));
+            }
+        ",
+    }
+}
+
+mod project {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0, rgba32f) uniform readonly image2D velocity;
+            layout(set = 0, binding = 1, r32f) uniform readonly image2D pressure;
+            layout(set = 0, binding = 2, rgba32f) uniform writeonly image2D dst;
+
+            float fetch(ivec2 p, ivec2 size) {
+                return imageLoad(pressure, clamp(p, ivec2(0), size - 1)).r;
+            }
+
+            void main() {
+                ivec2 p = ivec2(gl_GlobalInvocationID.xy);
+                ivec2 size = imageSize(dst);
+                if (any(greaterThanEqual(p, size))) {
+                    return;
+                }
+
+                // Subtracting the pressure gradient leaves the divergence free
+                // part of the velocity.
+                vec2 gradient = 0.5 * vec2(
+                    fetch(p + ivec2(1, 0), size) - fetch(p - ivec2(1, 0), size),
+                    fetch(p + ivec2(0, 1), size) - fetch(p - ivec2(0, 1), size));
+                vec2 v = imageLoad(velocity, p).xy - gradient;
+                imageStore(dst, p, vec4(v, 0.0, 0.0));
+            }
+        ",
+    }
+}
+
+mod display {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0, rgba32f) uniform readonly image2D dye;
+            layout(set = 0, binding = 1, rgba8) uniform writeonly image2D img;
+
+            void main() {
+                ivec2 p = ivec2(gl_GlobalInvocationID.xy);
+                if (any(greaterThanEqual(p, imageSize(img)))) {
+                    return;
+                }
+
+                // Dye piles up without limit, map it smoothly into 0..1.
+                vec3 c = 1.0 - exp(-imageLoad(dye, p).rgb);
+                imageStore(img, p, vec4(c, 1.0));
+            }
+        ",
+    }
+}
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct AdvectParams {
+    dt: f32,
+    dissipation: f32,
+}
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct SplatParams {
+    value: [f32; 4],
+    center: [f32; 2],
+    radius: f32,
+}
+
+// A source of dye and momentum for one step, in grid cells. The force is
+// scaled by the time step, the dye is added once per step.
+#[derive(Clone, Copy, Debug)]
+struct Splat {
+    center: [f32; 2],
+    radius: f32,
+    force: [f32; 2],
+    color: [f32; 3],
+}
+
+struct Fluid {
+    queue: Arc<Queue>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    descriptor_set_allocator: StandardDescriptorSetAllocator,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+    advect: Arc<ComputePipeline>,
+    splat: Arc<ComputePipeline>,
+    divergence: Arc<ComputePipeline>,
+    jacobi: Arc<ComputePipeline>,
+    project: Arc<ComputePipeline>,
+    display: Arc<ComputePipeline>,
+    // Ping-pong pairs, index 0 always holds the current state.
+    velocity: [Arc<ImageView>; 2],
+    dye: [Arc<ImageView>; 2],
+    pressure: [Arc<ImageView>; 2],
+    divergence_field: Arc<ImageView>,
+    output: Arc<ImageView>,
+    extent: [u32; 2],
+}
+
+impl Fluid {
+    fn new(queue: Arc<Queue>, extent: [u32; 2]) -> Self {
+        let device = queue.device().clone();
+        let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+        let pipeline = |shader| util::create_compute_pipeline(device.clone(), shader);
+        let advect = pipeline(advect::load(device.clone()).unwrap());
+        let splat = pipeline(splat::load(device.clone()).unwrap());
+        let divergence = pipeline(divergence::load(device.clone()).unwrap());
+        let jacobi = pipeline(jacobi::load(device.clone()).unwrap());
+        let project = pipeline(project::load(device.clone()).unwrap());
+        let display = pipeline(display::load(device.clone()).unwrap());
+
+        let image = |format| {
+            let image = util::create_render_target(
+                &memory_allocator,
+                format,
+                extent,
+                ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST,
+            );
+            ImageView::new_default(image).unwrap()
+        };
+        let velocity = [image(FIELD_FORMAT), image(FIELD_FORMAT)];
+        let dye = [image(FIELD_FORMAT), image(FIELD_FORMAT)];
+        let pressure = [image(SCALAR_FORMAT), image(SCALAR_FORMAT)];
+        let divergence_field = image(SCALAR_FORMAT);
+        let output = image(Format::R8G8B8A8_UNORM);
+
+        let descriptor_set_allocator =
+            StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+        let command_buffer_allocator = StandardCommandBufferAllocator::new(
+            device.clone(),
+            StandardCommandBufferAllocatorCreateInfo::default(),
+        );
+
+        let fluid = Fluid {
+            queue,
+            memory_allocator,
+            descriptor_set_allocator,
+            command_buffer_allocator,
+            advect,
+            splat,
+            divergence,
+            jacobi,
+            project,
+            display,
+            velocity,
+            dye,
+            pressure,
+            divergence_field,
+            output,
+            extent,
+        };
+
+        // New images hold garbage, the fluid starts at rest and clear.
+        let mut builder = util::begin_one_time(&fluid.command_buffer_allocator, &fluid.queue);
+        for view in fluid
+            .velocity
+            .iter()
+            .chain(&fluid.dye)
+            .chain(&fluid.pressure)
+        {
+            builder
+                .clear_color_image(ClearColorImageInfo::image(view.image().clone()))
+                .unwrap();
+        }
+        util::submit_and_wait(builder, &fluid.queue);
+        fluid
+    }
+
+    // Binds the pipeline and the views in binding order.
+    fn bind(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        pipeline: &Arc<ComputePipeline>,
+        views: &[&Arc<ImageView>],
+    ) {
+        let set = PersistentDescriptorSet::new(
+            &self.descriptor_set_allocator,
+            pipeline.layout().set_layouts()[0].clone(),
+            views.iter().enumerate().map(|(binding, &view)| {
+                WriteDescriptorSet::image_view(binding as u32, view.clone())
+            }),
+            [],
+        )
+        .unwrap();
+
+        builder
+            .bind_pipeline_compute(pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                pipeline.layout().clone(),
+                0,
+                set,
+            )
+            .unwrap();
+    }
+
+    // One invocation per cell.
+    fn dispatch(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        pipeline: &Arc<ComputePipeline>,
+        views: &[&Arc<ImageView>],
+    ) {
+        self.bind(builder, pipeline, views);
+        builder
+            .dispatch([self.extent[0].div_ceil(16), self.extent[1].div_ceil(16), 1])
+            .unwrap();
+    }
+
+    fn dispatch_with<P: BufferContents>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        pipeline: &Arc<ComputePipeline>,
+        views: &[&Arc<ImageView>],
+        push_constants: P,
+    ) {
+        self.bind(builder, pipeline, views);
+        builder
+            .push_constants(pipeline.layout().clone(), 0, push_constants)
+            .unwrap()
+            .dispatch([self.extent[0].div_ceil(16), self.extent[1].div_ceil(16), 1])
+            .unwrap();
+    }
+
+    fn record_splat(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        splat: Splat,
+        dt: f32,
+    ) {
+        let params = |value| SplatParams {
+            value,
+            center: splat.center,
+            radius: splat.radius,
+        };
+        let [fx, fy] = splat.force;
+        let [r, g, b] = splat.color;
+        self.dispatch_with(
+            builder,
+            &self.splat,
+            &[&self.velocity[0]],
+            params([fx * dt, fy * dt, 0.0, 0.0]),
+        );
+        self.dispatch_with(
+            builder,
+            &self.splat,
+            &[&self.dye[0]],
+            params([r, g, b, 0.0]),
+        );
+    }
+
+    // Makes the current velocity divergence free.
+    fn record_projection(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) {
+        self.dispatch(
+            builder,
+            &self.divergence,
+            &[&self.velocity[0], &self.divergence_field],
+        );
+        // The pressure of the last step is a good first guess.
+        for _ in 0..JACOBI_ITERATIONS {
+            self.dispatch(
+                builder,
+                &self.jacobi,
+                &[&self.pressure[0], &self.divergence_field, &self.pressure[1]],
+            );
+            self.pressure.swap(0, 1);
+        }
+        self.dispatch(
+            builder,
+            &self.project,
+            &[&self.velocity[0], &self.pressure[0], &self.velocity[1]],
+        );
+        self.velocity.swap(0, 1);
+    }
+
+    // One full step in a single command buffer: forces and dye, advection of
+    // the velocity by itself, projection and finally advection of the dye.
+    fn step(&mut self, dt: f32, splats: &[Splat]) {
+        let mut builder = util::begin_one_time(&self.command_buffer_allocator, &self.queue);
+        for &splat in splats {
+            self.record_splat(&mut builder, splat, dt);
+        }
+
+        let advect = AdvectParams {
+            dt,
+            dissipation: 0.999,
+        };
+        self.dispatch_with(
+            &mut builder,
+            &self.advect,
+            &[&self.velocity[0], &self.velocity[0], &self.velocity[1]],
+            advect,
+        );
+        self.velocity.swap(0, 1);
+
+        self.record_projection(&mut builder);
+
+        let advect = AdvectParams {
+            dt,
+            dissipation: 0.995,
+        };
+        self.dispatch_with(
+            &mut builder,
+            &self.advect,
+            &[&self.velocity[0], &self.dye[0], &self.dye[1]],
+            advect,
+        );
+        self.dye.swap(0, 1);
+
+        util::submit_and_wait(builder, &self.queue);
+    }
+
+    fn render(&self) -> RgbaImage {
+        let mut builder = util::begin_one_time(&self.command_buffer_allocator, &self.queue);
+        self.dispatch(&mut builder, &self.display, &[&self.dye[0], &self.output]);
+        util::submit_and_wait(builder, &self.queue);
+
+        texture::read_mip_level(
+            self.output.image(),
+            0,
+            &self.memory_allocator,
+            &self.command_buffer_allocator,
+            &self.queue,
+        )
+    }
+
+    // The velocity field as (x, y) pairs, row by row.
+    fn read_velocity(&self) -> Vec<[f32; 2]> {
+        let [width, height] = self.extent;
+        let buf = util::create_buffer(
+            (0..width * height).map(|_| [0.0f32; 4]),
+            &self.memory_allocator,
+            BufferUsage::TRANSFER_DST,
+            MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+        );
+        let mut builder = util::begin_one_time(&self.command_buffer_allocator, &self.queue);
+        builder
+            .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+                self.velocity[0].image().clone(),
+                buf.clone(),
+            ))
+            .unwrap();
+        util::submit_and_wait(builder, &self.queue);
+
+        let velocity = buf.read().unwrap();
+        velocity.iter().map(|&[x, y, _, _]| [x, y]).collect()
+    }
+}
+
+// Mean absolute divergence, with the same stencil and walls as the shader.
+fn mean_divergence(velocity: &[[f32; 2]], extent: [u32; 2]) -> f64 {
+    let [width, height] = extent.map(|e| e as i32);
+    let fetch = |x: i32, y: i32| {
+        if x < 0 || y < 0 || x >= width || y >= height {
+            [0.0, 0.0]
+        } else {
+            velocity[(y * width + x) as usize]
+        }
+    };
+    let mut sum = 0.0;
+    for y in 0..height {
+        for x in 0..width {
+            let d = 0.5
+                * (fetch(x + 1, y)[0] - fetch(x - 1, y)[0] + fetch(x, y + 1)[1]
+                    - fetch(x, y - 1)[1]);
+            sum += d.abs() as f64;
+        }
+    }
+    sum / (width * height) as f64
+}
+
+pub fn fluid() {
+    let mut vk_device = util::create_device();
+    let queue = vk_device.queues.next().unwrap();
+    let extent = [256, 256];
+
+    // A single push creates a strongly divergent field, projecting it has to
+    // remove most of that.
+    let mut fluid = Fluid::new(queue.clone(), extent);
+    let mut builder = util::begin_one_time(&fluid.command_buffer_allocator, &fluid.queue);
+    fluid.record_splat(
+        &mut builder,
+        Splat {
+            center: [128.0, 128.0],
+            radius: 12.0,
+            force: [0.0, 80.0],
+            color: [1.0, 1.0, 1.0],
+        },
+        1.0,
+    );
+    util::submit_and_wait(builder, &fluid.queue);
+    let before = mean_divergence(&fluid.read_velocity(), extent);
+    let mut builder = util::begin_one_time(&fluid.command_buffer_allocator, &fluid.queue);
+    fluid.record_projection(&mut builder);
+    util::submit_and_wait(builder, &fluid.queue);
+    let after = mean_divergence(&fluid.read_velocity(), extent);
+    println!("Mean divergence before projection {before:.3e}, after {after:.3e}");
+    assert!(before > 0.0 && after < 0.5 * before);
+
+    // Two jets of dye pushing towards each other from the sides, their
+    // colors slowly cycling.
+    let mut fluid = Fluid::new(queue, extent);
+    let mut frames = Vec::new();
+    for n in 0..240 {
+        let t = n as f32 * 0.05;
+        let splats = [
+            Splat {
+                center: [40.0, 118.0],
+                radius: 8.0,
+                force: [240.0, 40.0 * t.sin()],
+                color: [0.8 + 0.2 * t.sin(), 0.2, 0.1],
+            },
+            Splat {
+                center: [216.0, 138.0],
+                radius: 8.0,
+                force: [-240.0, 40.0 * t.cos()],
+                color: [0.1, 0.3, 0.8 + 0.2 * t.cos()],
+            },
+        ];
+        fluid.step(0.25, &splats);
+        if n % 4 == 3 {
+            frames.push(fluid.render());
+        }
+    }
+
+    let last = frames.last().unwrap();
+    assert!(
+        last.pixels().any(|p| p[0] > 0 || p[2] > 0),
+        "no dye in the final frame"
+    );
+    last.save("fluid.png").unwrap();
+    export::save_png_sequence(&frames, "fluid");
+
+    println!("Stable fluids simulation successful!");
+}
@@ -0,0 +1,408 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use image::{Rgba, RgbaImage};
+use vulkano::buffer::{BufferContents, BufferUsage};
+use vulkano::command_buffer::allocator::{
+    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+};
+use vulkano::command_buffer::CopyBufferToImageInfo;
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::device::{DeviceOwned, Queue};
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageUsage};
+use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::{ComputePipeline, Pipeline, PipelineBindPoint};
+
+use crate::{export, texture, util};
+
+mod cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0, rgba8) uniform readonly image2D src;
+            layout(set = 0, binding = 1, rgba8) uniform writeonly image2D dst;
+
+            // Bit n is set if a cell with n live neighbours is born or
+            // survives.
+            layout(push_constant) uniform Params {
+                uint birth;
+                uint survival;
+            } params;
+
+            bool alive(ivec2 p, ivec2 size) {
+                // The grid is a torus, cells past one edge come in at the
+                // opposite one.
+                return imageLoad(src, (p + size) % size).r > 0.5;
+            }
+
+            void main() {
+                ivec2 p = ivec2(gl_GlobalInvocationID.xy);
+                ivec2 size = imageSize(src);
+                if (any(greaterThanEqual(p, size))) {
+                    return;
+                }
+
+                uint neighbours = 0;
+                for (int y = -1; y <= 1; y++) {
+                    for (int x = -1; x <= 1; x++) {
+                        if ((x != 0 || y != 0) && alive(p + ivec2(x, y), size)) {
+                            neighbours++;
+                        }
+                    }
+                }
+
+                uint rule = alive(p, size) ? params.survival : params.birth;
+                float next = float((rule >> neighbours) & 1u);
+                imageStore(dst, p, vec4(vec3(next), 1.0));
+            }
+        ",
+    }
+}
+
+// A Life-like rule in B/S notation, e.g. B3/S23 for Conway's Game of Life.
+#[derive(BufferContents, Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct Rule {
+    birth: u32,
+    survival: u32,
+}
+
+impl Rule {
+    pub const CONWAY: Rule = Rule::new(&[3], &[2, 3]);
+    pub const HIGH_LIFE: Rule = Rule::new(&[3, 6], &[2, 3]);
+    pub const SEEDS: Rule = Rule::new(&[2], &[]);
+    pub const DAY_AND_NIGHT: Rule = Rule::new(&[3, 6, 7, 8], &[3, 4, 6, 7, 8]);
+
+    pub const fn new(birth: &[u32], survival: &[u32]) -> Self {
+        Rule {
+            birth: Self::mask(birth),
+            survival: Self::mask(survival),
+        }
+    }
+
+    const fn mask(counts: &[u32]) -> u32 {
+        let mut mask = 0;
+        let mut i = 0;
+        while i < counts.len() {
+            mask |= 1 << counts[i];
+            i += 1;
+        }
+        mask
+    }
+
+    fn next(self, alive: bool, neighbours: u32) -> bool {
+        let rule = if alive { self.survival } else { self.birth };
+        (rule >> neighbours) & 1 != 0
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |part: Option<&str>, prefix: char| {
+            let digits = part
+                .and_then(|p| p.strip_prefix(prefix))
+                .ok_or_else(|| format!("{s}: expected B<digits>/S<digits>"))?;
+            let mut mask = 0u32;
+            for d in digits.chars() {
+                match d.to_digit(10) {
+                    Some(n) if n <= 8 => mask |= 1 << n,
+                    _ => return Err(format!("{s}: {d} is not a neighbour count")),
+                }
+            }
+            Ok(mask)
+        };
+        let mut parts = s.split('/');
+        let birth = parse(parts.next(), 'B')?;
+        let survival = parse(parts.next(), 'S')?;
+        if parts.next().is_some() {
+            return Err(format!("{s}: expected B<digits>/S<digits>"));
+        }
+        Ok(Rule { birth, survival })
+    }
+}
+
+pub enum Seed {
+    // Every cell is alive with probability `density`.
+    Random {
+        width: u32,
+        height: u32,
+        density: f32,
+        seed: u32,
+    },
+    // Bright pixels are alive, the grid takes the size of the image.
+    Png(PathBuf),
+}
+
+impl Seed {
+    fn cells(&self) -> RgbaImage {
+        match self {
+            Seed::Random {
+                width,
+                height,
+                density,
+                seed,
+            } => {
+                let threshold = (density.clamp(0.0, 1.0) as f64 * u32::MAX as f64) as u32;
+                let mut words = util::random_words((width * height) as usize, *seed);
+                RgbaImage::from_fn(*width, *height, |_, _| {
+                    cell(words.next().unwrap() < threshold)
+                })
+            }
+            Seed::Png(path) => {
+                let image = image::open(path)
+                    .expect("failed to load seed image")
+                    .to_luma8();
+                RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+                    cell(image.get_pixel(x, y)[0] >= 128)
+                })
+            }
+        }
+    }
+}
+
+fn cell(alive: bool) -> Rgba<u8> {
+    let v = if alive { 255 } else { 0 };
+    Rgba([v, v, v, 255])
+}
+
+fn is_alive(pixel: &Rgba<u8>) -> bool {
+    pixel[0] >= 128
+}
+
+// Two storage images take turns as source and destination, after every step
+// the one that was just written becomes the current generation.
+struct Life {
+    queue: Arc<Queue>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+    pipeline: Arc<ComputePipeline>,
+    images: [Arc<Image>; 2],
+    // descriptor_sets[i] reads images[i] and writes the other one.
+    descriptor_sets: [Arc<PersistentDescriptorSet>; 2],
+    current: usize,
+    rule: Rule,
+}
+
+impl Life {
+    fn new(queue: Arc<Queue>, rule: Rule, seed: &Seed) -> Self {
+        let device = queue.device().clone();
+        let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+        let descriptor_set_allocator =
+            StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+        let command_buffer_allocator = StandardCommandBufferAllocator::new(
+            device.clone(),
+            StandardCommandBufferAllocatorCreateInfo::default(),
+        );
+
+        let shader = cs::load(device.clone()).expect("failed to create shader module");
+        let pipeline = util::create_compute_pipeline(device.clone(), shader);
+
+        let cells = seed.cells();
+        let image = || {
+            util::create_render_target(
+                &memory_allocator,
+                Format::R8G8B8A8_UNORM,
+                [cells.width(), cells.height()],
+                ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST,
+            )
+        };
+        let images = [image(), image()];
+
+        let view = |i: usize| ImageView::new_default(images[i].clone()).unwrap();
+        let descriptor_set = |src: usize| {
+            PersistentDescriptorSet::new(
+                &descriptor_set_allocator,
+                pipeline.layout().set_layouts()[0].clone(),
+                [
+                    WriteDescriptorSet::image_view(0, view(src)),
+                    WriteDescriptorSet::image_view(1, view(1 - src)),
+                ],
+                [],
+            )
+            .unwrap()
+        };
+        let descriptor_sets = [descriptor_set(0), descriptor_set(1)];
+
+        let mut life = Life {
+            queue,
+            memory_allocator,
+            command_buffer_allocator,
+            pipeline,
+            images,
+            descriptor_sets,
+            current: 0,
+            rule,
+        };
+        life.upload(&cells);
+        life
+    }
+
+    fn upload(&mut self, cells: &RgbaImage) {
+        let staging_buffer = util::create_buffer(
+            cells.as_raw().iter().copied(),
+            &self.memory_allocator,
+            BufferUsage::TRANSFER_SRC,
+            MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+        );
+        self.current = 0;
+        let mut builder = util::begin_one_time(&self.command_buffer_allocator, &self.queue);
+        builder
+            .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(
+                staging_buffer,
+                self.images[0].clone(),
+            ))
+            .unwrap();
+        util::submit_and_wait(builder, &self.queue);
+    }
+
+    // All steps go into one command buffer, vulkano puts the barriers between
+    // them.
+    fn step(&mut self, steps: u32) {
+        let [width, height, _] = self.images[0].extent();
+        let mut builder = util::begin_one_time(&self.command_buffer_allocator, &self.queue);
+        builder
+            .bind_pipeline_compute(self.pipeline.clone())
+            .unwrap()
+            .push_constants(self.pipeline.layout().clone(), 0, self.rule)
+            .unwrap();
+        for _ in 0..steps {
+            builder
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Compute,
+                    self.pipeline.layout().clone(),
+                    0,
+                    self.descriptor_sets[self.current].clone(),
+                )
+                .unwrap()
+                .dispatch([width.div_ceil(16), height.div_ceil(16), 1])
+                .unwrap();
+            self.current = 1 - self.current;
+        }
+        util::submit_and_wait(builder, &self.queue);
+    }
+
+    fn snapshot(&self) -> RgbaImage {
+        texture::read_mip_level(
+            &self.images[self.current],
+            0,
+            &self.memory_allocator,
+            &self.command_buffer_allocator,
+            &self.queue,
+        )
+    }
+}
+
+fn cpu_step(cells: &RgbaImage, rule: Rule) -> RgbaImage {
+    let (width, height) = cells.dimensions();
+    RgbaImage::from_fn(width, height, |x, y| {
+        let mut neighbours = 0;
+        for dy in [height - 1, 0, 1] {
+            for dx in [width - 1, 0, 1] {
+                if (dx, dy) != (0, 0)
+                    && is_alive(cells.get_pixel((x + dx) % width, (y + dy) % height))
+                {
+                    neighbours += 1;
+                }
+            }
+        }
+        cell(rule.next(is_alive(cells.get_pixel(x, y)), neighbours))
+    })
+}
+
+fn run(
+    queue: &Arc<Queue>,
+    rule: Rule,
+    seed: &Seed,
+    frames: u32,
+    steps_per_frame: u32,
+) -> Vec<RgbaImage> {
+    let mut life = Life::new(queue.clone(), rule, seed);
+    let mut snapshots = vec![life.snapshot()];
+    for _ in 1..frames {
+        life.step(steps_per_frame);
+        snapshots.push(life.snapshot());
+    }
+    snapshots
+}
+
+pub fn game_of_life() {
+    let mut vk_device = util::create_device();
+    let queue = vk_device.queues.next().unwrap();
+
+    // Every generation has to match the CPU, odd sizes test the partial
+    // workgroups and the wrap-around.
+    let seed = Seed::Random {
+        width: 67,
+        height: 45,
+        density: 0.35,
+        seed: 0x27d4_eb2f,
+    };
+    for rule in ["B3/S23", "B36/S23", "B2/S", "B3678/S34678"] {
+        let rule: Rule = rule.parse().unwrap();
+        let generations = run(&queue, rule, &seed, 30, 1);
+        for (n, pair) in generations.windows(2).enumerate() {
+            assert!(
+                pair[1] == cpu_step(&pair[0], rule),
+                "{rule:?} differs from the CPU after {} steps",
+                n + 1
+            );
+        }
+    }
+    assert_eq!("B3/S23".parse(), Ok(Rule::CONWAY));
+    assert_eq!("B36/S23".parse(), Ok(Rule::HIGH_LIFE));
+    assert_eq!("B2/S".parse(), Ok(Rule::SEEDS));
+    assert_eq!("B3678/S34678".parse(), Ok(Rule::DAY_AND_NIGHT));
+    assert!("B3/S23/junk".parse::<Rule>().is_err());
+
+    // A glider moves one cell diagonally every 4 generations, so on a 32x32
+    // torus it is back where it started after 128.
+    let mut glider = RgbaImage::from_pixel(32, 32, cell(false));
+    for (x, y) in [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+        glider.put_pixel(x, y, cell(true));
+    }
+    glider.save("life_glider.png").unwrap();
+    let generations = run(
+        &queue,
+        Rule::CONWAY,
+        &Seed::Png("life_glider.png".into()),
+        2,
+        128,
+    );
+    assert!(generations[1] == glider, "the glider did not wrap around");
+
+    let frames = run(
+        &queue,
+        Rule::CONWAY,
+        &Seed::Random {
+            width: 256,
+            height: 256,
+            density: 0.3,
+            seed: 1,
+        },
+        120,
+        1,
+    );
+    export::save_gif(&frames, 20, "game_of_life.gif");
+
+    // Day and Night from a picture keeps its blobs around for a long time.
+    let frames = run(
+        &queue,
+        Rule::DAY_AND_NIGHT,
+        &Seed::Png("assets/checker.png".into()),
+        60,
+        2,
+    );
+    export::save_png_sequence(&frames, "day_and_night");
+
+    println!("Game of Life successful!");
+}
@@ -11,7 +11,7 @@ use vulkano::command_buffer::{
 };
 use vulkano::format::Format;
 use vulkano::image::view::ImageView;
-use vulkano::image::ImageUsage;
+use vulkano::image::{ImageUsage, SampleCount};
 use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
 use vulkano::pipeline::graphics::color_blend::{ColorBlendAttachmentState, ColorBlendState};
 use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
@@ -64,7 +64,10 @@ mod fs {
     }
 }
 
-pub fn graphics_pipeline() {
+// `msaa_samples` is clamped to what the device supports. With more than one
+// sample the triangle is rendered into a multisampled image that is resolved
+// into `image` at the end of the subpass.
+pub fn graphics_pipeline(msaa_samples: u32) {
     let mut vk_device = util::create_device();
     let queue = vk_device.queues.next().unwrap();
     let device = vk_device.device.clone();
@@ -100,28 +103,71 @@ pub fn graphics_pipeline() {
         MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
     );
 
-    let render_pass = vulkano::single_pass_renderpass!(
-        device.clone(),
-        attachments: {
-            color: {
-                format: Format::R8G8B8A8_UNORM,
-                samples: 1,
-                load_op: Clear,
-                store_op: Store,
+    let samples = util::choose_sample_count(device.physical_device(), msaa_samples);
+    let view = ImageView::new_default(image.clone()).unwrap();
+
+    let (render_pass, attachments) = if samples == SampleCount::Sample1 {
+        let render_pass = vulkano::single_pass_renderpass!(
+            device.clone(),
+            attachments: {
+                color: {
+                    format: Format::R8G8B8A8_UNORM,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: Store,
+                },
             },
-        },
-        pass: {
-            color: [color],
-            depth_stencil: {},
-        },
-    )
-    .unwrap();
+            pass: {
+                color: [color],
+                depth_stencil: {},
+            },
+        )
+        .unwrap();
+        (render_pass, vec![view])
+    } else {
+        let msaa_image = util::create_multisampled_image(
+            &memory_allocator,
+            Format::R8G8B8A8_UNORM,
+            [1024, 1024],
+            samples,
+        );
+        let render_pass = vulkano::single_pass_renderpass!(
+            device.clone(),
+            attachments: {
+                msaa_color: {
+                    format: Format::R8G8B8A8_UNORM,
+                    samples: u32::from(samples),
+                    load_op: Clear,
+                    // The samples are discarded once they are resolved.
+                    store_op: DontCare,
+                },
+                color: {
+                    format: Format::R8G8B8A8_UNORM,
+                    samples: 1,
+                    load_op: DontCare,
+                    store_op: Store,
+                },
+            },
+            pass: {
+                color: [msaa_color],
+                color_resolve: [color],
+                depth_stencil: {},
+            },
+        )
+        .unwrap();
+        let msaa_view = ImageView::new_default(msaa_image).unwrap();
+        (render_pass, vec![msaa_view, view])
+    };
+
+    // Only the first attachment is cleared, the resolve target is fully
+    // overwritten.
+    let mut clear_values = vec![Some([0.0, 0.0, 1.0, 1.0].into())];
+    clear_values.resize(attachments.len(), None);
 
-    let view = ImageView::new_default(image.clone()).unwrap();
     let framebuffer = Framebuffer::new(
         render_pass.clone(),
         FramebufferCreateInfo {
-            attachments: vec![view],
+            attachments,
             ..Default::default()
         },
     )
@@ -173,9 +219,13 @@ pub fn graphics_pipeline() {
                     viewports: [viewport].into_iter().collect(),
                     ..Default::default()
                 }),
-                // Ignore these for now.
+                // Ignore this for now.
                 rasterization_state: Some(RasterizationState::default()),
-                multisample_state: Some(MultisampleState::default()),
+                // Must match the sample count of the color attachment.
+                multisample_state: Some(MultisampleState {
+                    rasterization_samples: samples,
+                    ..Default::default()
+                }),
                 color_blend_state: Some(ColorBlendState::with_attachment_states(
                     subpass.num_color_attachments(),
                     ColorBlendAttachmentState::default(),
@@ -203,7 +253,7 @@ pub fn graphics_pipeline() {
     builder
         .begin_render_pass(
             RenderPassBeginInfo {
-                clear_values: vec![Some([0.0, 0.0, 1.0, 1.0].into())],
+                clear_values,
                 ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
             },
             SubpassBeginInfo {
@@ -236,7 +286,13 @@ pub fn graphics_pipeline() {
 
     let buffer_content = buf.read().unwrap();
     let image = ImageBuffer::<Rgba<u8>, _>::from_raw(1024, 1024, &buffer_content[..]).unwrap();
-    image.save("vertex_image.png").unwrap();
+    if samples == SampleCount::Sample1 {
+        image.save("vertex_image.png").unwrap();
+    } else {
+        image
+            .save(format!("vertex_image_msaa{}x.png", u32::from(samples)))
+            .unwrap();
+    }
 
     println!("Graphics pipeline successful!");
 }
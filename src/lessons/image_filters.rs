@@ -0,0 +1,165 @@
+use std::sync::Arc;
+
+use image::{Rgba, RgbaImage};
+use vulkano::memory::allocator::StandardMemoryAllocator;
+
+use crate::filter::{FilterChain, ImageFilter};
+use crate::util;
+
+const USAGE: &str = "usage: filter <input> <output> <filter>...";
+
+fn create_chain() -> FilterChain {
+    let mut vk_device = util::create_device();
+    let device = vk_device.device.clone();
+    let queue = vk_device.queues.next().unwrap();
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+    FilterChain::new(queue, memory_allocator)
+}
+
+fn random_image(width: u32, height: u32, seed: u32) -> RgbaImage {
+    let mut words = util::random_words((width * height) as usize, seed);
+    RgbaImage::from_fn(width, height, |_, _| {
+        Rgba(words.next().unwrap().to_le_bytes())
+    })
+}
+
+// The median of 8 bit values is one of the inputs, so the GPU has to match
+// this exactly.
+fn median_reference(image: &RgbaImage, radius: i32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    RgbaImage::from_fn(width, height, |x, y| {
+        let mut window = Vec::new();
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let sx = (x as i32 + dx).clamp(0, width as i32 - 1) as u32;
+                let sy = (y as i32 + dy).clamp(0, height as i32 - 1) as u32;
+                window.push(*image.get_pixel(sx, sy));
+            }
+        }
+        let mut pixel = *image.get_pixel(x, y);
+        for c in 0..3 {
+            let mut values: Vec<u8> = window.iter().map(|p| p[c]).collect();
+            values.sort_unstable();
+            pixel[c] = values[values.len() / 2];
+        }
+        pixel
+    })
+}
+
+fn assert_close(a: &RgbaImage, b: &RgbaImage, tolerance: u8, what: &str) {
+    assert_eq!(a.dimensions(), b.dimensions());
+    for (x, y, p) in a.enumerate_pixels() {
+        let q = b.get_pixel(x, y);
+        for c in 0..4 {
+            assert!(
+                p[c].abs_diff(q[c]) <= tolerance,
+                "{what} differs at ({x}, {y}): {p:?} vs {q:?}"
+            );
+        }
+    }
+}
+
+pub fn image_filters() {
+    let chain = create_chain();
+
+    // Odd sizes so the last workgroups are only partially inside.
+    let input = random_image(37, 23, 0x85eb_ca6b);
+
+    let identity = ImageFilter::BrightnessContrast {
+        brightness: 0.0,
+        contrast: 1.0,
+    };
+    assert_close(&chain.apply(&input, &[identity]), &input, 0, "identity");
+
+    let gray = chain.apply(&input, &[ImageFilter::Grayscale]);
+    let expected = RgbaImage::from_fn(input.width(), input.height(), |x, y| {
+        let [r, g, b, a] = input.get_pixel(x, y).0.map(|c| c as f32);
+        let l = (0.299 * r + 0.587 * g + 0.114 * b).round() as u8;
+        Rgba([l, l, l, a as u8])
+    });
+    assert_close(&gray, &expected, 1, "grayscale");
+
+    for radius in 1..=3 {
+        let median = chain.apply(&input, &[ImageFilter::Median { radius }]);
+        assert_close(
+            &median,
+            &median_reference(&input, radius as i32),
+            0,
+            "median",
+        );
+    }
+
+    // Flat images stay flat under blurring and sharpening and have no edges.
+    let flat = RgbaImage::from_pixel(40, 30, Rgba([200, 120, 40, 255]));
+    let filters = [
+        ImageFilter::GaussianBlur { sigma: 3.0 },
+        ImageFilter::Sharpen { amount: 1.0 },
+    ];
+    assert_close(
+        &chain.apply(&flat, &filters),
+        &flat,
+        1,
+        "blurred flat image",
+    );
+    let edges = chain.apply(&flat, &[ImageFilter::Sobel { scale: 1.0 }]);
+    assert_close(
+        &edges,
+        &RgbaImage::from_pixel(40, 30, Rgba([0, 0, 0, 255])),
+        0,
+        "edges of a flat image",
+    );
+
+    // A whole chain on the Mandelbrot image rendered earlier.
+    let mandelbrot = image::open("mandelbrot.png")
+        .expect("failed to load mandelbrot.png")
+        .to_rgba8();
+    let filters = [
+        ImageFilter::Grayscale,
+        ImageFilter::GaussianBlur { sigma: 1.5 },
+        ImageFilter::Sobel { scale: 2.0 },
+        ImageFilter::BrightnessContrast {
+            brightness: 0.0,
+            contrast: 1.5,
+        },
+    ];
+    chain
+        .apply(&mandelbrot, &filters)
+        .save("mandelbrot_edges.png")
+        .unwrap();
+
+    println!("Image filters successful!");
+}
+
+// Runs the filters named on the command line over one image, e.g.
+// `filter in.png out.png grayscale blur:1.5 sobel:2`.
+pub fn filter_image(args: &[String]) {
+    let [input, output, filters @ ..] = args else {
+        usage_error("missing arguments");
+    };
+    let filters: Vec<ImageFilter> = filters
+        .iter()
+        .map(|f| f.parse().unwrap_or_else(|e: String| usage_error(&e)))
+        .collect();
+
+    let image = image::open(input)
+        .unwrap_or_else(|e| file_error(&format!("failed to load {input}: {e}")))
+        .to_rgba8();
+    create_chain()
+        .apply(&image, &filters)
+        .save(output)
+        .unwrap_or_else(|e| file_error(&format!("failed to save {output}: {e}")));
+
+    println!("Applied {} filters to {input}", filters.len());
+}
+
+// Bad command line input is not a bug, so no panic and backtrace.
+fn usage_error(message: &str) -> ! {
+    eprintln!("{message}\n{USAGE}");
+    std::process::exit(2);
+}
+
+// Neither is a missing, unreadable or unwritable file.
+fn file_error(message: &str) -> ! {
+    eprintln!("{message}");
+    std::process::exit(1);
+}
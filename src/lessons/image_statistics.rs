@@ -0,0 +1,126 @@
+use std::sync::Arc;
+
+use image::RgbaImage;
+use vulkano::buffer::BufferUsage;
+use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
+
+use crate::histogram::{Histogram, HistogramCounter};
+use crate::lessons::mandelbrot::{MandelbrotRenderer, MandelbrotView};
+use crate::util;
+
+fn cpu_histogram(bytes: &[u8], channels: usize) -> Histogram {
+    let mut histogram = Histogram {
+        channels: vec![[0; 256]; channels],
+    };
+    for (i, &b) in bytes.iter().enumerate() {
+        histogram.channels[i % channels][b as usize] += 1;
+    }
+    histogram
+}
+
+// Compares the derived statistics against ones computed from the sorted
+// samples.
+fn check_statistics(histogram: &Histogram, bytes: &[u8], channels: usize) {
+    for channel in 0..channels {
+        let mut values: Vec<u8> = bytes
+            .iter()
+            .skip(channel)
+            .step_by(channels)
+            .copied()
+            .collect();
+        values.sort_unstable();
+        let mean = values.iter().map(|&v| v as f64).sum::<f64>() / values.len() as f64;
+
+        assert!((histogram.mean(channel) - mean).abs() < 1e-9);
+        assert_eq!(histogram.min(channel), values[0]);
+        assert_eq!(histogram.max(channel), values[values.len() - 1]);
+        for p in [1.0, 25.0, 50.0, 75.0, 99.0] {
+            let rank = (p / 100.0 * values.len() as f64).ceil().max(1.0) as usize;
+            assert_eq!(histogram.percentile(channel, p), values[rank - 1], "p{p}");
+        }
+    }
+}
+
+pub fn image_statistics() {
+    let mut vk_device = util::create_device();
+    let device = vk_device.device.clone();
+    let queue = vk_device.queues.next().unwrap();
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+    let counter = HistogramCounter::new(queue.clone(), memory_allocator.clone());
+
+    for channels in 1..=4 {
+        // Skewed towards small values so the bins differ a lot in size.
+        let bytes: Vec<u8> = util::random_words(4 * 300_007, channels)
+            .map(|w| ((w & 0xff) * (w >> 24) / 255) as u8)
+            .collect();
+        let buffer = util::create_buffer(
+            bytes.iter().copied(),
+            &memory_allocator,
+            BufferUsage::STORAGE_BUFFER,
+            MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+        );
+        let histogram = counter.of_bytes(&buffer, channels);
+        assert_eq!(histogram, cpu_histogram(&bytes, channels as usize));
+        check_statistics(&histogram, &bytes, channels as usize);
+    }
+
+    // The Mandelbrot image straight from the storage image it is rendered to.
+    let renderer = MandelbrotRenderer::with_queue(queue, 1024, 1024);
+    let pixels = renderer.render(&MandelbrotView::default());
+    let histogram = counter.of_image(renderer.image());
+    assert_eq!(histogram, cpu_histogram(pixels.as_raw(), 4));
+    check_statistics(&histogram, pixels.as_raw(), 4);
+    for (channel, name) in ["red", "green", "blue", "alpha"].iter().enumerate() {
+        println!(
+            "Mandelbrot {name}: mean {:.1}, min {}, median {}, p99 {}, max {}",
+            histogram.mean(channel),
+            histogram.min(channel),
+            histogram.percentile(channel, 50.0),
+            histogram.percentile(channel, 99.0),
+            histogram.max(channel),
+        );
+    }
+
+    // Most of the Mandelbrot image is a handful of dark values, equalizing
+    // spreads them over the whole range.
+    let buffer = util::create_buffer(
+        pixels.as_raw().iter().copied(),
+        &memory_allocator,
+        BufferUsage::STORAGE_BUFFER,
+        MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+    );
+    let (equalized, histogram) = counter.equalize(&buffer, 4);
+    let luts: Vec<[u8; 256]> = (0..3)
+        .map(|channel| histogram.equalization_lut(channel))
+        .collect();
+    let expected: Vec<u8> = pixels
+        .as_raw()
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| {
+            if i % 4 == 3 {
+                b
+            } else {
+                luts[i % 4][b as usize]
+            }
+        })
+        .collect();
+    let equalized = equalized.read().unwrap().to_vec();
+    assert!(
+        equalized == expected,
+        "equalized pixels differ from the CPU"
+    );
+
+    let histogram = cpu_histogram(&equalized, 4);
+    for channel in 0..3 {
+        if histogram.min(channel) != histogram.max(channel) {
+            assert_eq!(histogram.max(channel), 255);
+        }
+    }
+    RgbaImage::from_raw(pixels.width(), pixels.height(), equalized)
+        .unwrap()
+        .save("mandelbrot_equalized.png")
+        .unwrap();
+
+    println!("Histogram and image statistics successful!");
+}
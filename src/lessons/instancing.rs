@@ -0,0 +1,298 @@
+use std::sync::Arc;
+
+use image::{ImageBuffer, Rgba};
+use vulkano::buffer::{BufferContents, BufferUsage};
+use vulkano::command_buffer::allocator::{
+    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CommandBufferUsage, CopyImageToBufferInfo, RenderPassBeginInfo,
+    SubpassBeginInfo, SubpassContents, SubpassEndInfo,
+};
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::ImageUsage;
+use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::color_blend::{ColorBlendAttachmentState, ColorBlendState};
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::RasterizationState;
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{GraphicsPipeline, PipelineLayout, PipelineShaderStageCreateInfo};
+use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, Subpass};
+use vulkano::sync::{self, GpuFuture};
+
+use crate::util::{self, create_buffer};
+
+const GRID_SIZE: u32 = 128;
+
+#[derive(BufferContents, Vertex)]
+#[repr(C)]
+struct TriangleVertex {
+    #[format(R32G32_SFLOAT)]
+    position: [f32; 2],
+}
+
+// Advanced once per instance instead of once per vertex.
+#[derive(BufferContents, Vertex)]
+#[repr(C)]
+struct InstanceData {
+    #[format(R32G32_SFLOAT)]
+    offset: [f32; 2],
+    #[format(R32_SFLOAT)]
+    scale: f32,
+    #[format(R32G32B32A32_SFLOAT)]
+    color: [f32; 4],
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 460
+
+            // Per vertex.
+            layout(location = 0) in vec2 position;
+
+            // Per instance.
+            layout(location = 1) in vec2 offset;
+            layout(location = 2) in float scale;
+            layout(location = 3) in vec4 color;
+
+            layout(location = 0) out vec4 v_color;
+
+            void main() {
+                v_color = color;
+                gl_Position = vec4(position * scale + offset, 0.0, 1.0);
+            }
+        ",
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec4 v_color;
+
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                f_color = v_color;
+            }
+        ",
+    }
+}
+
+// One triangle per grid cell, with the size varying in a repeating pattern
+// and the color following the position.
+fn grid_instances() -> Vec<InstanceData> {
+    let cell = 2.0 / GRID_SIZE as f32;
+    (0..GRID_SIZE * GRID_SIZE)
+        .map(|i| {
+            let (column, row) = (i % GRID_SIZE, i / GRID_SIZE);
+            let u = column as f32 / (GRID_SIZE - 1) as f32;
+            let v = row as f32 / (GRID_SIZE - 1) as f32;
+            InstanceData {
+                offset: [
+                    -1.0 + (column as f32 + 0.5) * cell,
+                    -1.0 + (row as f32 + 0.5) * cell,
+                ],
+                scale: cell * (0.5 + 0.5 * ((column * 7 + row * 13) % 16) as f32 / 15.0),
+                color: [u, v, 1.0 - 0.5 * (u + v), 1.0],
+            }
+        })
+        .collect()
+}
+
+pub fn instancing() {
+    let mut vk_device = util::create_device();
+    let queue = vk_device.queues.next().unwrap();
+    let device = vk_device.device.clone();
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+
+    let image = util::create_image(
+        &memory_allocator,
+        ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+        MemoryTypeFilter::PREFER_DEVICE,
+    );
+
+    let buf = util::create_buffer(
+        (0..1024 * 1024 * 4).map(|_| 0u8),
+        &memory_allocator,
+        BufferUsage::TRANSFER_DST,
+        MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+    );
+
+    // A unit triangle pointing up, centered on the origin.
+    let vertex_buffer = create_buffer(
+        [[0.0, -0.5], [0.5, 0.5], [-0.5, 0.5]].map(|position| TriangleVertex { position }),
+        &memory_allocator,
+        BufferUsage::VERTEX_BUFFER,
+        MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+    );
+
+    let instances = grid_instances();
+    let expected: Vec<_> = instances
+        .iter()
+        .map(|instance| (instance.offset, instance.color))
+        .collect();
+    let instance_buffer = create_buffer(
+        instances,
+        &memory_allocator,
+        BufferUsage::VERTEX_BUFFER,
+        MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+    );
+
+    let render_pass = vulkano::single_pass_renderpass!(
+        device.clone(),
+        attachments: {
+            color: {
+                format: Format::R8G8B8A8_UNORM,
+                samples: 1,
+                load_op: Clear,
+                store_op: Store,
+            },
+        },
+        pass: {
+            color: [color],
+            depth_stencil: {},
+        },
+    )
+    .unwrap();
+
+    let view = ImageView::new_default(image.clone()).unwrap();
+    let framebuffer = Framebuffer::new(
+        render_pass.clone(),
+        FramebufferCreateInfo {
+            attachments: vec![view],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let vs = vs::load(device.clone()).expect("failed to create shader module");
+    let fs = fs::load(device.clone()).expect("failed to create shader module");
+
+    let viewport = Viewport {
+        offset: [0.0, 0.0],
+        extent: [1024.0, 1024.0],
+        depth_range: 0.0..=1.0,
+    };
+    let pipeline = {
+        let vs = vs.entry_point("main").unwrap();
+        let fs = fs.entry_point("main").unwrap();
+
+        // Binding 0 holds the vertices, binding 1 the instances.
+        let vertex_input_state = [TriangleVertex::per_vertex(), InstanceData::per_instance()]
+            .definition(&vs.info().input_interface)
+            .unwrap();
+
+        let stages = [
+            PipelineShaderStageCreateInfo::new(vs),
+            PipelineShaderStageCreateInfo::new(fs),
+        ];
+
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+                .into_pipeline_layout_create_info(device.clone())
+                .unwrap(),
+        )
+        .unwrap();
+
+        let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+
+        GraphicsPipeline::new(
+            device.clone(),
+            None,
+            GraphicsPipelineCreateInfo {
+                stages: stages.into_iter().collect(),
+                vertex_input_state: Some(vertex_input_state),
+                input_assembly_state: Some(InputAssemblyState::default()),
+                viewport_state: Some(ViewportState {
+                    viewports: [viewport].into_iter().collect(),
+                    ..Default::default()
+                }),
+                rasterization_state: Some(RasterizationState::default()),
+                multisample_state: Some(MultisampleState::default()),
+                color_blend_state: Some(ColorBlendState::with_attachment_states(
+                    subpass.num_color_attachments(),
+                    ColorBlendAttachmentState::default(),
+                )),
+                subpass: Some(subpass.into()),
+                ..GraphicsPipelineCreateInfo::layout(layout)
+            },
+        )
+        .unwrap()
+    };
+
+    let command_buffer_allocator = StandardCommandBufferAllocator::new(
+        device.clone(),
+        StandardCommandBufferAllocatorCreateInfo::default(),
+    );
+
+    let mut builder = AutoCommandBufferBuilder::primary(
+        &command_buffer_allocator,
+        queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+
+    let instance_count = instance_buffer.len() as u32;
+    builder
+        .begin_render_pass(
+            RenderPassBeginInfo {
+                clear_values: vec![Some([0.0, 0.0, 0.0, 1.0].into())],
+                ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
+            },
+            SubpassBeginInfo {
+                contents: SubpassContents::Inline,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .bind_pipeline_graphics(pipeline.clone())
+        .unwrap()
+        .bind_vertex_buffers(0, (vertex_buffer.clone(), instance_buffer.clone()))
+        .unwrap()
+        // Every triangle in a single draw call.
+        .draw(3, instance_count, 0, 0)
+        .unwrap()
+        .end_render_pass(SubpassEndInfo::default())
+        .unwrap()
+        .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(image, buf.clone()))
+        .unwrap();
+
+    let command_buffer = builder.build().unwrap();
+
+    let future = sync::now(device.clone())
+        .then_execute(queue.clone(), command_buffer)
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap();
+    future.wait(None).unwrap();
+
+    let buffer_content = buf.read().unwrap();
+    let image = ImageBuffer::<Rgba<u8>, _>::from_raw(1024, 1024, &buffer_content[..]).unwrap();
+
+    // The pixel under each instance's offset must have been drawn by that
+    // instance.
+    for (offset, color) in expected {
+        let x = ((offset[0] + 1.0) * 512.0) as u32;
+        let y = ((offset[1] + 1.0) * 512.0) as u32;
+        let pixel = image.get_pixel(x, y);
+        for channel in 0..4 {
+            let expected = (color[channel] * 255.0).round() as i32;
+            assert!((pixel[channel] as i32 - expected).abs() <= 1);
+        }
+    }
+    image.save("instancing.png").unwrap();
+
+    println!("Instanced rendering of {instance_count} triangles successful!");
+}
@@ -0,0 +1,358 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use vulkano::buffer::{BufferContents, BufferUsage};
+use vulkano::command_buffer::{
+    BlitImageInfo, RenderPassBeginInfo, SubpassBeginInfo, SubpassContents, SubpassEndInfo,
+};
+use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::color_blend::{ColorBlendAttachmentState, ColorBlendState};
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::RasterizationState;
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{
+    DynamicState, GraphicsPipeline, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo,
+};
+use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, Subpass};
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit::event_loop::EventLoop;
+use winit::platform::run_return::EventLoopExtRunReturn;
+
+use crate::lessons::mandelbrot::{
+    interpolate_keyframes, Keyframe, MandelbrotRenderer, MandelbrotView,
+};
+use crate::util::create_buffer;
+use crate::window::Presenter;
+
+#[derive(BufferContents, Vertex)]
+#[repr(C)]
+struct ColoredVertex {
+    #[format(R32G32_SFLOAT)]
+    position: [f32; 2],
+    #[format(R32G32B32_SFLOAT)]
+    color: [f32; 3],
+}
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct PushConstants {
+    angle: f32,
+    aspect_ratio: f32,
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec2 position;
+            layout(location = 1) in vec3 color;
+
+            layout(location = 0) out vec3 v_color;
+
+            layout(push_constant) uniform PushConstants {
+                float angle;
+                float aspect_ratio;
+            } pc;
+
+            void main() {
+                mat2 rotation = mat2(cos(pc.angle), sin(pc.angle), -sin(pc.angle), cos(pc.angle));
+                vec2 p = rotation * position;
+                // Keep the triangle's shape when the window isn't square.
+                gl_Position = vec4(p.x / pc.aspect_ratio, p.y, 0.0, 1.0);
+                v_color = color;
+            }
+        ",
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec3 v_color;
+
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                f_color = vec4(v_color, 1.0);
+            }
+        ",
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scene {
+    Triangle,
+    Mandelbrot,
+}
+
+impl Scene {
+    fn toggled(self) -> Self {
+        match self {
+            Scene::Triangle => Scene::Mandelbrot,
+            Scene::Mandelbrot => Scene::Triangle,
+        }
+    }
+}
+
+// Opens a window and draws `scene` until it is closed. Space switches between
+// the scenes, resizing the window recreates the swapchain.
+pub fn live_preview(mut scene: Scene) {
+    let mut event_loop = EventLoop::new();
+    let mut presenter = Presenter::new(&event_loop, "rust-vulkan");
+
+    // The Mandelbrot scene is blitted into the swapchain, without that only
+    // the triangle can be shown.
+    let can_blit = presenter.can_blit();
+    if scene == Scene::Mandelbrot && !can_blit {
+        eprintln!("The surface can't be blitted to, showing the triangle instead");
+        scene = Scene::Triangle;
+    }
+    let device = presenter.device().clone();
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+
+    let render_pass = vulkano::single_pass_renderpass!(
+        device.clone(),
+        attachments: {
+            color: {
+                format: presenter.format(),
+                samples: 1,
+                load_op: Clear,
+                store_op: Store,
+            },
+        },
+        pass: {
+            color: [color],
+            depth_stencil: {},
+        },
+    )
+    .unwrap();
+
+    let vs = vs::load(device.clone()).expect("failed to create shader module");
+    let fs = fs::load(device.clone()).expect("failed to create shader module");
+
+    let pipeline = {
+        let vs = vs.entry_point("main").unwrap();
+        let fs = fs.entry_point("main").unwrap();
+
+        let vertex_input_state = ColoredVertex::per_vertex()
+            .definition(&vs.info().input_interface)
+            .unwrap();
+
+        let stages = [
+            PipelineShaderStageCreateInfo::new(vs),
+            PipelineShaderStageCreateInfo::new(fs),
+        ];
+
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+                .into_pipeline_layout_create_info(device.clone())
+                .unwrap(),
+        )
+        .unwrap();
+
+        let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+
+        GraphicsPipeline::new(
+            device.clone(),
+            None,
+            GraphicsPipelineCreateInfo {
+                stages: stages.into_iter().collect(),
+                vertex_input_state: Some(vertex_input_state),
+                input_assembly_state: Some(InputAssemblyState::default()),
+                // The window can be resized without rebuilding the pipeline.
+                viewport_state: Some(ViewportState::default()),
+                rasterization_state: Some(RasterizationState::default()),
+                multisample_state: Some(MultisampleState::default()),
+                color_blend_state: Some(ColorBlendState::with_attachment_states(
+                    subpass.num_color_attachments(),
+                    ColorBlendAttachmentState::default(),
+                )),
+                dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+                subpass: Some(subpass.into()),
+                ..GraphicsPipelineCreateInfo::layout(layout)
+            },
+        )
+        .unwrap()
+    };
+
+    let vertex_buffer = create_buffer(
+        [
+            ColoredVertex {
+                position: [0.0, -0.6],
+                color: [1.0, 0.0, 0.0],
+            },
+            ColoredVertex {
+                position: [0.52, 0.3],
+                color: [0.0, 1.0, 0.0],
+            },
+            ColoredVertex {
+                position: [-0.52, 0.3],
+                color: [0.0, 0.0, 1.0],
+            },
+        ],
+        &memory_allocator,
+        BufferUsage::VERTEX_BUFFER,
+        MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+    );
+
+    // The zoom animation's path, slowed down and looped.
+    let keyframes = [
+        Keyframe {
+            time: 0.0,
+            center: [-0.5, 0.0],
+            zoom: 0.8,
+        },
+        Keyframe {
+            time: 2.0,
+            center: [-0.75, 0.1],
+            zoom: 4.0,
+        },
+        Keyframe {
+            time: 10.0,
+            center: [-0.743_643_9, 0.131_825_9],
+            zoom: 2000.0,
+        },
+    ];
+    let duration = keyframes.last().unwrap().time;
+
+    let queue = presenter.queue().clone();
+    let [width, height] = presenter.extent();
+    let mut mandelbrot = MandelbrotRenderer::with_queue(queue.clone(), width, height);
+
+    let start = Instant::now();
+    let mut frame_count = 0u32;
+
+    event_loop.run_return(|event, _, control_flow| {
+        control_flow.set_poll();
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => control_flow.set_exit(),
+            Event::WindowEvent {
+                event: WindowEvent::Resized(_),
+                ..
+            } => presenter.resized(),
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(key),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => match key {
+                VirtualKeyCode::Space if can_blit => scene = scene.toggled(),
+                VirtualKeyCode::Escape => control_flow.set_exit(),
+                _ => {}
+            },
+            Event::MainEventsCleared => presenter.window().request_redraw(),
+            Event::RedrawRequested(_) => {
+                let time = start.elapsed().as_secs_f32();
+                let presented = presenter.draw_frame(|builder, image_view| {
+                    let image = image_view.image();
+                    let [width, height, _] = image.extent();
+
+                    match scene {
+                        Scene::Triangle => {
+                            let framebuffer = Framebuffer::new(
+                                render_pass.clone(),
+                                FramebufferCreateInfo {
+                                    attachments: vec![image_view.clone()],
+                                    ..Default::default()
+                                },
+                            )
+                            .unwrap();
+                            let viewport = Viewport {
+                                offset: [0.0, 0.0],
+                                extent: [width as f32, height as f32],
+                                depth_range: 0.0..=1.0,
+                            };
+                            let push_constants = PushConstants {
+                                angle: time,
+                                aspect_ratio: width as f32 / height as f32,
+                            };
+
+                            builder
+                                .begin_render_pass(
+                                    RenderPassBeginInfo {
+                                        clear_values: vec![Some([0.1, 0.1, 0.1, 1.0].into())],
+                                        ..RenderPassBeginInfo::framebuffer(framebuffer)
+                                    },
+                                    SubpassBeginInfo {
+                                        contents: SubpassContents::Inline,
+                                        ..Default::default()
+                                    },
+                                )
+                                .unwrap()
+                                .bind_pipeline_graphics(pipeline.clone())
+                                .unwrap()
+                                .set_viewport(0, [viewport].into_iter().collect())
+                                .unwrap()
+                                .push_constants(pipeline.layout().clone(), 0, push_constants)
+                                .unwrap()
+                                .bind_vertex_buffers(0, vertex_buffer.clone())
+                                .unwrap()
+                                .draw(3, 1, 0, 0)
+                                .unwrap()
+                                .end_render_pass(SubpassEndInfo::default())
+                                .unwrap();
+                        }
+                        Scene::Mandelbrot => {
+                            // The compute shader writes rgba8, which swapchains
+                            // rarely support as a storage image. Render
+                            // offscreen at the window size and blit instead,
+                            // which also converts to the swapchain's format.
+                            mandelbrot.resize(width, height);
+
+                            let (center, zoom) = interpolate_keyframes(&keyframes, time % duration);
+                            mandelbrot.record(
+                                builder,
+                                &MandelbrotView {
+                                    center,
+                                    scale: 1.0 / zoom,
+                                    max_iterations: 200 + (100.0 * zoom.log10().max(0.0)) as u32,
+                                    ..Default::default()
+                                },
+                            );
+                            builder
+                                .blit_image(BlitImageInfo::images(
+                                    mandelbrot.image().clone(),
+                                    image.clone(),
+                                ))
+                                .unwrap();
+                        }
+                    }
+                });
+                if presented {
+                    frame_count += 1;
+                }
+            }
+            _ => {}
+        }
+    });
+
+    // Closing the window right away is fine, there is just nothing to report.
+    if frame_count == 0 {
+        println!("Live preview closed before the first frame");
+        return;
+    }
+    println!(
+        "Live preview successful! {frame_count} frames in {:.1}s",
+        start.elapsed().as_secs_f32()
+    );
+}
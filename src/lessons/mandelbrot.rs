@@ -0,0 +1,552 @@
+use std::sync::Arc;
+
+use image::{ImageBuffer, RgbaImage};
+use vulkano::{
+    buffer::{BufferContents, BufferUsage, Subbuffer},
+    command_buffer::{
+        allocator::{StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo},
+        AutoCommandBufferBuilder, CommandBufferUsage, CopyImageToBufferInfo,
+    },
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::{Device, DeviceOwned, Queue},
+    format::Format,
+    image::{view::ImageView, Image, ImageCreateInfo, ImageType, ImageUsage},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+    pipeline::{ComputePipeline, Pipeline, PipelineBindPoint},
+    sync::{self, GpuFuture},
+};
+
+use crate::{export, util};
+
+mod cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+        #version 460
+
+        layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+        layout(set = 0, binding = 0, rgba8) uniform writeonly image2D img;
+
+        layout(push_constant) uniform Params {
+            vec2 center;
+            float scale;
+            uint max_iterations;
+            uint sample_pattern;
+            uint sample_count;
+            float adaptive_threshold;
+        } params;
+
+        const uint PATTERN_GRID = 0;
+        const uint PATTERN_ROTATED_GRID = 1;
+        const uint PATTERN_JITTERED = 2;
+
+        // atan(1 / 2), the classic rotated grid angle.
+        const float ROTATION = 0.4636476;
+
+        uint hash(uint v) {
+            uint state = v * 747796405u + 2891336453u;
+            uint word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+            return (word >> 22u) ^ word;
+        }
+
+        float mandelbrot(vec2 pixel, vec2 size) {
+            vec2 norm_coordinates = pixel / size;
+            vec2 aspect = vec2(size.x / size.y, 1.0);
+            vec2 c = params.center + (norm_coordinates - vec2(0.5)) * 2.0 * params.scale * aspect;
+
+            vec2 z = vec2(0.0, 0.0);
+            uint i;
+            for (i = 0; i < params.max_iterations; i++) {
+                z = vec2(
+                    z.x * z.x - z.y * z.y + c.x,
+                    z.y * z.x + z.x * z.y + c.y
+                );
+
+                if (length(z) > 4.0) {
+                    break;
+                }
+            }
+
+            return float(i) / float(params.max_iterations);
+        }
+
+        // Offset of sample k inside the pixel, in [0, 1).
+        vec2 sample_offset(uint k, uint side, uint pixel_seed) {
+            if (params.sample_pattern == PATTERN_JITTERED) {
+                uint h = hash(pixel_seed ^ hash(k));
+                return vec2(float(h & 0xffffu), float(h >> 16u)) / 65536.0;
+            }
+
+            vec2 cell = (vec2(float(k % side), float(k / side)) + vec2(0.5)) / float(side);
+            if (params.sample_pattern == PATTERN_ROTATED_GRID) {
+                mat2 rotation = mat2(cos(ROTATION), sin(ROTATION), -sin(ROTATION), cos(ROTATION));
+                cell = fract(rotation * (cell - vec2(0.5)) + vec2(0.5));
+            }
+            return cell;
+        }
+
+        // Sum over the full pattern, which has `count` samples.
+        float supersample_sum(vec2 pixel, vec2 size, uint pixel_seed, out uint count) {
+            uint side = uint(ceil(sqrt(float(params.sample_count))));
+            count = params.sample_pattern == PATTERN_JITTERED ? params.sample_count : side * side;
+
+            float sum = 0.0;
+            for (uint k = 0; k < count; k++) {
+                sum += mandelbrot(pixel + sample_offset(k, side, pixel_seed), size);
+            }
+            return sum;
+        }
+
+        void main() {
+            ivec2 size = imageSize(img);
+            if (gl_GlobalInvocationID.x >= uint(size.x) || gl_GlobalInvocationID.y >= uint(size.y)) {
+                return;
+            }
+
+            vec2 pixel = vec2(gl_GlobalInvocationID.xy);
+            uint pixel_seed = hash(gl_GlobalInvocationID.y * uint(size.x) + gl_GlobalInvocationID.x);
+
+            float value;
+            uint count;
+            if (params.sample_count <= 1) {
+                value = mandelbrot(pixel + vec2(0.5), vec2(size));
+            } else if (params.adaptive_threshold > 0.0) {
+                // Take a cheap 4 sample estimate first and only pay for the
+                // full pattern where the samples disagree.
+                vec2 probes[4] = vec2[](
+                    vec2(0.375, 0.125), vec2(0.875, 0.375),
+                    vec2(0.625, 0.875), vec2(0.125, 0.625)
+                );
+                float sum = 0.0;
+                float sum_sq = 0.0;
+                for (uint k = 0; k < 4; k++) {
+                    float v = mandelbrot(pixel + probes[k], vec2(size));
+                    sum += v;
+                    sum_sq += v * v;
+                }
+                float mean = sum / 4.0;
+                float variance = sum_sq / 4.0 - mean * mean;
+
+                // Refined pixels keep the probes in their average.
+                if (variance > params.adaptive_threshold) {
+                    sum += supersample_sum(pixel, vec2(size), pixel_seed, count);
+                    value = sum / float(count + 4);
+                } else {
+                    value = mean;
+                }
+            } else {
+                value = supersample_sum(pixel, vec2(size), pixel_seed, count) / float(count);
+            }
+
+            imageStore(img, ivec2(gl_GlobalInvocationID.xy), vec4(vec3(value), 1.0));
+        }
+        ",
+    }
+}
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct Params {
+    center: [f32; 2],
+    scale: f32,
+    max_iterations: u32,
+    sample_pattern: u32,
+    sample_count: u32,
+    adaptive_threshold: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum SamplePattern {
+    Grid,
+    RotatedGrid,
+    Jittered,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Supersampling {
+    pub pattern: SamplePattern,
+    // Grid patterns round this up to the next square number.
+    pub samples: u32,
+    // When set, pixels whose 4 sample variance is below the threshold skip
+    // the full pattern.
+    pub adaptive_threshold: Option<f32>,
+}
+
+impl Default for Supersampling {
+    fn default() -> Self {
+        Supersampling {
+            pattern: SamplePattern::Grid,
+            samples: 1,
+            adaptive_threshold: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MandelbrotView {
+    pub center: [f64; 2],
+    // Half of the visible height in the complex plane.
+    pub scale: f64,
+    pub max_iterations: u32,
+    pub supersampling: Supersampling,
+}
+
+impl Default for MandelbrotView {
+    fn default() -> Self {
+        MandelbrotView {
+            center: [-1.0, 0.0],
+            scale: 1.0,
+            max_iterations: 200,
+            supersampling: Supersampling::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe {
+    pub time: f32,
+    pub center: [f64; 2],
+    pub zoom: f64,
+}
+
+// Zoom is interpolated in log space so the animation zooms at a constant
+// rate. The center follows the zoom progress rather than time, which keeps a
+// point that stays fixed between two keyframes fixed on screen as well.
+pub fn interpolate_keyframes(keyframes: &[Keyframe], time: f32) -> ([f64; 2], f64) {
+    let first = keyframes.first().expect("no keyframes");
+    let last = keyframes.last().unwrap();
+    if time <= first.time {
+        return (first.center, first.zoom);
+    }
+    if time >= last.time {
+        return (last.center, last.zoom);
+    }
+
+    let segment = keyframes
+        .windows(2)
+        .find(|pair| time <= pair[1].time)
+        .unwrap();
+    let (a, b) = (&segment[0], &segment[1]);
+    let t = ((time - a.time) / (b.time - a.time)) as f64;
+
+    let zoom = (a.zoom.ln() + (b.zoom.ln() - a.zoom.ln()) * t).exp();
+    let w = if a.zoom == b.zoom {
+        t
+    } else {
+        (1.0 / a.zoom - 1.0 / zoom) / (1.0 / a.zoom - 1.0 / b.zoom)
+    };
+    let center = [
+        a.center[0] + (b.center[0] - a.center[0]) * w,
+        a.center[1] + (b.center[1] - a.center[1]) * w,
+    ];
+    (center, zoom)
+}
+
+pub struct MandelbrotRenderer {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    descriptor_set_allocator: StandardDescriptorSetAllocator,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+    pipeline: Arc<ComputePipeline>,
+    target: Target,
+}
+
+// Everything that depends on the output size.
+struct Target {
+    image: Arc<Image>,
+    descriptor_set: Arc<PersistentDescriptorSet>,
+    buf: Subbuffer<[u8]>,
+    width: u32,
+    height: u32,
+}
+
+impl MandelbrotRenderer {
+    pub fn new(width: u32, height: u32) -> Self {
+        let mut vk_device = util::create_device();
+        Self::with_queue(vk_device.queues.next().unwrap(), width, height)
+    }
+
+    // Renders on an existing queue, e.g. one that can also present.
+    pub fn with_queue(queue: Arc<Queue>, width: u32, height: u32) -> Self {
+        let device = queue.device().clone();
+        let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+
+        let shader = cs::load(device.clone()).expect("failed to create shader module");
+        let pipeline = util::create_compute_pipeline(device.clone(), shader);
+
+        let descriptor_set_allocator =
+            StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+
+        let command_buffer_allocator = StandardCommandBufferAllocator::new(
+            device.clone(),
+            StandardCommandBufferAllocatorCreateInfo::default(),
+        );
+
+        let target = Target::new(
+            &memory_allocator,
+            &descriptor_set_allocator,
+            &pipeline,
+            width,
+            height,
+        );
+
+        MandelbrotRenderer {
+            device,
+            queue,
+            memory_allocator,
+            descriptor_set_allocator,
+            command_buffer_allocator,
+            pipeline,
+            target,
+        }
+    }
+
+    // Only the image, readback buffer and descriptor set are recreated, the
+    // pipeline and allocators stay.
+    #[cfg_attr(not(feature = "window"), allow(dead_code))]
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if [width, height] == [self.target.width, self.target.height] {
+            return;
+        }
+        self.target = Target::new(
+            &self.memory_allocator,
+            &self.descriptor_set_allocator,
+            &self.pipeline,
+            width,
+            height,
+        );
+    }
+
+    // Records the dispatch that fills `image()` without submitting it.
+    pub fn record<L>(&self, builder: &mut AutoCommandBufferBuilder<L>, view: &MandelbrotView) {
+        let params = Params {
+            center: [view.center[0] as f32, view.center[1] as f32],
+            scale: view.scale as f32,
+            max_iterations: view.max_iterations,
+            sample_pattern: view.supersampling.pattern as u32,
+            sample_count: view.supersampling.samples,
+            adaptive_threshold: view.supersampling.adaptive_threshold.unwrap_or(0.0),
+        };
+
+        builder
+            .bind_pipeline_compute(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.pipeline.layout().clone(),
+                0,
+                self.target.descriptor_set.clone(),
+            )
+            .unwrap()
+            .push_constants(self.pipeline.layout().clone(), 0, params)
+            .unwrap()
+            .dispatch([
+                self.target.width.div_ceil(8),
+                self.target.height.div_ceil(8),
+                1,
+            ])
+            .unwrap();
+    }
+
+    pub fn render(&self, view: &MandelbrotView) -> RgbaImage {
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        self.record(&mut builder, view);
+        builder
+            .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+                self.target.image.clone(),
+                self.target.buf.clone(),
+            ))
+            .unwrap();
+
+        let command_buffer = builder.build().unwrap();
+        let future = sync::now(self.device.clone())
+            .then_execute(self.queue.clone(), command_buffer)
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap();
+
+        future.wait(None).unwrap();
+
+        let buffer_content = self.target.buf.read().unwrap();
+        ImageBuffer::from_raw(
+            self.target.width,
+            self.target.height,
+            buffer_content.to_vec(),
+        )
+        .unwrap()
+    }
+
+    // The storage image `record` writes to, e.g. for presenting it directly.
+    pub fn image(&self) -> &Arc<Image> {
+        &self.target.image
+    }
+}
+
+impl Target {
+    fn new(
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        descriptor_set_allocator: &StandardDescriptorSetAllocator,
+        pipeline: &Arc<ComputePipeline>,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let image = Image::new(
+            memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: Format::R8G8B8A8_UNORM,
+                extent: [width, height, 1],
+                usage: ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let view = ImageView::new_default(image.clone()).unwrap();
+
+        let buf = util::create_buffer(
+            (0..width * height * 4).map(|_| 0u8),
+            memory_allocator,
+            BufferUsage::TRANSFER_DST,
+            MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+        );
+
+        let layout = pipeline.layout().set_layouts().first().unwrap();
+        let descriptor_set = PersistentDescriptorSet::new(
+            descriptor_set_allocator,
+            layout.clone(),
+            [WriteDescriptorSet::image_view(0, view)], // 0 is the binding
+            [],
+        )
+        .unwrap();
+
+        Target {
+            image,
+            descriptor_set,
+            buf,
+            width,
+            height,
+        }
+    }
+}
+
+pub fn mandelbrot_set() {
+    let renderer = MandelbrotRenderer::new(1024, 1024);
+    let image = renderer.render(&MandelbrotView::default());
+    image.save("mandelbrot.png").unwrap();
+
+    println!("Mandelbrot set creation successful!");
+}
+
+pub fn mandelbrot_supersampled() {
+    let renderer = MandelbrotRenderer::new(512, 512);
+    let view = MandelbrotView {
+        center: [-0.745, 0.113],
+        scale: 0.02,
+        max_iterations: 500,
+        ..Default::default()
+    };
+
+    let variants = [
+        ("mandelbrot_aliased.png", Supersampling::default()),
+        (
+            "mandelbrot_grid.png",
+            Supersampling {
+                pattern: SamplePattern::Grid,
+                samples: 16,
+                adaptive_threshold: None,
+            },
+        ),
+        (
+            "mandelbrot_rotated_grid.png",
+            Supersampling {
+                pattern: SamplePattern::RotatedGrid,
+                samples: 16,
+                adaptive_threshold: None,
+            },
+        ),
+        (
+            "mandelbrot_jittered.png",
+            Supersampling {
+                pattern: SamplePattern::Jittered,
+                samples: 16,
+                adaptive_threshold: None,
+            },
+        ),
+        (
+            "mandelbrot_adaptive.png",
+            Supersampling {
+                pattern: SamplePattern::RotatedGrid,
+                samples: 16,
+                adaptive_threshold: Some(0.001),
+            },
+        ),
+    ];
+
+    for (path, supersampling) in variants {
+        let image = renderer.render(&MandelbrotView {
+            supersampling,
+            ..view
+        });
+        image.save(path).unwrap();
+    }
+
+    println!("Supersampled Mandelbrot set successful!");
+}
+
+pub fn mandelbrot_zoom() {
+    let fps = 20;
+    let duration = 4.0;
+    let frame_count = (duration * fps as f32) as u32;
+
+    let keyframes = [
+        Keyframe {
+            time: 0.0,
+            center: [-0.5, 0.0],
+            zoom: 0.8,
+        },
+        Keyframe {
+            time: 1.0,
+            center: [-0.75, 0.1],
+            zoom: 4.0,
+        },
+        Keyframe {
+            time: duration,
+            center: [-0.743_643_9, 0.131_825_9],
+            zoom: 2000.0,
+        },
+    ];
+
+    let renderer = MandelbrotRenderer::new(256, 256);
+    let frames: Vec<RgbaImage> = (0..frame_count)
+        .map(|n| {
+            let (center, zoom) = interpolate_keyframes(&keyframes, n as f32 / fps as f32);
+            renderer.render(&MandelbrotView {
+                center,
+                scale: 1.0 / zoom,
+                // Deeper zooms need more iterations to resolve the boundary.
+                max_iterations: 200 + (100.0 * zoom.log10().max(0.0)) as u32,
+                ..Default::default()
+            })
+        })
+        .collect();
+
+    export::save_gif(&frames, fps, "mandelbrot_zoom.gif");
+    export::save_png_sequence(&frames, "mandelbrot_zoom");
+    export::save_y4m(&frames, fps, "mandelbrot_zoom.y4m");
+
+    println!("Mandelbrot zoom animation successful!");
+}
@@ -0,0 +1,118 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use vulkano::buffer::{BufferUsage, Subbuffer};
+use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
+
+use crate::gemm::{Gemm, GemmKernel};
+use crate::util;
+
+const BENCHMARK_RUNS: u32 = 5;
+
+fn random_matrix(
+    rows: u32,
+    cols: u32,
+    seed: u32,
+    allocator: &Arc<StandardMemoryAllocator>,
+) -> (Vec<f32>, Subbuffer<[f32]>) {
+    let data: Vec<f32> = util::random_words((rows * cols) as usize, seed)
+        .map(|w| w as f32 / u32::MAX as f32 * 2.0 - 1.0)
+        .collect();
+    let buffer = util::create_buffer(
+        data.iter().copied(),
+        allocator,
+        BufferUsage::STORAGE_BUFFER,
+        MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+    );
+    (data, buffer)
+}
+
+// Element (row, col) of A * B in double precision, together with the sum of
+// the absolute products to scale the tolerance by.
+fn reference(a: &[f32], b: &[f32], k: u32, n: u32, row: u32, col: u32) -> (f64, f64) {
+    (0..k).fold((0.0, 0.0), |(sum, magnitude), i| {
+        let p = a[(row * k + i) as usize] as f64 * b[(i * n + col) as usize] as f64;
+        (sum + p, magnitude + p.abs())
+    })
+}
+
+fn check(c: &[f32], a: &[f32], b: &[f32], k: u32, n: u32, row: u32, col: u32) {
+    let (expected, magnitude) = reference(a, b, k, n, row, col);
+    let got = c[(row * n + col) as usize] as f64;
+    assert!(
+        (got - expected).abs() <= 1e-5 * magnitude.max(1.0),
+        "C[{row}][{col}] is {got}, expected {expected}"
+    );
+}
+
+pub fn matrix_multiply() {
+    let mut vk_device = util::create_device();
+    let device = vk_device.device.clone();
+    let queue = vk_device.queues.next().unwrap();
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+    let gemm = Gemm::new(queue, memory_allocator.clone());
+
+    // (m, k, n) around and between the 16 and 64 wide tiles.
+    let shapes = [
+        (1, 1, 1),
+        (3, 5, 7),
+        (16, 16, 16),
+        (17, 33, 9),
+        (64, 64, 64),
+        (65, 129, 63),
+        (100, 1, 250),
+        (255, 300, 130),
+    ];
+
+    for (i, &(m, k, n)) in shapes.iter().enumerate() {
+        let (a_data, a) = random_matrix(m, k, 0x1b87_3593 ^ i as u32, &memory_allocator);
+        let (b_data, b) = random_matrix(k, n, 0xcc9e_2d51 ^ i as u32, &memory_allocator);
+
+        for kernel in GemmKernel::ALL {
+            let c = gemm.multiply(kernel, &a, &b, m, k, n);
+            let c = c.read().unwrap();
+            for row in 0..m {
+                for col in 0..n {
+                    check(&c, &a_data, &b_data, k, n, row, col);
+                }
+            }
+        }
+    }
+
+    // The full CPU product is too slow at benchmark sizes, so only a sample
+    // of the elements is checked there.
+    let (m, k, n) = (1024, 1024, 1024);
+    let (a_data, a) = random_matrix(m, k, 5, &memory_allocator);
+    let (b_data, b) = random_matrix(k, n, 6, &memory_allocator);
+    let samples: Vec<(u32, u32)> = util::random_words(64, 7)
+        .zip(util::random_words(64, 8))
+        .map(|(r, c)| (r % m, c % n))
+        .collect();
+
+    for kernel in GemmKernel::ALL {
+        // Recorded up front, so the timed runs only cover submitting the
+        // dispatch and waiting on its fence, no allocations or descriptor
+        // sets. The first run warms up caches and clocks.
+        let (command_buffer, c) = gemm.prepare(kernel, &a, &b, m, k, n);
+        gemm.run(&command_buffer);
+
+        let start = Instant::now();
+        for _ in 0..BENCHMARK_RUNS {
+            gemm.run(&command_buffer);
+        }
+        let elapsed = start.elapsed() / BENCHMARK_RUNS;
+
+        let c = c.read().unwrap();
+        for &(row, col) in &samples {
+            check(&c, &a_data, &b_data, k, n, row, col);
+        }
+
+        let gflops = 2.0 * m as f64 * k as f64 * n as f64 / elapsed.as_secs_f64() / 1e9;
+        println!(
+            "{kernel:?} {m}x{k}x{n}: {elapsed:?}, {gflops:.1} GFLOP/s (dispatch and fence wait, \
+             average of {BENCHMARK_RUNS})"
+        );
+    }
+
+    println!("Matrix multiplication successful!");
+}
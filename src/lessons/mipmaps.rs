@@ -0,0 +1,274 @@
+use std::sync::Arc;
+
+use image::{Rgba, RgbaImage};
+use vulkano::command_buffer::allocator::{
+    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::device::Queue;
+use vulkano::format::Format;
+use vulkano::image::sampler::{
+    Filter, Sampler, SamplerAddressMode, SamplerCreateInfo, SamplerMipmapMode,
+};
+use vulkano::image::view::ImageView;
+use vulkano::image::ImageUsage;
+use vulkano::memory::allocator::StandardMemoryAllocator;
+use vulkano::pipeline::{Pipeline, PipelineBindPoint};
+use vulkano::sync::{self, GpuFuture};
+
+use crate::texture::{self, ColorSpace, MipmapMethod};
+use crate::util;
+
+// Draws the whole texture into the storage image, so it is minified by the
+// ratio of their sizes. The gradients passed to textureGrad are the ones a
+// fragment shader would get, the sampler picks the mip level from them.
+mod minify {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0) uniform sampler2D tex;
+            layout(set = 0, binding = 1, rgba8) uniform writeonly image2D img;
+
+            vec3 to_srgb(vec3 c) {
+                return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+            }
+
+            void main() {
+                ivec2 p = ivec2(gl_GlobalInvocationID.xy);
+                ivec2 size = imageSize(img);
+                if (any(greaterThanEqual(p, size))) {
+                    return;
+                }
+
+                // Shifted onto a texel center of level 0, where sampling
+                // level 0 alone returns a single stripe instead of their
+                // average.
+                vec2 texel = 1.0 / vec2(size);
+                vec2 uv = (vec2(p) + 0.5) * texel + 0.5 / vec2(textureSize(tex, 0));
+                vec4 c = textureGrad(tex, uv, vec2(texel.x, 0.0), vec2(0.0, texel.y));
+                imageStore(img, p, vec4(to_srgb(c.rgb), c.a));
+            }
+        ",
+    }
+}
+
+// Fine stripes over a color gradient: the stripes should fade to a flat gray
+// in the small levels while the gradient survives.
+fn source_image(size: u32) -> RgbaImage {
+    RgbaImage::from_fn(size, size, |x, y| {
+        let stripe = if (x / 2 + y / 2) % 2 == 0 { 1.0 } else { 0.5 };
+        let r = x as f32 / size as f32;
+        let b = y as f32 / size as f32;
+        Rgba([
+            (255.0 * r * stripe) as u8,
+            (255.0 * 0.5 * stripe) as u8,
+            (255.0 * b * stripe) as u8,
+            255,
+        ])
+    })
+}
+
+fn mean_difference(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    let total: u64 = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(&a, &b)| a.abs_diff(b) as u64)
+        .sum();
+    total as f64 / a.as_raw().len() as f64
+}
+
+fn sample_minified(
+    texture: &Arc<ImageView>,
+    sampler: &Arc<Sampler>,
+    size: u32,
+    memory_allocator: &Arc<StandardMemoryAllocator>,
+    command_buffer_allocator: &StandardCommandBufferAllocator,
+    queue: &Arc<Queue>,
+) -> RgbaImage {
+    let device = queue.device().clone();
+    let shader = minify::load(device.clone()).expect("failed to create shader module");
+    let pipeline = util::create_compute_pipeline(device.clone(), shader);
+    let descriptor_set_allocator = StandardDescriptorSetAllocator::new(device, Default::default());
+
+    let output = util::create_render_target(
+        memory_allocator,
+        Format::R8G8B8A8_UNORM,
+        [size, size],
+        ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC,
+    );
+    let set = PersistentDescriptorSet::new(
+        &descriptor_set_allocator,
+        pipeline.layout().set_layouts()[0].clone(),
+        [
+            WriteDescriptorSet::image_view_sampler(0, texture.clone(), sampler.clone()),
+            WriteDescriptorSet::image_view(1, ImageView::new_default(output.clone()).unwrap()),
+        ],
+        [],
+    )
+    .unwrap();
+
+    let mut builder = util::begin_one_time(command_buffer_allocator, queue);
+    builder
+        .bind_pipeline_compute(pipeline.clone())
+        .unwrap()
+        .bind_descriptor_sets(
+            PipelineBindPoint::Compute,
+            pipeline.layout().clone(),
+            0,
+            set,
+        )
+        .unwrap()
+        .dispatch([size.div_ceil(8), size.div_ceil(8), 1])
+        .unwrap();
+    util::submit_and_wait(builder, queue);
+
+    texture::read_mip_level(
+        &output,
+        0,
+        memory_allocator,
+        command_buffer_allocator,
+        queue,
+    )
+}
+
+pub fn mipmaps() {
+    let mut vk_device = util::create_device();
+    let queue = vk_device.queues.next().unwrap();
+    let device = vk_device.device.clone();
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+    let command_buffer_allocator = StandardCommandBufferAllocator::new(
+        device.clone(),
+        StandardCommandBufferAllocatorCreateInfo::default(),
+    );
+
+    let pixels = source_image(512);
+    let format = ColorSpace::Srgb.rgba8_format();
+    let method = MipmapMethod::for_format(device.physical_device(), format)
+        .expect("RGBA8 always has a compute fallback");
+    println!("Mipmaps for {format:?} are generated with {method:?}");
+
+    let mut builder = AutoCommandBufferBuilder::primary(
+        &command_buffer_allocator,
+        queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+
+    let texture =
+        texture::upload_rgba8_mipmapped(&pixels, format, method, &memory_allocator, &mut builder);
+    // Always build the compute version too, so both paths get exercised.
+    let fallback = texture::upload_rgba8_mipmapped(
+        &pixels,
+        format,
+        MipmapMethod::Compute,
+        &memory_allocator,
+        &mut builder,
+    );
+
+    let command_buffer = builder.build().unwrap();
+    let future = sync::now(device.clone())
+        .then_execute(queue.clone(), command_buffer)
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap();
+    future.wait(None).unwrap();
+
+    let image = texture.image();
+    assert_eq!(image.mip_levels(), 10);
+
+    for level in 0..image.mip_levels() {
+        let mip = texture::read_mip_level(
+            image,
+            level,
+            &memory_allocator,
+            &command_buffer_allocator,
+            &queue,
+        );
+        mip.save(format!("mipmap_level_{level}.png")).unwrap();
+
+        // Blits and the compute shader both average 2x2 blocks in linear
+        // space, they should only disagree by rounding.
+        let fallback_mip = texture::read_mip_level(
+            fallback.image(),
+            level,
+            &memory_allocator,
+            &command_buffer_allocator,
+            &queue,
+        );
+        assert_eq!(mip.dimensions(), fallback_mip.dimensions());
+        let difference = mean_difference(&mip, &fallback_mip);
+        assert!(
+            difference < 1.0,
+            "level {level} differs by {difference} on average"
+        );
+    }
+
+    // Drawn at 16x16 the 512x512 texture is minified by 32, so trilinear
+    // filtering lands exactly on level 5.
+    let sampler = texture::create_sampler(
+        device.clone(),
+        Filter::Linear,
+        SamplerAddressMode::ClampToEdge,
+    );
+    let minified = sample_minified(
+        &texture,
+        &sampler,
+        16,
+        &memory_allocator,
+        &command_buffer_allocator,
+        &queue,
+    );
+    minified.save("mipmap_minified.png").unwrap();
+    let level_5 = texture::read_mip_level(
+        image,
+        5,
+        &memory_allocator,
+        &command_buffer_allocator,
+        &queue,
+    );
+    let difference = mean_difference(&minified, &level_5);
+    assert!(
+        difference < 1.5,
+        "minified sampling differs from level 5 by {difference} on average"
+    );
+
+    // The same with the mip chain clamped away picks single stripes.
+    let level_0_only = Sampler::new(
+        device.clone(),
+        SamplerCreateInfo {
+            mag_filter: Filter::Linear,
+            min_filter: Filter::Linear,
+            mipmap_mode: SamplerMipmapMode::Linear,
+            address_mode: [SamplerAddressMode::ClampToEdge; 3],
+            lod: 0.0..=0.0,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let aliased = sample_minified(
+        &texture,
+        &level_0_only,
+        16,
+        &memory_allocator,
+        &command_buffer_allocator,
+        &queue,
+    );
+    let aliased_difference = mean_difference(&aliased, &level_5);
+    println!(
+        "Minified texture differs from level 5 by {difference:.2}, without mips by \
+         {aliased_difference:.2}"
+    );
+    assert!(
+        aliased_difference > 4.0 * difference.max(1.0),
+        "sampling with and without mips gives the same result"
+    );
+
+    println!("Mipmap generation successful!");
+}
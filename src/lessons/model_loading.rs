@@ -0,0 +1,383 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3};
+use image::{ImageBuffer, Rgba, RgbaImage};
+use vulkano::buffer::{BufferContents, BufferUsage};
+use vulkano::command_buffer::allocator::{
+    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CommandBufferUsage, CopyImageToBufferInfo, RenderPassBeginInfo,
+    SubpassBeginInfo, SubpassContents, SubpassEndInfo,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::format::Format;
+use vulkano::image::sampler::{Filter, SamplerAddressMode};
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::color_blend::{ColorBlendAttachmentState, ColorBlendState};
+use vulkano::pipeline::graphics::depth_stencil::{DepthState, DepthStencilState};
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::RasterizationState;
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{
+    GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo,
+};
+use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, Subpass};
+use vulkano::sync::{self, GpuFuture};
+
+use crate::mesh::{Mesh, MeshVertex};
+use crate::model::{Material, Model};
+use crate::texture::{self, ColorSpace};
+use crate::util;
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct PushConstants {
+    model: [[f32; 4]; 4],
+    base_color: [f32; 4],
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec3 position;
+            layout(location = 1) in vec3 normal;
+            layout(location = 2) in vec2 uv;
+
+            layout(location = 0) out vec3 v_normal;
+            layout(location = 1) out vec2 v_uv;
+
+            layout(push_constant) uniform PushConstants {
+                mat4 model;
+                vec4 base_color;
+            } pc;
+
+            void main() {
+                vec4 p = pc.model * vec4(position, 1.0);
+
+                // Good enough for rotations and uniform scales.
+                v_normal = mat3(pc.model) * normal;
+                v_uv = uv;
+
+                // Flip y and map z from [-1, 1] (towards the viewer) to a
+                // [0, 1] depth.
+                gl_Position = vec4(p.x, -p.y, 0.5 - p.z * 0.4, 1.0);
+            }
+        ",
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec3 v_normal;
+            layout(location = 1) in vec2 v_uv;
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 0, binding = 0) uniform sampler2D base_color_texture;
+
+            layout(push_constant) uniform PushConstants {
+                mat4 model;
+                vec4 base_color;
+            } pc;
+
+            void main() {
+                vec4 base = texture(base_color_texture, v_uv) * pc.base_color;
+
+                vec3 light = normalize(vec3(0.4, 0.8, 0.6));
+                float diffuse = max(dot(normalize(v_normal), light), 0.0);
+                f_color = vec4(base.rgb * (0.25 + 0.75 * diffuse), base.a);
+            }
+        ",
+    }
+}
+
+pub fn model_loading(path: impl AsRef<Path>) {
+    let path = path.as_ref();
+    let model = Model::load(path);
+
+    let mut vk_device = util::create_device();
+    let queue = vk_device.queues.next().unwrap();
+    let device = vk_device.device.clone();
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+
+    // Base color textures are authored in sRGB, so render into an sRGB image
+    // as well to get the same colors back out.
+    let image = Image::new(
+        memory_allocator.clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: Format::R8G8B8A8_SRGB,
+            extent: [1024, 1024, 1],
+            usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let depth_format = util::find_depth_format(device.physical_device());
+    let depth_image = util::create_depth_image(&memory_allocator, depth_format, [1024, 1024]);
+
+    let buf = util::create_buffer(
+        (0..1024 * 1024 * 4).map(|_| 0u8),
+        &memory_allocator,
+        BufferUsage::TRANSFER_DST,
+        MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+    );
+
+    let render_pass = vulkano::single_pass_renderpass!(
+        device.clone(),
+        attachments: {
+            color: {
+                format: Format::R8G8B8A8_SRGB,
+                samples: 1,
+                load_op: Clear,
+                store_op: Store,
+            },
+            depth: {
+                format: depth_format,
+                samples: 1,
+                load_op: Clear,
+                store_op: DontCare,
+            },
+        },
+        pass: {
+            color: [color],
+            depth_stencil: {depth},
+        },
+    )
+    .unwrap();
+
+    let view = ImageView::new_default(image.clone()).unwrap();
+    let depth_view = ImageView::new_default(depth_image).unwrap();
+    let framebuffer = Framebuffer::new(
+        render_pass.clone(),
+        FramebufferCreateInfo {
+            attachments: vec![view, depth_view],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let vs = vs::load(device.clone()).expect("failed to create shader module");
+    let fs = fs::load(device.clone()).expect("failed to create shader module");
+
+    let viewport = Viewport {
+        offset: [0.0, 0.0],
+        extent: [1024.0, 1024.0],
+        depth_range: 0.0..=1.0,
+    };
+    let pipeline = {
+        let vs = vs.entry_point("main").unwrap();
+        let fs = fs.entry_point("main").unwrap();
+
+        let vertex_input_state = MeshVertex::per_vertex()
+            .definition(&vs.info().input_interface)
+            .unwrap();
+
+        let stages = [
+            PipelineShaderStageCreateInfo::new(vs),
+            PipelineShaderStageCreateInfo::new(fs),
+        ];
+
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+                .into_pipeline_layout_create_info(device.clone())
+                .unwrap(),
+        )
+        .unwrap();
+
+        let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+
+        GraphicsPipeline::new(
+            device.clone(),
+            None,
+            GraphicsPipelineCreateInfo {
+                stages: stages.into_iter().collect(),
+                vertex_input_state: Some(vertex_input_state),
+                input_assembly_state: Some(InputAssemblyState::default()),
+                viewport_state: Some(ViewportState {
+                    viewports: [viewport].into_iter().collect(),
+                    ..Default::default()
+                }),
+                rasterization_state: Some(RasterizationState::default()),
+                multisample_state: Some(MultisampleState::default()),
+                depth_stencil_state: Some(DepthStencilState {
+                    depth: Some(DepthState::simple()),
+                    ..Default::default()
+                }),
+                color_blend_state: Some(ColorBlendState::with_attachment_states(
+                    subpass.num_color_attachments(),
+                    ColorBlendAttachmentState::default(),
+                )),
+                subpass: Some(subpass.into()),
+                ..GraphicsPipelineCreateInfo::layout(layout)
+            },
+        )
+        .unwrap()
+    };
+
+    let command_buffer_allocator = StandardCommandBufferAllocator::new(
+        device.clone(),
+        StandardCommandBufferAllocatorCreateInfo::default(),
+    );
+
+    let mut builder = AutoCommandBufferBuilder::primary(
+        &command_buffer_allocator,
+        queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+
+    // The uploads are recorded into the same command buffer as the draw, the
+    // builder inserts the barriers between the copies and the render pass.
+    let textures: Vec<_> = model
+        .textures
+        .iter()
+        .map(|pixels| {
+            texture::upload_rgba8(
+                pixels,
+                ColorSpace::Srgb.rgba8_format(),
+                &memory_allocator,
+                &mut builder,
+            )
+        })
+        .collect();
+    // Untextured materials sample a single white texel instead of needing a
+    // second pipeline.
+    let white = texture::upload_rgba8(
+        &RgbaImage::from_pixel(1, 1, Rgba([255; 4])),
+        ColorSpace::Srgb.rgba8_format(),
+        &memory_allocator,
+        &mut builder,
+    );
+
+    let sampler =
+        texture::create_sampler(device.clone(), Filter::Linear, SamplerAddressMode::Repeat);
+    let descriptor_set_allocator =
+        StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+    let set_layout = pipeline.layout().set_layouts().first().unwrap();
+
+    // One descriptor set per material, with the default material last.
+    let default_material = Material::default();
+    let materials: Vec<_> = model
+        .materials
+        .iter()
+        .chain([&default_material])
+        .map(|material| {
+            let view = material
+                .base_color_texture
+                .map_or(white.clone(), |index| textures[index].clone());
+            let set = PersistentDescriptorSet::new(
+                &descriptor_set_allocator,
+                set_layout.clone(),
+                [WriteDescriptorSet::image_view_sampler(
+                    0,
+                    view,
+                    sampler.clone(),
+                )],
+                [],
+            )
+            .unwrap();
+            (set, material.base_color)
+        })
+        .collect();
+
+    // Center the model, scale it to fit the image and tilt it towards the
+    // viewer a little.
+    let (min, max) = model.bounds();
+    let extent = (max - min).max_element();
+    let fit = Mat4::from_rotation_x(0.45)
+        * Mat4::from_rotation_y(0.6)
+        * Mat4::from_scale(Vec3::splat(1.2 / extent))
+        * Mat4::from_translation(-(min + max) * 0.5);
+
+    let meshes: Vec<_> = model
+        .primitives
+        .iter()
+        .map(|primitive| {
+            let mesh = Mesh::new(primitive.mesh.clone(), &memory_allocator);
+            let material = primitive.material.unwrap_or(model.materials.len());
+            (mesh, fit * primitive.transform, material)
+        })
+        .collect();
+
+    builder
+        .begin_render_pass(
+            RenderPassBeginInfo {
+                clear_values: vec![Some([0.1, 0.1, 0.1, 1.0].into()), Some(1.0.into())],
+                ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
+            },
+            SubpassBeginInfo {
+                contents: SubpassContents::Inline,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .bind_pipeline_graphics(pipeline.clone())
+        .unwrap();
+
+    for (mesh, transform, material) in &meshes {
+        let (set, base_color) = &materials[*material];
+        builder
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                pipeline.layout().clone(),
+                0,
+                set.clone(),
+            )
+            .unwrap()
+            .push_constants(
+                pipeline.layout().clone(),
+                0,
+                PushConstants {
+                    model: transform.to_cols_array_2d(),
+                    base_color: *base_color,
+                },
+            )
+            .unwrap();
+        mesh.draw(&mut builder);
+    }
+
+    builder
+        .end_render_pass(SubpassEndInfo::default())
+        .unwrap()
+        .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(image, buf.clone()))
+        .unwrap();
+
+    let command_buffer = builder.build().unwrap();
+
+    let future = sync::now(device.clone())
+        .then_execute(queue.clone(), command_buffer)
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap();
+    future.wait(None).unwrap();
+
+    let buffer_content = buf.read().unwrap();
+    let image = ImageBuffer::<Rgba<u8>, _>::from_raw(1024, 1024, &buffer_content[..]).unwrap();
+    let name = path.file_stem().unwrap().to_string_lossy();
+    image.save(format!("model_{name}.png")).unwrap();
+
+    println!("Model loading successful!");
+}
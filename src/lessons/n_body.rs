@@ -0,0 +1,131 @@
+use std::f32::consts::{FRAC_PI_4, TAU};
+use std::sync::Arc;
+
+use glam::Vec3;
+use vulkano::memory::allocator::StandardMemoryAllocator;
+
+use crate::camera::Camera;
+use crate::export;
+use crate::particles::{Gravity, NBody, Particle, ParticleRenderer};
+use crate::util;
+
+fn uniform(words: &mut impl Iterator<Item = u32>) -> f32 {
+    words.next().unwrap() as f32 / u32::MAX as f32
+}
+
+// A heavy body in the middle with a light disk on circular orbits around it.
+fn disk(count: usize, gravity: Gravity, seed: u32) -> Vec<Particle> {
+    let central_mass = 1.0;
+    let disk_mass = 0.2 / count as f32;
+    let mut words = util::random_words(count * 3, seed);
+
+    let mut particles = vec![Particle {
+        position: [0.0, 0.0, 0.0, central_mass],
+        velocity: [0.0; 4],
+    }];
+    for _ in 1..count {
+        let r = 0.3 + 0.7 * uniform(&mut words);
+        let angle = TAU * uniform(&mut words);
+        let height = 0.02 * (uniform(&mut words) - 0.5);
+        // Only roughly circular, the disk's own mass is left out.
+        let speed = (gravity.g * central_mass / r).sqrt();
+        particles.push(Particle {
+            position: [r * angle.cos(), height, r * angle.sin(), disk_mass],
+            velocity: [-speed * angle.sin(), 0.0, speed * angle.cos(), 0.0],
+        });
+    }
+    particles
+}
+
+// One semi-implicit Euler step in double precision.
+fn cpu_step(particles: &[Particle], gravity: Gravity, dt: f32) -> Vec<Particle> {
+    let softening2 = gravity.softening as f64 * gravity.softening as f64;
+    particles
+        .iter()
+        .map(|p| {
+            let mut acceleration = [0.0f64; 3];
+            for q in particles {
+                let d: [f64; 3] = std::array::from_fn(|k| (q.position[k] - p.position[k]) as f64);
+                let r2 = d[0] * d[0] + d[1] * d[1] + d[2] * d[2] + softening2;
+                for k in 0..3 {
+                    acceleration[k] += q.position[3] as f64 * d[k] / (r2 * r2 * r2).sqrt();
+                }
+            }
+            let v: [f64; 3] = std::array::from_fn(|k| {
+                p.velocity[k] as f64 + gravity.g as f64 * acceleration[k] * dt as f64
+            });
+            Particle {
+                position: std::array::from_fn(|k| {
+                    if k < 3 {
+                        (p.position[k] as f64 + v[k] * dt as f64) as f32
+                    } else {
+                        p.position[3]
+                    }
+                }),
+                velocity: [v[0] as f32, v[1] as f32, v[2] as f32, 0.0],
+            }
+        })
+        .collect()
+}
+
+pub fn n_body() {
+    let mut vk_device = util::create_device();
+    let device = vk_device.device.clone();
+    let queue = vk_device.queues.next().unwrap();
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+
+    let gravity = Gravity {
+        g: 1.0,
+        softening: 0.02,
+    };
+    let dt = 0.001;
+
+    // A single step against the CPU, with a count that leaves the last tile
+    // partially filled.
+    let particles = disk(300, gravity, 0x1656_67b1);
+    let mut simulation = NBody::new(queue.clone(), &memory_allocator, &particles, gravity);
+    simulation.step(1, dt);
+    let expected = cpu_step(&particles, gravity, dt);
+    let result = simulation.particles().read().unwrap();
+    for (i, (got, expected)) in result.iter().zip(&expected).enumerate() {
+        for k in 0..3 {
+            assert!(
+                (got.position[k] - expected.position[k]).abs() < 1e-5
+                    && (got.velocity[k] - expected.velocity[k]).abs() < 1e-3,
+                "particle {i} differs from the CPU: {got:?} vs {expected:?}"
+            );
+        }
+    }
+    drop(result);
+
+    let particles = disk(4096, gravity, 0xd3a2_646c);
+    let initial_energy = gravity.energy(&particles);
+    let mut simulation = NBody::new(queue.clone(), &memory_allocator, &particles, gravity);
+    let renderer = ParticleRenderer::new(queue, memory_allocator.clone(), [512, 512]);
+    let camera = Camera::perspective(FRAC_PI_4, renderer.aspect_ratio(), 0.1, 100.0)
+        .look_at(Vec3::new(0.0, 1.5, 2.2), Vec3::ZERO);
+    let view_projection = camera.projection() * camera.view();
+
+    let mut frames = Vec::new();
+    for _ in 0..60 {
+        frames.push(renderer.render(simulation.particles(), view_projection, 2.0));
+        simulation.step(10, dt);
+    }
+    assert!(
+        frames[0].pixels().any(|p| p[0] > 0),
+        "no particles were drawn"
+    );
+    export::save_gif(&frames, 20, "n_body.gif");
+    frames.last().unwrap().save("n_body.png").unwrap();
+
+    // Symplectic Euler is only first order, but its energy error stays
+    // bounded instead of growing with the number of steps.
+    let energy = simulation
+        .gravity()
+        .energy(&simulation.particles().read().unwrap());
+    let drift = ((energy - initial_energy) / initial_energy).abs();
+    println!("N-body energy drift after 600 steps: {:.2e}", drift);
+    assert!(drift < 1e-2, "energy drifted by {drift}");
+
+    println!("N-body simulation successful!");
+}
@@ -0,0 +1,102 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use vulkano::buffer::BufferUsage;
+use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
+
+use crate::scan::{Predicate, Scanner};
+use crate::util;
+
+pub fn prefix_sum() {
+    let mut vk_device = util::create_device();
+    let device = vk_device.device.clone();
+    let queue = vk_device.queues.next().unwrap();
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+    let scanner = Scanner::new(queue, memory_allocator.clone());
+
+    // One block, exactly one block, one more and enough blocks that the block
+    // sums need two more levels.
+    let lengths = [1, 5, 1024, 1025, 300_001, 3_000_000];
+
+    for (i, &len) in lengths.iter().enumerate() {
+        let seed = 0x2545_f491 ^ i as u32;
+
+        let words: Vec<u32> = util::random_words(len, seed).map(|w| w % 100).collect();
+        let buffer = util::create_buffer(
+            words.iter().copied(),
+            &memory_allocator,
+            BufferUsage::STORAGE_BUFFER,
+            MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+        );
+
+        let inclusive = scanner.inclusive_scan(&buffer);
+        let exclusive = scanner.exclusive_scan(&buffer);
+        let (inclusive, exclusive) = (inclusive.read().unwrap(), exclusive.read().unwrap());
+        let mut sum = 0u32;
+        for (n, &w) in words.iter().enumerate() {
+            assert_eq!(exclusive[n], sum, "exclusive scan differs at {n} of {len}");
+            sum = sum.wrapping_add(w);
+            assert_eq!(inclusive[n], sum, "inclusive scan differs at {n} of {len}");
+        }
+
+        // Stream compaction, dropping the zeros is the classic use.
+        let check_compact = |predicate: Predicate<u32>, keep: fn(u32) -> bool| {
+            let (compacted, count) = scanner.compact(&buffer, predicate);
+            let expected: Vec<u32> = words.iter().copied().filter(|&w| keep(w)).collect();
+            assert_eq!(count as usize, expected.len(), "{predicate:?} of {len}");
+            assert_eq!(compacted.read().unwrap()[..expected.len()], expected[..]);
+        };
+        check_compact(Predicate::NonZero, |w| w != 0);
+        check_compact(Predicate::Equal(42), |w| w == 42);
+        check_compact(Predicate::NotEqual(42), |w| w != 42);
+        check_compact(Predicate::Greater(90), |w| w > 90);
+
+        // Floats are summed in a different order than on the CPU, so compare
+        // against a double precision reference with a tolerance.
+        let floats: Vec<f32> = util::random_words(len, seed)
+            .map(|w| w as f32 / u32::MAX as f32 - 0.5)
+            .collect();
+        let buffer = util::create_buffer(
+            floats.iter().copied(),
+            &memory_allocator,
+            BufferUsage::STORAGE_BUFFER,
+            MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+        );
+
+        let inclusive = scanner.inclusive_scan(&buffer);
+        let inclusive = inclusive.read().unwrap();
+        let (mut sum, mut magnitude) = (0.0f64, 0.0f64);
+        for (n, &f) in floats.iter().enumerate() {
+            sum += f as f64;
+            magnitude += f.abs() as f64;
+            let error = (inclusive[n] as f64 - sum).abs();
+            assert!(
+                error <= 1e-5 * magnitude.max(1.0),
+                "float scan differs at {n} of {len}: {} vs {sum}",
+                inclusive[n]
+            );
+        }
+
+        let (compacted, count) = scanner.compact(&buffer, Predicate::Less(0.0));
+        let expected: Vec<f32> = floats.iter().copied().filter(|&f| f < 0.0).collect();
+        assert_eq!(count as usize, expected.len());
+        assert_eq!(compacted.read().unwrap()[..expected.len()], expected[..]);
+    }
+
+    let words: Vec<u32> = util::random_words(1 << 24, 7).map(|w| w & 0xff).collect();
+    let buffer = util::create_buffer(
+        words.iter().copied(),
+        &memory_allocator,
+        BufferUsage::STORAGE_BUFFER,
+        MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+    );
+    let start = Instant::now();
+    scanner.exclusive_scan(&buffer);
+    println!(
+        "Exclusive scan of {} words took {:?}",
+        words.len(),
+        start.elapsed()
+    );
+
+    println!("Prefix sum and stream compaction successful!");
+}
@@ -0,0 +1,152 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use vulkano::buffer::{BufferUsage, Subbuffer};
+use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
+
+use crate::sort::{RadixKey, RadixSorter};
+use crate::util;
+
+const BENCHMARK_RUNS: u32 = 5;
+
+fn upload<T: RadixKey>(data: &[T], allocator: &Arc<StandardMemoryAllocator>) -> Subbuffer<[T]> {
+    util::create_buffer(
+        data.iter().copied(),
+        allocator,
+        BufferUsage::STORAGE_BUFFER,
+        MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+    )
+}
+
+// Sorts the keys alone and with their original indices as payload. Radix
+// sort is stable, so the pairs have to match a stable sort on the CPU.
+fn check<K: RadixKey + Debug>(
+    sorter: &mut RadixSorter,
+    allocator: &Arc<StandardMemoryAllocator>,
+    keys: &[K],
+    name: &str,
+) {
+    let mut expected = keys.to_vec();
+    expected.sort_unstable();
+    let buffer = upload(keys, allocator);
+    sorter.sort(&buffer);
+    assert!(
+        *buffer.read().unwrap() == expected[..],
+        "{name} keys are not sorted ({} elements)",
+        keys.len()
+    );
+
+    let mut expected: Vec<(K, u32)> = keys.iter().copied().zip(0..).collect();
+    expected.sort_by_key(|&(key, _)| key);
+    let buffer = upload(keys, allocator);
+    let values = util::create_buffer(
+        0..keys.len() as u32,
+        allocator,
+        BufferUsage::STORAGE_BUFFER,
+        MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+    );
+    sorter.sort_by_key(&buffer, &values);
+    let (buffer, values) = (buffer.read().unwrap(), values.read().unwrap());
+    assert!(
+        buffer
+            .iter()
+            .copied()
+            .zip(values.iter().copied())
+            .eq(expected.iter().copied()),
+        "{name} pairs are not stably sorted ({} elements)",
+        keys.len()
+    );
+}
+
+fn benchmark<K: RadixKey>(
+    sorter: &mut RadixSorter,
+    allocator: &Arc<StandardMemoryAllocator>,
+    keys: &[K],
+    name: &str,
+) {
+    // The first run pays for pipeline creation and the scratch buffers.
+    let buffer = upload(keys, allocator);
+    sorter.sort(&buffer);
+
+    // Both sides sort a fresh copy of the keys each run, the copies are not
+    // timed.
+    let mut gpu_time = Duration::ZERO;
+    let mut cpu_time = Duration::ZERO;
+    let mut cpu = Vec::new();
+    for _ in 0..BENCHMARK_RUNS {
+        buffer.write().unwrap().copy_from_slice(keys);
+        let start = Instant::now();
+        sorter.sort(&buffer);
+        gpu_time += start.elapsed();
+
+        cpu = keys.to_vec();
+        let start = Instant::now();
+        cpu.sort_unstable();
+        cpu_time += start.elapsed();
+    }
+    // Averages over the runs.
+    let (gpu_time, cpu_time) = (gpu_time / BENCHMARK_RUNS, cpu_time / BENCHMARK_RUNS);
+
+    assert!(*buffer.read().unwrap() == cpu[..]);
+    println!(
+        "{} {name} keys: {gpu_time:?} radix sort, {cpu_time:?} sort_unstable",
+        keys.len()
+    );
+}
+
+pub fn radix_sort() {
+    let mut vk_device = util::create_device();
+    let device = vk_device.device.clone();
+    let queue = vk_device.queues.next().unwrap();
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+    let mut sorter = RadixSorter::new(queue, memory_allocator.clone());
+
+    let lengths = [1, 2, 1000, 1024, 1025, 100_003, 1 << 20];
+
+    for (i, &len) in lengths.iter().enumerate() {
+        let seed = 0x68e3_1da4 ^ i as u32;
+        let words: Vec<u32> = util::random_words(len, seed).collect();
+        let longs: Vec<u64> = words
+            .iter()
+            .zip(util::random_words(len, !seed))
+            .map(|(&lo, hi)| (hi as u64) << 32 | lo as u64)
+            .collect();
+
+        check(&mut sorter, &memory_allocator, &words, "random u32");
+        check(&mut sorter, &memory_allocator, &longs, "random u64");
+
+        let mut sorted = words.clone();
+        sorted.sort_unstable();
+        check(&mut sorter, &memory_allocator, &sorted, "sorted u32");
+        sorted.reverse();
+        check(&mut sorter, &memory_allocator, &sorted, "reversed u32");
+
+        // Few distinct keys, where stability actually shows.
+        let duplicates: Vec<u32> = words.iter().map(|w| w % 7).collect();
+        check(
+            &mut sorter,
+            &memory_allocator,
+            &duplicates,
+            "duplicate-heavy u32",
+        );
+        let duplicates: Vec<u64> = longs.iter().map(|w| (w % 7) << 40).collect();
+        check(
+            &mut sorter,
+            &memory_allocator,
+            &duplicates,
+            "duplicate-heavy u64",
+        );
+    }
+
+    let words: Vec<u32> = util::random_words(1 << 24, 3).collect();
+    benchmark(&mut sorter, &memory_allocator, &words, "u32");
+    let longs: Vec<u64> = words
+        .iter()
+        .zip(util::random_words(words.len(), 4))
+        .map(|(&lo, hi)| (hi as u64) << 32 | lo as u64)
+        .collect();
+    benchmark(&mut sorter, &memory_allocator, &longs, "u64");
+
+    println!("Radix sort successful!");
+}
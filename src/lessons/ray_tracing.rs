@@ -0,0 +1,613 @@
+use std::f32::consts::FRAC_PI_4;
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3};
+use image::RgbaImage;
+use vulkano::buffer::{BufferContents, BufferUsage, Subbuffer};
+use vulkano::command_buffer::allocator::{
+    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::device::{DeviceOwned, Queue};
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageUsage};
+use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::{ComputePipeline, Pipeline, PipelineBindPoint};
+
+use crate::camera::Camera;
+use crate::mesh::MeshData;
+use crate::{texture, util};
+
+mod cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+            // rgb is the sum of all samples so far, a their count.
+            layout(set = 0, binding = 0, rgba32f) uniform image2D accumulation;
+            layout(set = 0, binding = 1, rgba8) uniform writeonly image2D img;
+
+            struct Sphere {
+                vec3 center;
+                float radius;
+                uint material;
+            };
+
+            // All points x with dot(normal, x) == offset.
+            struct Plane {
+                vec3 normal;
+                float offset;
+                uint material;
+            };
+
+            struct Triangle {
+                vec4 a;
+                vec4 b;
+                vec4 c;
+                uint material;
+            };
+
+            struct Material {
+                vec4 albedo;
+                uint kind;
+                float fuzz;
+                float ior;
+            };
+
+            layout(set = 0, binding = 2) readonly buffer Spheres {
+                Sphere data[];
+            } spheres;
+
+            layout(set = 0, binding = 3) readonly buffer Planes {
+                Plane data[];
+            } planes;
+
+            layout(set = 0, binding = 4) readonly buffer Triangles {
+                Triangle data[];
+            } triangles;
+
+            layout(set = 0, binding = 5) readonly buffer Materials {
+                Material data[];
+            } materials;
+
+            layout(push_constant) uniform Params {
+                mat4 inverse_view_projection;
+                vec4 origin;
+                uint sample_index;
+                uint max_bounces;
+                uint sphere_count;
+                uint plane_count;
+                uint triangle_count;
+            } params;
+
+            const uint DIFFUSE = 0u;
+            const uint METAL = 1u;
+            const uint DIELECTRIC = 2u;
+
+            const float T_MIN = 0.001;
+            const float T_MAX = 1e30;
+            const float PI = 3.14159265;
+
+            uint rng_state;
+
+            // PCG hash, one step per random number.
+            uint pcg() {
+                rng_state = rng_state * 747796405u + 2891336453u;
+                uint word = ((rng_state >> ((rng_state >> 28u) + 4u)) ^ rng_state) * 277803737u;
+                return (word >> 22u) ^ word;
+            }
+
+            float rand() {
+                return float(pcg()) / 4294967296.0;
+            }
+
+            vec3 random_unit_vector() {
+                float z = 2.0 * rand() - 1.0;
+                float a = 2.0 * PI * rand();
+                float r = sqrt(1.0 - z * z);
+                return vec3(r * cos(a), r * sin(a), z);
+            }
+
+            struct Hit {
+                float t;
+                vec3 normal;
+                uint material;
+            };
+
+            // Keeps the closest hit in `hit`. The normal is stored as it comes
+            // from the surface, `main` turns it against the ray.
+            void hit_sphere(Sphere s, vec3 o, vec3 d, inout Hit hit) {
+                vec3 oc = o - s.center;
+                float b = dot(oc, d);
+                float c = dot(oc, oc) - s.radius * s.radius;
+                float disc = b * b - c;
+                if (disc < 0.0) {
+                    return;
+                }
+                float root = sqrt(disc);
+                float t = -b - root;
+                if (t < T_MIN) {
+                    t = -b + root;
+                }
+                if (t >= T_MIN && t < hit.t) {
+                    hit.t = t;
+                    hit.normal = (o + t * d - s.center) / s.radius;
+                    hit.material = s.material;
+                }
+            }
+
+            void hit_plane(Plane pl, vec3 o, vec3 d, inout Hit hit) {
+                float denom = dot(pl.normal, d);
+                if (abs(denom) < 1e-6) {
+                    return;
+                }
+                float t = (pl.offset - dot(pl.normal, o)) / denom;
+                if (t >= T_MIN && t < hit.t) {
+                    hit.t = t;
+                    hit.normal = pl.normal;
+                    hit.material = pl.material;
+                }
+            }
+
+            // Moller-Trumbore.
+            void hit_triangle(Triangle tri, vec3 o, vec3 d, inout Hit hit) {
+                vec3 e1 = tri.b.xyz - tri.a.xyz;
+                vec3 e2 = tri.c.xyz - tri.a.xyz;
+                vec3 pv = cross(d, e2);
+                float det = dot(e1, pv);
+                if (abs(det) < 1e-8) {
+                    return;
+                }
+                float inv_det = 1.0 / det;
+                vec3 tv = o - tri.a.xyz;
+                float u = dot(tv, pv) * inv_det;
+                if (u < 0.0 || u > 1.0) {
+                    return;
+                }
+                vec3 qv = cross(tv, e1);
+                float v = dot(d, qv) * inv_det;
+                if (v < 0.0 || u + v > 1.0) {
+                    return;
+                }
+                float t = dot(e2, qv) * inv_det;
+                if (t >= T_MIN && t < hit.t) {
+                    hit.t = t;
+                    hit.normal = normalize(cross(e1, e2));
+                    hit.material = tri.material;
+                }
+            }
+
+            Hit trace(vec3 o, vec3 d) {
+                Hit hit;
+                hit.t = T_MAX;
+                hit.normal = vec3(0.0);
+                hit.material = 0u;
+                for (uint i = 0; i < params.sphere_count; i++) {
+                    hit_sphere(spheres.data[i], o, d, hit);
+                }
+                for (uint i = 0; i < params.plane_count; i++) {
+                    hit_plane(planes.data[i], o, d, hit);
+                }
+                for (uint i = 0; i < params.triangle_count; i++) {
+                    hit_triangle(triangles.data[i], o, d, hit);
+                }
+                return hit;
+            }
+
+            vec3 sky(vec3 d) {
+                float t = 0.5 * (d.y + 1.0);
+                return mix(vec3(1.0), vec3(0.5, 0.7, 1.0), t);
+            }
+
+            float schlick(float cosine, float ratio) {
+                float r0 = (1.0 - ratio) / (1.0 + ratio);
+                r0 = r0 * r0;
+                return r0 + (1.0 - r0) * pow(1.0 - cosine, 5.0);
+            }
+
+            vec3 radiance(vec3 o, vec3 d) {
+                vec3 throughput = vec3(1.0);
+                for (uint bounce = 0; bounce <= params.max_bounces; bounce++) {
+                    Hit hit = trace(o, d);
+                    if (hit.t == T_MAX) {
+                        return throughput * sky(d);
+                    }
+
+                    Material m = materials.data[hit.material];
+                    bool front_face = dot(d, hit.normal) < 0.0;
+                    vec3 n = front_face ? hit.normal : -hit.normal;
+                    o = o + hit.t * d;
+
+                    if (m.kind == DIFFUSE) {
+                        d = n + random_unit_vector();
+                        d = dot(d, d) < 1e-8 ? n : normalize(d);
+                        throughput *= m.albedo.rgb;
+                    } else if (m.kind == METAL) {
+                        d = normalize(reflect(d, n) + m.fuzz * random_unit_vector());
+                        if (dot(d, n) <= 0.0) {
+                            return vec3(0.0);
+                        }
+                        throughput *= m.albedo.rgb;
+                    } else {
+                        // Glass absorbs nothing, it only picks between
+                        // reflection and refraction.
+                        float ratio = front_face ? 1.0 / m.ior : m.ior;
+                        float cosine = min(dot(-d, n), 1.0);
+                        float sine = sqrt(1.0 - cosine * cosine);
+                        if (ratio * sine > 1.0 || schlick(cosine, ratio) > rand()) {
+                            d = reflect(d, n);
+                        } else {
+                            d = refract(d, n, ratio);
+                        }
+                    }
+                }
+                // Out of bounces, the path contributes no light.
+                return vec3(0.0);
+            }
+
+            vec3 to_srgb(vec3 c) {
+                return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+            }
+
+            void main() {
+                ivec2 p = ivec2(gl_GlobalInvocationID.xy);
+                ivec2 size = imageSize(img);
+                if (any(greaterThanEqual(p, size))) {
+                    return;
+                }
+
+                // Different for every pixel and every sample.
+                rng_state = uint(p.y * size.x + p.x) * 9781u + params.sample_index * 6271u;
+                pcg();
+
+                // A random point inside the pixel, unprojected onto the far
+                // plane.
+                vec2 uv = (vec2(p) + vec2(rand(), rand())) / vec2(size);
+                vec4 far = params.inverse_view_projection * vec4(uv * 2.0 - 1.0, 1.0, 1.0);
+                vec3 d = normalize(far.xyz / far.w - params.origin.xyz);
+
+                vec4 sum = params.sample_index == 0 ? vec4(0.0) : imageLoad(accumulation, p);
+                sum += vec4(radiance(params.origin.xyz, d), 1.0);
+                imageStore(accumulation, p, sum);
+
+                // ACES filmic curve fitted by Krzysztof Narkowicz, then the
+                // sRGB encoding the UNORM image does not do for us.
+                vec3 c = sum.rgb / sum.a;
+                c = clamp((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14), 0.0, 1.0);
+                imageStore(img, p, vec4(to_srgb(c), 1.0));
+            }
+        ",
+    }
+}
+
+#[derive(BufferContents, Clone, Copy, Default)]
+#[repr(C)]
+struct Sphere {
+    center: [f32; 3],
+    radius: f32,
+    material: u32,
+    _pad: [u32; 3],
+}
+
+#[derive(BufferContents, Clone, Copy, Default)]
+#[repr(C)]
+struct Plane {
+    normal: [f32; 3],
+    offset: f32,
+    material: u32,
+    _pad: [u32; 3],
+}
+
+#[derive(BufferContents, Clone, Copy, Default)]
+#[repr(C)]
+struct Triangle {
+    a: [f32; 4],
+    b: [f32; 4],
+    c: [f32; 4],
+    material: u32,
+    _pad: [u32; 3],
+}
+
+#[derive(BufferContents, Clone, Copy, Default)]
+#[repr(C)]
+pub struct Material {
+    albedo: [f32; 4],
+    kind: u32,
+    fuzz: f32,
+    ior: f32,
+    _pad: u32,
+}
+
+impl Material {
+    pub fn diffuse(albedo: [f32; 3]) -> Self {
+        Self::new(albedo, 0, 0.0, 1.0)
+    }
+
+    // `fuzz` of 0 is a perfect mirror, larger values blur the reflection.
+    pub fn metal(albedo: [f32; 3], fuzz: f32) -> Self {
+        Self::new(albedo, 1, fuzz, 1.0)
+    }
+
+    // `ior` is the index of refraction, about 1.5 for glass.
+    pub fn dielectric(ior: f32) -> Self {
+        Self::new([1.0; 3], 2, 0.0, ior)
+    }
+
+    fn new(albedo: [f32; 3], kind: u32, fuzz: f32, ior: f32) -> Self {
+        let [r, g, b] = albedo;
+        Material {
+            albedo: [r, g, b, 1.0],
+            kind,
+            fuzz,
+            ior,
+            _pad: 0,
+        }
+    }
+}
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct Params {
+    inverse_view_projection: [[f32; 4]; 4],
+    origin: [f32; 4],
+    sample_index: u32,
+    max_bounces: u32,
+    sphere_count: u32,
+    plane_count: u32,
+    triangle_count: u32,
+}
+
+// Everything the shader intersects, with materials referenced by index.
+#[derive(Default)]
+pub struct Scene {
+    spheres: Vec<Sphere>,
+    planes: Vec<Plane>,
+    triangles: Vec<Triangle>,
+    materials: Vec<Material>,
+}
+
+impl Scene {
+    pub fn add_material(&mut self, material: Material) -> u32 {
+        self.materials.push(material);
+        self.materials.len() as u32 - 1
+    }
+
+    pub fn add_sphere(&mut self, center: Vec3, radius: f32, material: u32) {
+        self.spheres.push(Sphere {
+            center: center.to_array(),
+            radius,
+            material,
+            ..Default::default()
+        });
+    }
+
+    pub fn add_plane(&mut self, normal: Vec3, offset: f32, material: u32) {
+        self.planes.push(Plane {
+            normal: normal.normalize().to_array(),
+            offset,
+            material,
+            ..Default::default()
+        });
+    }
+
+    // Flattens the indexed mesh into world space triangles.
+    pub fn add_mesh(&mut self, mesh: &MeshData, transform: Mat4, material: u32) {
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|k| {
+                let position = Vec3::from(mesh.vertices[triangle[k] as usize].position);
+                transform.transform_point3(position).extend(1.0).to_array()
+            });
+            self.triangles.push(Triangle {
+                a,
+                b,
+                c,
+                material,
+                ..Default::default()
+            });
+        }
+    }
+}
+
+// Storage buffers can't be empty, absent kinds of objects get one unused
+// element.
+fn upload<T: BufferContents + Copy + Default>(
+    items: &[T],
+    allocator: &Arc<StandardMemoryAllocator>,
+) -> Subbuffer<[T]> {
+    let mut items = items.to_vec();
+    if items.is_empty() {
+        items.push(T::default());
+    }
+    util::create_buffer(
+        items,
+        allocator,
+        BufferUsage::STORAGE_BUFFER,
+        MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+    )
+}
+
+// Adds samples to a float accumulation image one dispatch at a time and keeps
+// a tone mapped copy of the running average in an RGBA8 image.
+pub struct RayTracer {
+    queue: Arc<Queue>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+    pipeline: Arc<ComputePipeline>,
+    descriptor_set: Arc<PersistentDescriptorSet>,
+    output: Arc<Image>,
+    params: Params,
+}
+
+impl RayTracer {
+    pub fn new(queue: Arc<Queue>, extent: [u32; 2], scene: &Scene, camera: &Camera) -> Self {
+        let device = queue.device().clone();
+        let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+        let descriptor_set_allocator =
+            StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+        let command_buffer_allocator = StandardCommandBufferAllocator::new(
+            device.clone(),
+            StandardCommandBufferAllocatorCreateInfo::default(),
+        );
+
+        let shader = cs::load(device.clone()).expect("failed to create shader module");
+        let pipeline = util::create_compute_pipeline(device.clone(), shader);
+
+        let accumulation = util::create_render_target(
+            &memory_allocator,
+            Format::R32G32B32A32_SFLOAT,
+            extent,
+            ImageUsage::STORAGE,
+        );
+        let output = util::create_render_target(
+            &memory_allocator,
+            Format::R8G8B8A8_UNORM,
+            extent,
+            ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC,
+        );
+
+        let descriptor_set = PersistentDescriptorSet::new(
+            &descriptor_set_allocator,
+            pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::image_view(0, ImageView::new_default(accumulation).unwrap()),
+                WriteDescriptorSet::image_view(1, ImageView::new_default(output.clone()).unwrap()),
+                WriteDescriptorSet::buffer(2, upload(&scene.spheres, &memory_allocator)),
+                WriteDescriptorSet::buffer(3, upload(&scene.planes, &memory_allocator)),
+                WriteDescriptorSet::buffer(4, upload(&scene.triangles, &memory_allocator)),
+                WriteDescriptorSet::buffer(5, upload(&scene.materials, &memory_allocator)),
+            ],
+            [],
+        )
+        .unwrap();
+
+        let view_projection = camera.projection() * camera.view();
+        let params = Params {
+            inverse_view_projection: view_projection.inverse().to_cols_array_2d(),
+            origin: camera.position.extend(1.0).to_array(),
+            sample_index: 0,
+            max_bounces: 8,
+            sphere_count: scene.spheres.len() as u32,
+            plane_count: scene.planes.len() as u32,
+            triangle_count: scene.triangles.len() as u32,
+        };
+
+        RayTracer {
+            queue,
+            memory_allocator,
+            command_buffer_allocator,
+            pipeline,
+            descriptor_set,
+            output,
+            params,
+        }
+    }
+
+    // One dispatch per sample, all in one command buffer. Each dispatch reads
+    // what the previous one accumulated.
+    pub fn add_samples(&mut self, samples: u32) {
+        let [width, height, _] = self.output.extent();
+        let mut builder = util::begin_one_time(&self.command_buffer_allocator, &self.queue);
+        builder
+            .bind_pipeline_compute(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.pipeline.layout().clone(),
+                0,
+                self.descriptor_set.clone(),
+            )
+            .unwrap();
+        for _ in 0..samples {
+            builder
+                .push_constants(self.pipeline.layout().clone(), 0, self.params)
+                .unwrap()
+                .dispatch([width.div_ceil(8), height.div_ceil(8), 1])
+                .unwrap();
+            self.params.sample_index += 1;
+        }
+
+        util::submit_and_wait(builder, &self.queue);
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.params.sample_index
+    }
+
+    pub fn image(&self) -> RgbaImage {
+        texture::read_mip_level(
+            &self.output,
+            0,
+            &self.memory_allocator,
+            &self.command_buffer_allocator,
+            &self.queue,
+        )
+    }
+}
+
+fn rms_difference(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    let sum: f64 = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(&x, &y)| (x as f64 - y as f64).powi(2))
+        .sum();
+    (sum / a.as_raw().len() as f64).sqrt()
+}
+
+pub fn ray_tracing() {
+    let mut vk_device = util::create_device();
+    let queue = vk_device.queues.next().unwrap();
+
+    let mut scene = Scene::default();
+    let ground = scene.add_material(Material::diffuse([0.5, 0.5, 0.5]));
+    let red = scene.add_material(Material::diffuse([0.7, 0.2, 0.2]));
+    let gold = scene.add_material(Material::metal([0.8, 0.6, 0.2], 0.1));
+    let mirror = scene.add_material(Material::metal([0.9, 0.9, 0.9], 0.0));
+    let glass = scene.add_material(Material::dielectric(1.5));
+
+    scene.add_plane(Vec3::Y, 0.0, ground);
+    scene.add_sphere(Vec3::new(0.0, 0.5, 0.0), 0.5, red);
+    scene.add_sphere(Vec3::new(-1.1, 0.5, 0.0), 0.5, glass);
+    scene.add_sphere(Vec3::new(1.1, 0.5, 0.0), 0.5, gold);
+    scene.add_mesh(
+        &MeshData::cube(),
+        Mat4::from_translation(Vec3::new(0.4, 0.3, 1.0))
+            * Mat4::from_rotation_y(0.7)
+            * Mat4::from_scale(Vec3::splat(0.6)),
+        mirror,
+    );
+
+    let extent = [640, 360];
+    let camera = Camera::perspective(FRAC_PI_4, extent[0] as f32 / extent[1] as f32, 0.1, 100.0)
+        .look_at(Vec3::new(0.0, 1.2, 4.0), Vec3::new(0.0, 0.4, 0.0));
+    let mut tracer = RayTracer::new(queue, extent, &scene, &camera);
+
+    tracer.add_samples(1);
+    let one_sample = tracer.image();
+    one_sample.save("ray_tracing_1spp.png").unwrap();
+    tracer.add_samples(31);
+    let some_samples = tracer.image();
+    tracer.add_samples(224);
+    let converged = tracer.image();
+    converged.save("ray_tracing.png").unwrap();
+
+    // The noise has to go down as samples accumulate.
+    let early = rms_difference(&one_sample, &converged);
+    let later = rms_difference(&some_samples, &converged);
+    println!(
+        "RMS difference to {} samples: {early:.2} after 1, {later:.2} after 32",
+        tracer.samples()
+    );
+    assert!(later < 0.5 * early, "accumulation does not reduce noise");
+
+    // The top of the frame only sees the sky, which is blue towards the top.
+    let sky = converged.get_pixel(extent[0] / 2, 0);
+    assert!(sky[2] > sky[0], "expected sky at the top, got {sky:?}");
+
+    println!("Ray tracing successful!");
+}
@@ -0,0 +1,116 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Instant;
+
+use vulkano::buffer::{BufferUsage, Subbuffer};
+use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
+
+use crate::reduce::{Element, ReduceMethod, Reducer};
+use crate::util;
+
+fn upload<T: Element>(data: &[T], allocator: &Arc<StandardMemoryAllocator>) -> Subbuffer<[T]> {
+    util::create_buffer(
+        data.iter().copied(),
+        allocator,
+        BufferUsage::STORAGE_BUFFER,
+        MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+    )
+}
+
+// Min, max and argmax have to match exactly. Sums are checked by the caller
+// since float sums depend on the order of additions.
+fn check<T>(
+    reducer: &Reducer,
+    allocator: &Arc<StandardMemoryAllocator>,
+    data: &[T],
+    sum_ok: impl Fn(T) -> bool,
+) where
+    T: Element + PartialOrd + Debug,
+{
+    let buffer = upload(data, allocator);
+
+    let sum = reducer.sum(&buffer);
+    assert!(sum_ok(sum), "wrong sum {sum:?} for {} elements", data.len());
+
+    let min = data
+        .iter()
+        .copied()
+        .reduce(|a, b| if b < a { b } else { a });
+    assert_eq!(Some(reducer.min(&buffer)), min);
+
+    let max = data
+        .iter()
+        .copied()
+        .reduce(|a, b| if b > a { b } else { a });
+    assert_eq!(Some(reducer.max(&buffer)), max);
+
+    let first_max = data
+        .iter()
+        .copied()
+        .enumerate()
+        .reduce(|a, b| if b.1 > a.1 { b } else { a })
+        .map(|(i, v)| (i as u32, v));
+    assert_eq!(Some(reducer.argmax(&buffer)), first_max);
+}
+
+pub fn reduction() {
+    let mut vk_device = util::create_device();
+    let device = vk_device.device.clone();
+    let queue = vk_device.queues.next().unwrap();
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+
+    let mut methods = vec![ReduceMethod::SharedMemory];
+    if ReduceMethod::best(device.physical_device()) == ReduceMethod::Subgroup {
+        methods.push(ReduceMethod::Subgroup);
+    }
+
+    // Lengths around the workgroup chunk size and ones that need several passes.
+    let lengths = [1, 7, 1023, 1024, 1025, 100_000, 1_048_577, 5_000_000];
+
+    for method in methods {
+        let reducer = Reducer::new(queue.clone(), memory_allocator.clone(), method);
+
+        for (i, &len) in lengths.iter().enumerate() {
+            let seed = 0x9e37_79b9 ^ i as u32;
+
+            // A small range gives lots of ties for argmax.
+            let words: Vec<u32> = util::random_words(len, seed).map(|w| w % 1000).collect();
+            let expected: u32 = words.iter().fold(0, |a, &b| a.wrapping_add(b));
+            check(&reducer, &memory_allocator, &words, |sum| sum == expected);
+
+            let ints: Vec<i32> = util::random_words(len, seed).map(|w| w as i32).collect();
+            let expected: i32 = ints.iter().fold(0, |a, &b| a.wrapping_add(b));
+            check(&reducer, &memory_allocator, &ints, |sum| sum == expected);
+
+            let floats: Vec<f32> = util::random_words(len, seed)
+                .map(|w| w as f32 / u32::MAX as f32 * 2.0 - 1.0)
+                .collect();
+            let expected: f64 = floats.iter().map(|&f| f as f64).sum();
+            let magnitude: f64 = floats.iter().map(|&f| f.abs() as f64).sum();
+            check(&reducer, &memory_allocator, &floats, |sum| {
+                (sum as f64 - expected).abs() <= 1e-5 * magnitude.max(1.0)
+            });
+        }
+
+        let floats: Vec<f32> = util::random_words(1 << 24, 1)
+            .map(|w| (w >> 8) as f32)
+            .collect();
+        let buffer = upload(&floats, &memory_allocator);
+
+        let start = Instant::now();
+        let gpu_max = reducer.max(&buffer);
+        let gpu_time = start.elapsed();
+
+        let start = Instant::now();
+        let cpu_max = floats.iter().copied().fold(f32::MIN, f32::max);
+        let cpu_time = start.elapsed();
+
+        assert_eq!(gpu_max, cpu_max);
+        println!(
+            "{method:?} max of {} floats: {gpu_time:?} on the GPU, {cpu_time:?} on the CPU",
+            floats.len()
+        );
+    }
+
+    println!("Parallel reduction successful!");
+}
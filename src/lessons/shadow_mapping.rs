@@ -0,0 +1,635 @@
+use std::f32::consts::FRAC_PI_4;
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3, Vec4Swizzles};
+use image::{GrayImage, ImageBuffer, Rgba};
+use vulkano::buffer::allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo};
+use vulkano::buffer::{BufferContents, BufferUsage};
+use vulkano::command_buffer::allocator::{
+    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CommandBufferUsage, CopyImageToBufferInfo, RenderPassBeginInfo,
+    SubpassBeginInfo, SubpassContents, SubpassEndInfo,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::device::physical::PhysicalDevice;
+use vulkano::format::{Format, FormatFeatures};
+use vulkano::image::sampler::{
+    BorderColor, Filter, Sampler, SamplerAddressMode, SamplerCreateInfo,
+};
+use vulkano::image::view::ImageView;
+use vulkano::image::ImageUsage;
+use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::color_blend::{ColorBlendAttachmentState, ColorBlendState};
+use vulkano::pipeline::graphics::depth_stencil::{CompareOp, DepthState, DepthStencilState};
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::{CullMode, DepthBiasState, RasterizationState};
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{
+    GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo,
+};
+use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, Subpass};
+use vulkano::sync::{self, GpuFuture};
+
+use crate::camera::{Camera, Transforms};
+use crate::mesh::{Mesh, MeshData, MeshVertex};
+use crate::util;
+
+const SHADOW_MAP_SIZE: u32 = 2048;
+
+// Depth-only formats are simple to read back. D16_UNORM is always supported
+// as a sampled depth attachment, D32_SFLOAT just has more precision.
+const SHADOW_MAP_FORMATS: [Format; 2] = [Format::D32_SFLOAT, Format::D16_UNORM];
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct Light {
+    view_projection: [[f32; 4]; 4],
+    // Points towards the light.
+    direction: [f32; 4],
+}
+
+// Only writes depth, there is no fragment shader at all.
+mod shadow_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec3 position;
+
+            layout(set = 0, binding = 0) uniform Transforms {
+                mat4 model;
+                mat4 view;
+                mat4 projection;
+            } transforms;
+
+            void main() {
+                gl_Position = transforms.projection * transforms.view * transforms.model * vec4(position, 1.0);
+            }
+        ",
+    }
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec3 position;
+            layout(location = 1) in vec3 normal;
+
+            layout(location = 0) out vec3 v_position;
+            layout(location = 1) out vec3 v_normal;
+
+            layout(set = 0, binding = 0) uniform Transforms {
+                mat4 model;
+                mat4 view;
+                mat4 projection;
+            } transforms;
+
+            void main() {
+                vec4 world = transforms.model * vec4(position, 1.0);
+                v_position = world.xyz;
+                v_normal = mat3(transforms.model) * normal;
+                gl_Position = transforms.projection * transforms.view * world;
+            }
+        ",
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec3 v_position;
+            layout(location = 1) in vec3 v_normal;
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 0, binding = 1) uniform Light {
+                mat4 view_projection;
+                vec4 direction;
+            } light;
+
+            // Sampling a shadow sampler compares the reference depth against
+            // the stored one and returns 1.0 where it is lit.
+            layout(set = 0, binding = 2) uniform sampler2DShadow shadow_map;
+
+            layout(push_constant) uniform Material {
+                vec4 color;
+            } material;
+
+            float shadow(vec3 position) {
+                vec4 clip = light.view_projection * vec4(position, 1.0);
+                vec3 ndc = clip.xyz / clip.w;
+                if (ndc.z > 1.0) {
+                    return 1.0;
+                }
+                vec2 uv = ndc.xy * 0.5 + 0.5;
+
+                // Percentage-closer filtering: average the comparison over a
+                // 5x5 neighbourhood instead of the depths themselves.
+                vec2 texel = 1.0 / vec2(textureSize(shadow_map, 0));
+                float lit = 0.0;
+                for (int y = -2; y <= 2; y++) {
+                    for (int x = -2; x <= 2; x++) {
+                        lit += texture(shadow_map, vec3(uv + vec2(x, y) * texel, ndc.z));
+                    }
+                }
+                return lit / 25.0;
+            }
+
+            void main() {
+                vec3 normal = normalize(v_normal);
+                float diffuse = max(dot(normal, light.direction.xyz), 0.0);
+                float lit = diffuse > 0.0 ? shadow(v_position) : 0.0;
+                f_color = vec4(material.color.rgb * (0.2 + 0.8 * diffuse * lit), 1.0);
+            }
+        ",
+    }
+}
+
+fn find_shadow_map_format(physical_device: &PhysicalDevice) -> Format {
+    SHADOW_MAP_FORMATS
+        .into_iter()
+        .find(|&format| {
+            physical_device
+                .format_properties(format)
+                .unwrap()
+                .optimal_tiling_features
+                .contains(FormatFeatures::DEPTH_STENCIL_ATTACHMENT | FormatFeatures::SAMPLED_IMAGE)
+        })
+        .expect("no supported shadow map format")
+}
+
+fn shadow_map_to_gray(bytes: &[u8], format: Format) -> GrayImage {
+    let pixels = match format {
+        Format::D32_SFLOAT => bytes
+            .chunks(4)
+            .map(|d| (f32::from_ne_bytes([d[0], d[1], d[2], d[3]]) * 255.0) as u8)
+            .collect(),
+        Format::D16_UNORM => bytes
+            .chunks(2)
+            .map(|d| (u16::from_ne_bytes([d[0], d[1]]) >> 8) as u8)
+            .collect(),
+        _ => unreachable!(),
+    };
+    GrayImage::from_raw(SHADOW_MAP_SIZE, SHADOW_MAP_SIZE, pixels).unwrap()
+}
+
+pub fn shadow_mapping() {
+    let mut vk_device = util::create_device();
+    let queue = vk_device.queues.next().unwrap();
+    let device = vk_device.device.clone();
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+
+    let image = util::create_image(
+        &memory_allocator,
+        ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+        MemoryTypeFilter::PREFER_DEVICE,
+    );
+    let depth_format = util::find_depth_format(device.physical_device());
+    let depth_image = util::create_depth_image(&memory_allocator, depth_format, [1024, 1024]);
+
+    let shadow_map_format = find_shadow_map_format(device.physical_device());
+    let shadow_map = util::create_render_target(
+        &memory_allocator,
+        shadow_map_format,
+        [SHADOW_MAP_SIZE, SHADOW_MAP_SIZE],
+        ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::SAMPLED | ImageUsage::TRANSFER_SRC,
+    );
+
+    let buf = util::create_buffer(
+        (0..1024 * 1024 * 4).map(|_| 0u8),
+        &memory_allocator,
+        BufferUsage::TRANSFER_DST,
+        MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+    );
+    let shadow_map_buf = util::create_buffer(
+        (0..SHADOW_MAP_SIZE * SHADOW_MAP_SIZE * shadow_map_format.block_size() as u32).map(|_| 0u8),
+        &memory_allocator,
+        BufferUsage::TRANSFER_DST,
+        MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+    );
+
+    // The shadow pass has a depth attachment and nothing else. It is stored so
+    // the main pass can sample it.
+    let shadow_render_pass = vulkano::single_pass_renderpass!(
+        device.clone(),
+        attachments: {
+            depth: {
+                format: shadow_map_format,
+                samples: 1,
+                load_op: Clear,
+                store_op: Store,
+            },
+        },
+        pass: {
+            color: [],
+            depth_stencil: {depth},
+        },
+    )
+    .unwrap();
+
+    let render_pass = vulkano::single_pass_renderpass!(
+        device.clone(),
+        attachments: {
+            color: {
+                format: Format::R8G8B8A8_UNORM,
+                samples: 1,
+                load_op: Clear,
+                store_op: Store,
+            },
+            depth: {
+                format: depth_format,
+                samples: 1,
+                load_op: Clear,
+                store_op: DontCare,
+            },
+        },
+        pass: {
+            color: [color],
+            depth_stencil: {depth},
+        },
+    )
+    .unwrap();
+
+    let shadow_map_view = ImageView::new_default(shadow_map.clone()).unwrap();
+    let shadow_framebuffer = Framebuffer::new(
+        shadow_render_pass.clone(),
+        FramebufferCreateInfo {
+            attachments: vec![shadow_map_view.clone()],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let framebuffer = Framebuffer::new(
+        render_pass.clone(),
+        FramebufferCreateInfo {
+            attachments: vec![
+                ImageView::new_default(image.clone()).unwrap(),
+                ImageView::new_default(depth_image).unwrap(),
+            ],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let shadow_pipeline = {
+        let vs = shadow_vs::load(device.clone()).expect("failed to create shader module");
+        let vs = vs.entry_point("main").unwrap();
+
+        let vertex_input_state = MeshVertex::per_vertex()
+            .definition(&vs.info().input_interface)
+            .unwrap();
+
+        let stages = [PipelineShaderStageCreateInfo::new(vs)];
+
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+                .into_pipeline_layout_create_info(device.clone())
+                .unwrap(),
+        )
+        .unwrap();
+
+        let subpass = Subpass::from(shadow_render_pass.clone(), 0).unwrap();
+
+        GraphicsPipeline::new(
+            device.clone(),
+            None,
+            GraphicsPipelineCreateInfo {
+                stages: stages.into_iter().collect(),
+                vertex_input_state: Some(vertex_input_state),
+                input_assembly_state: Some(InputAssemblyState::default()),
+                viewport_state: Some(ViewportState {
+                    viewports: [Viewport {
+                        offset: [0.0, 0.0],
+                        extent: [SHADOW_MAP_SIZE as f32; 2],
+                        depth_range: 0.0..=1.0,
+                    }]
+                    .into_iter()
+                    .collect(),
+                    ..Default::default()
+                }),
+                // Pushing the stored depth away from the light, more so on
+                // slopes, keeps lit surfaces from shadowing themselves (shadow
+                // acne).
+                rasterization_state: Some(RasterizationState {
+                    cull_mode: CullMode::Back,
+                    depth_bias: Some(DepthBiasState {
+                        constant_factor: 1.25,
+                        clamp: 0.0,
+                        slope_factor: 1.75,
+                    }),
+                    ..Default::default()
+                }),
+                multisample_state: Some(MultisampleState::default()),
+                depth_stencil_state: Some(DepthStencilState {
+                    depth: Some(DepthState::simple()),
+                    ..Default::default()
+                }),
+                subpass: Some(subpass.into()),
+                ..GraphicsPipelineCreateInfo::layout(layout)
+            },
+        )
+        .unwrap()
+    };
+
+    let pipeline = {
+        let vs = vs::load(device.clone()).expect("failed to create shader module");
+        let fs = fs::load(device.clone()).expect("failed to create shader module");
+        let vs = vs.entry_point("main").unwrap();
+        let fs = fs.entry_point("main").unwrap();
+
+        let vertex_input_state = MeshVertex::per_vertex()
+            .definition(&vs.info().input_interface)
+            .unwrap();
+
+        let stages = [
+            PipelineShaderStageCreateInfo::new(vs),
+            PipelineShaderStageCreateInfo::new(fs),
+        ];
+
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+                .into_pipeline_layout_create_info(device.clone())
+                .unwrap(),
+        )
+        .unwrap();
+
+        let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+
+        GraphicsPipeline::new(
+            device.clone(),
+            None,
+            GraphicsPipelineCreateInfo {
+                stages: stages.into_iter().collect(),
+                vertex_input_state: Some(vertex_input_state),
+                input_assembly_state: Some(InputAssemblyState::default()),
+                viewport_state: Some(ViewportState {
+                    viewports: [Viewport {
+                        offset: [0.0, 0.0],
+                        extent: [1024.0, 1024.0],
+                        depth_range: 0.0..=1.0,
+                    }]
+                    .into_iter()
+                    .collect(),
+                    ..Default::default()
+                }),
+                rasterization_state: Some(RasterizationState {
+                    cull_mode: CullMode::Back,
+                    ..Default::default()
+                }),
+                multisample_state: Some(MultisampleState::default()),
+                depth_stencil_state: Some(DepthStencilState {
+                    depth: Some(DepthState::simple()),
+                    ..Default::default()
+                }),
+                color_blend_state: Some(ColorBlendState::with_attachment_states(
+                    subpass.num_color_attachments(),
+                    ColorBlendAttachmentState::default(),
+                )),
+                subpass: Some(subpass.into()),
+                ..GraphicsPipelineCreateInfo::layout(layout)
+            },
+        )
+        .unwrap()
+    };
+
+    // Hardware filtering of the comparison results, where available, smooths
+    // the PCF kernel a little more. Outside of the map everything is lit.
+    let shadow_filter = if shadow_map
+        .format_features()
+        .intersects(FormatFeatures::SAMPLED_IMAGE_FILTER_LINEAR)
+    {
+        Filter::Linear
+    } else {
+        Filter::Nearest
+    };
+    let shadow_sampler = Sampler::new(
+        device.clone(),
+        SamplerCreateInfo {
+            mag_filter: shadow_filter,
+            min_filter: shadow_filter,
+            address_mode: [SamplerAddressMode::ClampToBorder; 3],
+            border_color: BorderColor::FloatOpaqueWhite,
+            compare: Some(CompareOp::LessOrEqual),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    // A sphere floating over the ground next to a cube standing on it.
+    let ground = Mesh::new(MeshData::grid(1, 1), &memory_allocator);
+    let sphere = Mesh::new(MeshData::uv_sphere(32, 16), &memory_allocator);
+    let cube = Mesh::new(MeshData::cube(), &memory_allocator);
+    let sphere_center = Vec3::new(0.9, 0.5, -0.2);
+    let objects = [
+        (
+            &ground,
+            Mat4::from_translation(Vec3::new(0.0, -0.5, 0.0)) * Mat4::from_scale(Vec3::splat(8.0)),
+            [0.8, 0.8, 0.75, 1.0],
+        ),
+        (
+            &sphere,
+            Mat4::from_translation(sphere_center),
+            [0.9, 0.3, 0.2, 1.0],
+        ),
+        (
+            &cube,
+            Mat4::from_translation(Vec3::new(-0.8, 0.0, 0.3)) * Mat4::from_rotation_y(0.5),
+            [0.2, 0.6, 0.9, 1.0],
+        ),
+    ];
+
+    // A directional light, so the light "camera" uses an orthographic
+    // projection that covers the scene.
+    let camera = Camera::perspective(FRAC_PI_4, 1.0, 0.1, 100.0)
+        .look_at(Vec3::new(0.0, 2.5, 4.5), Vec3::ZERO);
+    let light_camera =
+        Camera::orthographic(7.0, 1.0, 0.1, 20.0).look_at(Vec3::new(-3.0, 5.0, -3.0), Vec3::ZERO);
+    let light_direction = (light_camera.position - light_camera.target).normalize();
+    let light = Light {
+        view_projection: (light_camera.projection() * light_camera.view()).to_cols_array_2d(),
+        direction: light_direction.extend(0.0).to_array(),
+    };
+
+    let uniform_buffer_allocator = SubbufferAllocator::new(
+        memory_allocator.clone(),
+        SubbufferAllocatorCreateInfo {
+            buffer_usage: BufferUsage::UNIFORM_BUFFER,
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+    );
+    let descriptor_set_allocator =
+        StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+
+    let light_buffer = uniform_buffer_allocator.allocate_sized::<Light>().unwrap();
+    *light_buffer.write().unwrap() = light;
+
+    let transforms_buffer = |camera: &Camera, model: Mat4| {
+        let buffer = uniform_buffer_allocator
+            .allocate_sized::<Transforms>()
+            .unwrap();
+        *buffer.write().unwrap() = camera.transforms(model);
+        buffer
+    };
+
+    let command_buffer_allocator = StandardCommandBufferAllocator::new(
+        device.clone(),
+        StandardCommandBufferAllocatorCreateInfo::default(),
+    );
+
+    let mut builder = AutoCommandBufferBuilder::primary(
+        &command_buffer_allocator,
+        queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+
+    // First the scene as seen from the light, depth only.
+    builder
+        .begin_render_pass(
+            RenderPassBeginInfo {
+                clear_values: vec![Some(1.0.into())],
+                ..RenderPassBeginInfo::framebuffer(shadow_framebuffer.clone())
+            },
+            SubpassBeginInfo {
+                contents: SubpassContents::Inline,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .bind_pipeline_graphics(shadow_pipeline.clone())
+        .unwrap();
+
+    for (mesh, model, _) in &objects {
+        let set = PersistentDescriptorSet::new(
+            &descriptor_set_allocator,
+            shadow_pipeline.layout().set_layouts()[0].clone(),
+            [WriteDescriptorSet::buffer(
+                0,
+                transforms_buffer(&light_camera, *model),
+            )],
+            [],
+        )
+        .unwrap();
+        builder
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                shadow_pipeline.layout().clone(),
+                0,
+                set,
+            )
+            .unwrap();
+        mesh.draw(&mut builder);
+    }
+
+    builder.end_render_pass(SubpassEndInfo::default()).unwrap();
+
+    // Then the scene from the camera, looking every fragment up in the shadow
+    // map.
+    builder
+        .begin_render_pass(
+            RenderPassBeginInfo {
+                clear_values: vec![Some([0.1, 0.1, 0.1, 1.0].into()), Some(1.0.into())],
+                ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
+            },
+            SubpassBeginInfo {
+                contents: SubpassContents::Inline,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .bind_pipeline_graphics(pipeline.clone())
+        .unwrap();
+
+    for (mesh, model, color) in objects {
+        let set = PersistentDescriptorSet::new(
+            &descriptor_set_allocator,
+            pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::buffer(0, transforms_buffer(&camera, model)),
+                WriteDescriptorSet::buffer(1, light_buffer.clone()),
+                WriteDescriptorSet::image_view_sampler(
+                    2,
+                    shadow_map_view.clone(),
+                    shadow_sampler.clone(),
+                ),
+            ],
+            [],
+        )
+        .unwrap();
+        builder
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                pipeline.layout().clone(),
+                0,
+                set,
+            )
+            .unwrap()
+            .push_constants(pipeline.layout().clone(), 0, color)
+            .unwrap();
+        mesh.draw(&mut builder);
+    }
+
+    builder
+        .end_render_pass(SubpassEndInfo::default())
+        .unwrap()
+        .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(image, buf.clone()))
+        .unwrap()
+        .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+            shadow_map,
+            shadow_map_buf.clone(),
+        ))
+        .unwrap();
+
+    let command_buffer = builder.build().unwrap();
+
+    let future = sync::now(device.clone())
+        .then_execute(queue.clone(), command_buffer)
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap();
+    future.wait(None).unwrap();
+
+    let buffer_content = buf.read().unwrap();
+    let image = ImageBuffer::<Rgba<u8>, _>::from_raw(1024, 1024, &buffer_content[..]).unwrap();
+    image.save("shadow_mapping.png").unwrap();
+    shadow_map_to_gray(&shadow_map_buf.read().unwrap(), shadow_map_format)
+        .save("shadow_map.png")
+        .unwrap();
+
+    // Follow the light ray through the center of the sphere down to the
+    // ground: that point must be darker than an open spot of ground nearby.
+    let to_pixel = |world: Vec3| {
+        let clip = camera.projection() * camera.view() * world.extend(1.0);
+        let ndc = clip.xy() / clip.w;
+        image.get_pixel(
+            ((ndc.x + 1.0) * 512.0) as u32,
+            ((ndc.y + 1.0) * 512.0) as u32,
+        )[0]
+    };
+    let shadowed = sphere_center - light_direction * ((sphere_center.y + 0.5) / light_direction.y);
+    let open = shadowed + Vec3::new(0.0, 0.0, 1.2);
+    assert!(
+        (to_pixel(shadowed) as f32) < to_pixel(open) as f32 * 0.6,
+        "the sphere casts no shadow"
+    );
+
+    println!("Shadow mapping successful!");
+}
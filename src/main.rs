@@ -1,15 +1,92 @@
+mod blend;
+mod camera;
+mod export;
+mod filter;
+mod gemm;
+mod histogram;
 mod lessons;
+mod mesh;
+mod model;
+mod particles;
+mod reduce;
+mod render_target;
+mod scan;
+mod sort;
+mod texture;
 mod util;
+#[cfg(feature = "window")]
+mod window;
 
+use lessons::blending::blending;
 use lessons::buffer_creation::buffer_creation;
+use lessons::camera::camera;
 use lessons::compute_pipeline::compute_pipeline;
+use lessons::deferred_shading::deferred_shading;
+use lessons::depth_buffer::depth_buffer;
+use lessons::dynamic_viewport::dynamic_viewport;
+use lessons::fluid::fluid;
+use lessons::game_of_life::game_of_life;
 use lessons::graphics_pipeline::graphics_pipeline;
-use lessons::using_images::{mandelbrot_set, using_images};
+use lessons::image_filters::{filter_image, image_filters};
+use lessons::image_statistics::image_statistics;
+use lessons::indexed_drawing::indexed_drawing;
+use lessons::instancing::instancing;
+#[cfg(feature = "window")]
+use lessons::live_preview::{live_preview, Scene};
+use lessons::mandelbrot::{mandelbrot_set, mandelbrot_supersampled, mandelbrot_zoom};
+use lessons::matrix_multiply::matrix_multiply;
+use lessons::mipmaps::mipmaps;
+use lessons::model_loading::model_loading;
+use lessons::n_body::n_body;
+use lessons::prefix_sum::prefix_sum;
+use lessons::radix_sort::radix_sort;
+use lessons::ray_tracing::ray_tracing;
+use lessons::reduction::reduction;
+use lessons::shadow_mapping::shadow_mapping;
+use lessons::textured_quad::textured_quad;
+use lessons::using_images::using_images;
 
 fn main() {
+    // `cargo run -- filter <input> <output> <filter>...` only runs a filter
+    // chain, see `ImageFilter` for the filter syntax.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("filter") {
+        filter_image(&args[1..]);
+        return;
+    }
+
     buffer_creation();
     compute_pipeline();
     using_images();
     mandelbrot_set();
-    graphics_pipeline();
+    mandelbrot_supersampled();
+    mandelbrot_zoom();
+    graphics_pipeline(1);
+    graphics_pipeline(8);
+    depth_buffer();
+    indexed_drawing();
+    model_loading("assets/cube.obj");
+    model_loading("assets/boxes.gltf");
+    camera();
+    textured_quad();
+    mipmaps();
+    instancing();
+    blending();
+    deferred_shading();
+    shadow_mapping();
+    dynamic_viewport();
+    reduction();
+    prefix_sum();
+    radix_sort();
+    matrix_multiply();
+    image_filters();
+    image_statistics();
+    game_of_life();
+    n_body();
+    fluid();
+    ray_tracing();
+
+    // Headless runs stop here, `--features window` opens a live preview.
+    #[cfg(feature = "window")]
+    live_preview(Scene::Mandelbrot);
 }
@@ -0,0 +1,281 @@
+use std::f32::consts::{PI, TAU};
+use std::sync::Arc;
+
+use vulkano::buffer::{BufferContents, BufferUsage, IndexBuffer, Subbuffer};
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::vertex_input::Vertex;
+
+use crate::util;
+
+#[derive(BufferContents, Vertex, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct MeshVertex {
+    #[format(R32G32B32_SFLOAT)]
+    pub position: [f32; 3],
+    #[format(R32G32B32_SFLOAT)]
+    pub normal: [f32; 3],
+    #[format(R32G32_SFLOAT)]
+    pub uv: [f32; 2],
+}
+
+// CPU side geometry. All generators produce shapes centered on the origin that
+// fit in a unit cube, with counter-clockwise front faces and y pointing up.
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    // Smooth normals, weighting each face by its area. Used for loaded meshes
+    // that come without normals.
+    pub fn compute_normals(&mut self) {
+        let mut normals = vec![[0.0f32; 3]; self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|k| self.vertices[triangle[k] as usize].position);
+            let ab: [f32; 3] = std::array::from_fn(|k| b[k] - a[k]);
+            let ac: [f32; 3] = std::array::from_fn(|k| c[k] - a[k]);
+            let face_normal = [
+                ab[1] * ac[2] - ab[2] * ac[1],
+                ab[2] * ac[0] - ab[0] * ac[2],
+                ab[0] * ac[1] - ab[1] * ac[0],
+            ];
+            for &index in triangle {
+                for k in 0..3 {
+                    normals[index as usize][k] += face_normal[k];
+                }
+            }
+        }
+
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            let length = normal.iter().map(|n| n * n).sum::<f32>().sqrt();
+            if length > 0.0 {
+                vertex.normal = normal.map(|n| n / length);
+            }
+        }
+    }
+
+    // A single quad in the XY plane facing +Z.
+    pub fn quad() -> Self {
+        let corners = [
+            ([-0.5, -0.5], [0.0, 1.0]),
+            ([0.5, -0.5], [1.0, 1.0]),
+            ([0.5, 0.5], [1.0, 0.0]),
+            ([-0.5, 0.5], [0.0, 0.0]),
+        ];
+        MeshData {
+            vertices: corners
+                .into_iter()
+                .map(|([x, y], uv)| MeshVertex {
+                    position: [x, y, 0.0],
+                    normal: [0.0, 0.0, 1.0],
+                    uv,
+                })
+                .collect(),
+            indices: vec![0, 1, 2, 2, 3, 0],
+        }
+    }
+
+    // A subdivided plane in the XZ plane facing +Y, useful as a floor.
+    pub fn grid(columns: u32, rows: u32) -> Self {
+        let mut mesh = MeshData::default();
+        for j in 0..=rows {
+            for i in 0..=columns {
+                let u = i as f32 / columns as f32;
+                let v = j as f32 / rows as f32;
+                mesh.vertices.push(MeshVertex {
+                    position: [u - 0.5, 0.0, v - 0.5],
+                    normal: [0.0, 1.0, 0.0],
+                    uv: [u, v],
+                });
+            }
+        }
+
+        let stride = columns + 1;
+        for j in 0..rows {
+            for i in 0..columns {
+                let a = j * stride + i;
+                let b = a + 1;
+                let d = a + stride;
+                let c = d + 1;
+                mesh.indices.extend([a, d, c, a, c, b]);
+            }
+        }
+        mesh
+    }
+
+    pub fn cube() -> Self {
+        // (normal, u axis, v axis) with u x v = normal.
+        let faces = [
+            ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+            ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+            ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+            ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+            ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ];
+        let corners = [
+            (-1.0, -1.0, [0.0, 1.0]),
+            (1.0, -1.0, [1.0, 1.0]),
+            (1.0, 1.0, [1.0, 0.0]),
+            (-1.0, 1.0, [0.0, 0.0]),
+        ];
+
+        let mut mesh = MeshData::default();
+        for (normal, u, v) in faces {
+            let base = mesh.vertices.len() as u32;
+            for (su, sv, uv) in corners {
+                let position: [f32; 3] =
+                    std::array::from_fn(|k| 0.5 * (normal[k] + su * u[k] + sv * v[k]));
+                mesh.vertices.push(MeshVertex {
+                    position,
+                    normal,
+                    uv,
+                });
+            }
+            mesh.indices
+                .extend([base, base + 1, base + 2, base + 2, base + 3, base]);
+        }
+        mesh
+    }
+
+    pub fn uv_sphere(segments: u32, rings: u32) -> Self {
+        let mut mesh = MeshData::default();
+        for r in 0..=rings {
+            let theta = PI * r as f32 / rings as f32;
+            for s in 0..=segments {
+                let phi = TAU * s as f32 / segments as f32;
+                let normal = [
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                ];
+                mesh.vertices.push(MeshVertex {
+                    position: normal.map(|n| n * 0.5),
+                    normal,
+                    uv: [s as f32 / segments as f32, r as f32 / rings as f32],
+                });
+            }
+        }
+
+        let stride = segments + 1;
+        for r in 0..rings {
+            for s in 0..segments {
+                let a = r * stride + s;
+                let b = a + stride;
+                mesh.indices.extend([a, a + 1, b, a + 1, b + 1, b]);
+            }
+        }
+        mesh
+    }
+
+    pub fn cylinder(segments: u32) -> Self {
+        let mut mesh = MeshData::default();
+
+        // Side, as pairs of top and bottom vertices.
+        for s in 0..=segments {
+            let phi = TAU * s as f32 / segments as f32;
+            let (sin, cos) = phi.sin_cos();
+            for (y, v) in [(0.5, 0.0), (-0.5, 1.0)] {
+                mesh.vertices.push(MeshVertex {
+                    position: [0.5 * cos, y, 0.5 * sin],
+                    normal: [cos, 0.0, sin],
+                    uv: [s as f32 / segments as f32, v],
+                });
+            }
+        }
+        for s in 0..segments {
+            let top = 2 * s;
+            let bottom = top + 1;
+            mesh.indices
+                .extend([top, top + 2, bottom, top + 2, bottom + 2, bottom]);
+        }
+
+        // Caps, as triangle fans around a center vertex.
+        for y in [0.5f32, -0.5] {
+            let normal = [0.0, y.signum(), 0.0];
+            let center = mesh.vertices.len() as u32;
+            mesh.vertices.push(MeshVertex {
+                position: [0.0, y, 0.0],
+                normal,
+                uv: [0.5, 0.5],
+            });
+            for s in 0..=segments {
+                let phi = TAU * s as f32 / segments as f32;
+                let (sin, cos) = phi.sin_cos();
+                mesh.vertices.push(MeshVertex {
+                    position: [0.5 * cos, y, 0.5 * sin],
+                    normal,
+                    uv: [0.5 + 0.5 * cos, 0.5 + 0.5 * sin],
+                });
+            }
+            for s in 0..segments {
+                let current = center + 1 + s;
+                if y > 0.0 {
+                    mesh.indices.extend([center, current + 1, current]);
+                } else {
+                    mesh.indices.extend([center, current, current + 1]);
+                }
+            }
+        }
+        mesh
+    }
+}
+
+// GPU side geometry. Indices are stored as u16 whenever the vertex count
+// allows it, which halves the index buffer size for most meshes.
+pub struct Mesh {
+    pub vertex_buffer: Subbuffer<[MeshVertex]>,
+    pub index_buffer: IndexBuffer,
+    pub index_count: u32,
+}
+
+impl Mesh {
+    pub fn new(data: MeshData, allocator: &Arc<StandardMemoryAllocator>) -> Self {
+        let index_count = data.indices.len() as u32;
+        let usage = BufferUsage::INDEX_BUFFER;
+        let type_filter = MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE;
+
+        let index_buffer = if data.vertices.len() <= u16::MAX as usize + 1 {
+            IndexBuffer::U16(util::create_buffer(
+                data.indices.into_iter().map(|i| i as u16),
+                allocator,
+                usage,
+                type_filter,
+            ))
+        } else {
+            IndexBuffer::U32(util::create_buffer(
+                data.indices,
+                allocator,
+                usage,
+                type_filter,
+            ))
+        };
+
+        let vertex_buffer = util::create_buffer(
+            data.vertices,
+            allocator,
+            BufferUsage::VERTEX_BUFFER,
+            type_filter,
+        );
+
+        Mesh {
+            vertex_buffer,
+            index_buffer,
+            index_count,
+        }
+    }
+
+    // Expects a graphics pipeline taking `MeshVertex` at binding 0 to already
+    // be bound.
+    pub fn draw<L>(&self, builder: &mut AutoCommandBufferBuilder<L>) {
+        builder
+            .bind_vertex_buffers(0, self.vertex_buffer.clone())
+            .unwrap()
+            .bind_index_buffer(self.index_buffer.clone())
+            .unwrap()
+            .draw_indexed(self.index_count, 1, 0, 0, 0)
+            .unwrap();
+    }
+}
@@ -0,0 +1,251 @@
+use std::path::Path;
+
+use glam::{Mat4, Vec3};
+use image::RgbaImage;
+
+use crate::mesh::{MeshData, MeshVertex};
+
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub base_color: [f32; 4],
+    // Index into `Model::textures`.
+    pub base_color_texture: Option<usize>,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            base_color: [1.0; 4],
+            base_color_texture: None,
+        }
+    }
+}
+
+pub struct ModelPrimitive {
+    pub mesh: MeshData,
+    // Model space to scene space, accumulated from the node hierarchy.
+    pub transform: Mat4,
+    // Index into `Model::materials`, `None` uses the default material.
+    pub material: Option<usize>,
+}
+
+pub struct Model {
+    pub primitives: Vec<ModelPrimitive>,
+    pub materials: Vec<Material>,
+    pub textures: Vec<RgbaImage>,
+}
+
+impl Model {
+    // Loads a Wavefront OBJ (.obj) or glTF 2.0 (.gltf or .glb) file. Textures
+    // are resolved relative to the model file.
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("obj") => load_obj(path),
+            Some("gltf" | "glb") => load_gltf(path),
+            _ => panic!("unsupported model format: {}", path.display()),
+        }
+    }
+
+    // Axis aligned bounds of the whole scene, after node transforms.
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        let mut min = Vec3::splat(f32::INFINITY);
+        let mut max = Vec3::splat(f32::NEG_INFINITY);
+        for primitive in &self.primitives {
+            for vertex in &primitive.mesh.vertices {
+                let position = primitive
+                    .transform
+                    .transform_point3(Vec3::from(vertex.position));
+                min = min.min(position);
+                max = max.max(position);
+            }
+        }
+        (min, max)
+    }
+}
+
+fn load_obj(path: &Path) -> Model {
+    let (models, materials) =
+        tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS).expect("failed to load obj file");
+    // A missing or broken .mtl file only costs us the materials.
+    let obj_materials = materials.unwrap_or_default();
+    let directory = path.parent().unwrap_or(Path::new(""));
+
+    let mut textures = Vec::new();
+    let materials = obj_materials
+        .iter()
+        .map(|material| {
+            let [r, g, b] = material.diffuse.unwrap_or([1.0; 3]);
+            let base_color_texture = material.diffuse_texture.as_ref().map(|file| {
+                let texture = image::open(directory.join(file))
+                    .expect("failed to load texture")
+                    .to_rgba8();
+                textures.push(texture);
+                textures.len() - 1
+            });
+            Material {
+                base_color: [r, g, b, material.dissolve.unwrap_or(1.0)],
+                base_color_texture,
+            }
+        })
+        .collect();
+
+    let primitives = models
+        .into_iter()
+        .map(|model| {
+            let mesh = model.mesh;
+            let vertices = (0..mesh.positions.len() / 3)
+                .map(|i| MeshVertex {
+                    position: [0, 1, 2].map(|k| mesh.positions[3 * i + k]),
+                    normal: if mesh.normals.is_empty() {
+                        [0.0; 3]
+                    } else {
+                        [0, 1, 2].map(|k| mesh.normals[3 * i + k])
+                    },
+                    // OBJ puts the texture origin at the bottom left.
+                    uv: if mesh.texcoords.is_empty() {
+                        [0.0; 2]
+                    } else {
+                        [mesh.texcoords[2 * i], 1.0 - mesh.texcoords[2 * i + 1]]
+                    },
+                })
+                .collect();
+
+            let mut data = MeshData {
+                vertices,
+                indices: mesh.indices,
+            };
+            if mesh.normals.is_empty() {
+                data.compute_normals();
+            }
+
+            ModelPrimitive {
+                mesh: data,
+                transform: Mat4::IDENTITY,
+                material: mesh.material_id,
+            }
+        })
+        .collect();
+
+    Model {
+        primitives,
+        materials,
+        textures,
+    }
+}
+
+fn load_gltf(path: &Path) -> Model {
+    let (document, buffers, images) = gltf::import(path).expect("failed to load gltf file");
+
+    let textures = images.into_iter().map(gltf_image_to_rgba8).collect();
+    let materials = document
+        .materials()
+        .map(|material| {
+            let pbr = material.pbr_metallic_roughness();
+            Material {
+                base_color: pbr.base_color_factor(),
+                base_color_texture: pbr
+                    .base_color_texture()
+                    .map(|info| info.texture().source().index()),
+            }
+        })
+        .collect();
+
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .expect("gltf file has no scenes");
+
+    let mut primitives = Vec::new();
+    for node in scene.nodes() {
+        visit_gltf_node(&node, Mat4::IDENTITY, &buffers, &mut primitives);
+    }
+
+    Model {
+        primitives,
+        materials,
+        textures,
+    }
+}
+
+fn visit_gltf_node(
+    node: &gltf::Node,
+    parent_transform: Mat4,
+    buffers: &[gltf::buffer::Data],
+    primitives: &mut Vec<ModelPrimitive>,
+) {
+    let transform = parent_transform * Mat4::from_cols_array_2d(&node.transform().matrix());
+
+    if let Some(mesh) = node.mesh() {
+        for primitive in mesh.primitives() {
+            // Points and lines would need their own pipelines.
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                continue;
+            }
+
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()][..]));
+            let positions: Vec<[f32; 3]> = reader
+                .read_positions()
+                .expect("primitive has no positions")
+                .collect();
+            let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(|n| n.collect());
+            let uvs: Option<Vec<[f32; 2]>> =
+                reader.read_tex_coords(0).map(|uv| uv.into_f32().collect());
+            let indices = reader
+                .read_indices()
+                .map(|indices| indices.into_u32().collect())
+                .unwrap_or_else(|| (0..positions.len() as u32).collect());
+
+            let vertices = positions
+                .iter()
+                .enumerate()
+                .map(|(i, &position)| MeshVertex {
+                    position,
+                    normal: normals.as_ref().map_or([0.0; 3], |normals| normals[i]),
+                    uv: uvs.as_ref().map_or([0.0; 2], |uvs| uvs[i]),
+                })
+                .collect();
+
+            let mut data = MeshData { vertices, indices };
+            if normals.is_none() {
+                data.compute_normals();
+            }
+
+            primitives.push(ModelPrimitive {
+                mesh: data,
+                transform,
+                material: primitive.material().index(),
+            });
+        }
+    }
+
+    for child in node.children() {
+        visit_gltf_node(&child, transform, buffers, primitives);
+    }
+}
+
+fn gltf_image_to_rgba8(data: gltf::image::Data) -> RgbaImage {
+    use gltf::image::Format;
+
+    let pixels = match data.format {
+        Format::R8G8B8A8 => data.pixels,
+        Format::R8G8B8 => data
+            .pixels
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        Format::R8G8 => data
+            .pixels
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[1], 0, 255])
+            .collect(),
+        Format::R8 => data.pixels.iter().flat_map(|&p| [p, p, p, 255]).collect(),
+        format => panic!("unsupported texture format {format:?}"),
+    };
+    RgbaImage::from_raw(data.width, data.height, pixels).unwrap()
+}
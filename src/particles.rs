@@ -0,0 +1,460 @@
+use std::sync::Arc;
+
+use glam::Mat4;
+use image::{ImageBuffer, RgbaImage};
+use vulkano::buffer::{BufferContents, BufferUsage, Subbuffer};
+use vulkano::command_buffer::allocator::{
+    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+};
+use vulkano::command_buffer::{
+    CopyImageToBufferInfo, RenderPassBeginInfo, SubpassBeginInfo, SubpassContents, SubpassEndInfo,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::device::{DeviceOwned, Queue};
+use vulkano::format::Format;
+use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::input_assembly::{InputAssemblyState, PrimitiveTopology};
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::RasterizationState;
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::pipeline::graphics::viewport::ViewportState;
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{
+    ComputePipeline, DynamicState, GraphicsPipeline, Pipeline, PipelineLayout,
+    PipelineShaderStageCreateInfo,
+};
+use vulkano::render_pass::Subpass;
+
+use crate::blend::{self, BlendMode};
+use crate::render_target::RenderTarget;
+use crate::util;
+
+// Must match the workgroup size and the tile size in the shader.
+const WORKGROUP_SIZE: u32 = 256;
+
+// The same buffer is simulated in compute and drawn as a point list, so a
+// particle is also a vertex.
+#[derive(BufferContents, Vertex, Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct Particle {
+    // xyz is the position, w the mass.
+    #[format(R32G32B32A32_SFLOAT)]
+    pub position: [f32; 4],
+    #[format(R32G32B32A32_SFLOAT)]
+    pub velocity: [f32; 4],
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Gravity {
+    pub g: f32,
+    // Keeps close encounters finite, forces act as if the distance were
+    // sqrt(r^2 + softening^2).
+    pub softening: f32,
+}
+
+impl Gravity {
+    // Kinetic plus potential energy, summed in double precision on the CPU.
+    // The potential matches the softened force the shader integrates.
+    pub fn energy(&self, particles: &[Particle]) -> f64 {
+        let softening2 = self.softening as f64 * self.softening as f64;
+        let mut energy = 0.0;
+        for (i, p) in particles.iter().enumerate() {
+            let [vx, vy, vz, _] = p.velocity.map(|c| c as f64);
+            let mass = p.position[3] as f64;
+            energy += 0.5 * mass * (vx * vx + vy * vy + vz * vz);
+
+            for q in &particles[i + 1..] {
+                let d: [f64; 3] = std::array::from_fn(|k| (q.position[k] - p.position[k]) as f64);
+                let r = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2] + softening2).sqrt();
+                energy -= self.g as f64 * mass * q.position[3] as f64 / r;
+            }
+        }
+        energy
+    }
+}
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct SimulationParams {
+    count: u32,
+    dt: f32,
+    g: f32,
+    softening2: f32,
+}
+
+mod simulate {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 256, local_size_y = 1, local_size_z = 1) in;
+
+            struct Particle {
+                vec4 position;
+                vec4 velocity;
+            };
+
+            layout(set = 0, binding = 0) readonly buffer Input {
+                Particle particles[];
+            } src;
+
+            layout(set = 0, binding = 1) buffer Output {
+                Particle particles[];
+            } dst;
+
+            layout(push_constant) uniform Params {
+                uint count;
+                float dt;
+                float g;
+                float softening2;
+            } params;
+
+            const uint TILE = 256;
+
+            // Position and mass of one tile of bodies, loaded once and read
+            // by the whole workgroup.
+            shared vec4 tile[TILE];
+
+            void main() {
+                uint block = gl_WorkGroupID.y * gl_NumWorkGroups.x + gl_WorkGroupID.x;
+                uint i = block * gl_WorkGroupSize.x + gl_LocalInvocationID.x;
+                bool active = i < params.count;
+                vec4 p = active ? src.particles[i].position : vec4(0.0);
+
+                // Every invocation helps loading the tiles, also the ones past
+                // the end, so nobody skips a barrier.
+                vec3 acceleration = vec3(0.0);
+                for (uint base = 0; base < params.count; base += TILE) {
+                    uint j = base + gl_LocalInvocationID.x;
+                    tile[gl_LocalInvocationID.x] = j < params.count ? src.particles[j].position : vec4(0.0);
+                    barrier();
+
+                    // The padding and the body itself are skipped, with zero
+                    // softening their zero distance would give NaN.
+                    uint tile_count = min(TILE, params.count - base);
+                    for (uint k = 0; k < tile_count; k++) {
+                        if (base + k == i) {
+                            continue;
+                        }
+                        vec3 d = tile[k].xyz - p.xyz;
+                        float r2 = dot(d, d) + params.softening2;
+                        acceleration += tile[k].w * d * inversesqrt(r2 * r2 * r2);
+                    }
+                    barrier();
+                }
+
+                if (active) {
+                    // Semi-implicit Euler, the velocity first and the position
+                    // with the new velocity. It is symplectic, so the energy
+                    // oscillates instead of drifting.
+                    vec3 v = src.particles[i].velocity.xyz + params.g * acceleration * params.dt;
+                    dst.particles[i].velocity = vec4(v, 0.0);
+                    dst.particles[i].position = vec4(p.xyz + v * params.dt, p.w);
+                }
+            }
+        ",
+    }
+}
+
+// All-pairs gravitational N-body simulation. Each step reads one buffer and
+// writes the other, the bodies all have to see the same old positions.
+pub struct NBody {
+    queue: Arc<Queue>,
+    descriptor_set_allocator: StandardDescriptorSetAllocator,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+    pipeline: Arc<ComputePipeline>,
+    buffers: [Subbuffer<[Particle]>; 2],
+    gravity: Gravity,
+}
+
+impl NBody {
+    pub fn new(
+        queue: Arc<Queue>,
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        particles: &[Particle],
+        gravity: Gravity,
+    ) -> Self {
+        let device = queue.device().clone();
+        let shader = simulate::load(device.clone()).expect("failed to create shader module");
+        let pipeline = util::create_compute_pipeline(device.clone(), shader);
+
+        let buffer = || {
+            util::create_buffer(
+                particles.iter().copied(),
+                memory_allocator,
+                BufferUsage::STORAGE_BUFFER | BufferUsage::VERTEX_BUFFER,
+                MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+            )
+        };
+        let buffers = [buffer(), buffer()];
+
+        let descriptor_set_allocator =
+            StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+        let command_buffer_allocator = StandardCommandBufferAllocator::new(
+            device.clone(),
+            StandardCommandBufferAllocatorCreateInfo::default(),
+        );
+
+        NBody {
+            queue,
+            descriptor_set_allocator,
+            command_buffer_allocator,
+            pipeline,
+            buffers,
+            gravity,
+        }
+    }
+
+    pub fn step(&mut self, steps: u32, dt: f32) {
+        let count = self.buffers[0].len() as u32;
+        let params = SimulationParams {
+            count,
+            dt,
+            g: self.gravity.g,
+            softening2: self.gravity.softening * self.gravity.softening,
+        };
+
+        let mut builder = util::begin_one_time(&self.command_buffer_allocator, &self.queue);
+        for _ in 0..steps {
+            util::dispatch_storage(
+                &mut builder,
+                &self.pipeline,
+                [
+                    self.buffers[0].as_bytes().clone(),
+                    self.buffers[1].as_bytes().clone(),
+                ],
+                params,
+                count.div_ceil(WORKGROUP_SIZE),
+                &self.descriptor_set_allocator,
+            );
+            self.buffers.swap(0, 1);
+        }
+
+        util::submit_and_wait(builder, &self.queue);
+    }
+
+    // The current state, usable as a vertex buffer.
+    pub fn particles(&self) -> &Subbuffer<[Particle]> {
+        &self.buffers[0]
+    }
+
+    pub fn gravity(&self) -> Gravity {
+        self.gravity
+    }
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec4 position;
+            layout(location = 1) in vec4 velocity;
+
+            layout(location = 0) out vec3 v_color;
+
+            layout(push_constant) uniform Params {
+                mat4 view_projection;
+                float max_speed;
+            } params;
+
+            void main() {
+                gl_Position = params.view_projection * vec4(position.xyz, 1.0);
+                // Points bigger than a pixel need the large_points feature.
+                gl_PointSize = 1.0;
+                // Slow bodies are red, fast ones blue-white.
+                float t = clamp(length(velocity.xyz) / params.max_speed, 0.0, 1.0);
+                v_color = mix(vec3(1.0, 0.4, 0.1), vec3(0.6, 0.8, 1.0), t);
+            }
+        ",
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec3 v_color;
+
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                f_color = vec4(v_color, 0.5);
+            }
+        ",
+    }
+}
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct DrawParams {
+    view_projection: [[f32; 4]; 4],
+    max_speed: f32,
+}
+
+// Draws particles as additively blended points into an offscreen target.
+pub struct ParticleRenderer {
+    queue: Arc<Queue>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+    pipeline: Arc<GraphicsPipeline>,
+    target: RenderTarget,
+}
+
+impl ParticleRenderer {
+    pub fn new(
+        queue: Arc<Queue>,
+        memory_allocator: Arc<StandardMemoryAllocator>,
+        extent: [u32; 2],
+    ) -> Self {
+        let device = queue.device().clone();
+        let render_pass = vulkano::single_pass_renderpass!(
+            device.clone(),
+            attachments: {
+                color: {
+                    format: Format::R8G8B8A8_UNORM,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: Store,
+                },
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {},
+            },
+        )
+        .unwrap();
+        let target = RenderTarget::new(render_pass.clone(), memory_allocator.clone(), extent);
+
+        let vs = vs::load(device.clone()).expect("failed to create shader module");
+        let fs = fs::load(device.clone()).expect("failed to create shader module");
+        let pipeline = {
+            let vs = vs.entry_point("main").unwrap();
+            let fs = fs.entry_point("main").unwrap();
+
+            let vertex_input_state = Particle::per_vertex()
+                .definition(&vs.info().input_interface)
+                .unwrap();
+
+            let stages = [
+                PipelineShaderStageCreateInfo::new(vs),
+                PipelineShaderStageCreateInfo::new(fs),
+            ];
+
+            let layout = PipelineLayout::new(
+                device.clone(),
+                PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+                    .into_pipeline_layout_create_info(device.clone())
+                    .unwrap(),
+            )
+            .unwrap();
+
+            let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+
+            // No depth buffer, overlapping points just add up.
+            GraphicsPipeline::new(
+                device.clone(),
+                None,
+                GraphicsPipelineCreateInfo {
+                    stages: stages.into_iter().collect(),
+                    vertex_input_state: Some(vertex_input_state),
+                    input_assembly_state: Some(InputAssemblyState {
+                        topology: PrimitiveTopology::PointList,
+                        ..Default::default()
+                    }),
+                    viewport_state: Some(ViewportState::default()),
+                    rasterization_state: Some(RasterizationState::default()),
+                    multisample_state: Some(MultisampleState::default()),
+                    color_blend_state: Some(blend::color_blend_state(&[BlendMode::Additive])),
+                    dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
+                        .into_iter()
+                        .collect(),
+                    subpass: Some(subpass.into()),
+                    ..GraphicsPipelineCreateInfo::layout(layout)
+                },
+            )
+            .unwrap()
+        };
+
+        let command_buffer_allocator = StandardCommandBufferAllocator::new(
+            device.clone(),
+            StandardCommandBufferAllocatorCreateInfo::default(),
+        );
+
+        ParticleRenderer {
+            queue,
+            memory_allocator,
+            command_buffer_allocator,
+            pipeline,
+            target,
+        }
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.target.aspect_ratio()
+    }
+
+    // `max_speed` is the speed that gets the hottest color.
+    pub fn render(
+        &self,
+        particles: &Subbuffer<[Particle]>,
+        view_projection: Mat4,
+        max_speed: f32,
+    ) -> RgbaImage {
+        let [width, height] = self.target.extent();
+        let buf = util::create_buffer(
+            (0..width * height * 4).map(|_| 0u8),
+            &self.memory_allocator,
+            BufferUsage::TRANSFER_DST,
+            MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+        );
+
+        let mut builder = util::begin_one_time(&self.command_buffer_allocator, &self.queue);
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![Some([0.0, 0.0, 0.0, 1.0].into())],
+                    ..RenderPassBeginInfo::framebuffer(self.target.framebuffer().clone())
+                },
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .unwrap();
+        self.target.set_full_viewport(&mut builder);
+        builder
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                DrawParams {
+                    view_projection: view_projection.to_cols_array_2d(),
+                    max_speed,
+                },
+            )
+            .unwrap()
+            .bind_vertex_buffers(0, particles.clone())
+            .unwrap()
+            .draw(particles.len() as u32, 1, 0, 0)
+            .unwrap()
+            .end_render_pass(SubpassEndInfo::default())
+            .unwrap()
+            .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+                self.target.attachment(0).clone(),
+                buf.clone(),
+            ))
+            .unwrap();
+
+        util::submit_and_wait(builder, &self.queue);
+
+        let buffer_content = buf.read().unwrap();
+        ImageBuffer::from_raw(width, height, buffer_content.to_vec()).unwrap()
+    }
+}
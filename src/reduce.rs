@@ -0,0 +1,535 @@
+use std::sync::Arc;
+
+use vulkano::buffer::{BufferContents, Subbuffer};
+use vulkano::command_buffer::allocator::{
+    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::device::physical::{PhysicalDevice, SubgroupFeatures};
+use vulkano::device::{DeviceOwned, Queue};
+use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::{ComputePipeline, Pipeline, PipelineBindPoint};
+use vulkano::shader::ShaderStages;
+use vulkano::Version;
+
+use crate::util;
+
+// Must match the workgroup size and ITEMS_PER_THREAD in the shaders.
+const WORKGROUP_SIZE: u32 = 256;
+const ITEMS_PER_WORKGROUP: u32 = WORKGROUP_SIZE * 4;
+
+// Element types the shaders know how to compare and add. They all travel
+// through the shaders as raw 32 bit words.
+pub trait Element: BufferContents + Copy {
+    const KIND: u32;
+
+    fn from_bits(bits: u32) -> Self;
+
+    fn to_bits(self) -> u32;
+}
+
+impl Element for u32 {
+    const KIND: u32 = 0;
+
+    fn from_bits(bits: u32) -> Self {
+        bits
+    }
+
+    fn to_bits(self) -> u32 {
+        self
+    }
+}
+
+impl Element for i32 {
+    const KIND: u32 = 1;
+
+    fn from_bits(bits: u32) -> Self {
+        bits as i32
+    }
+
+    fn to_bits(self) -> u32 {
+        self as u32
+    }
+}
+
+impl Element for f32 {
+    const KIND: u32 = 2;
+
+    fn from_bits(bits: u32) -> Self {
+        f32::from_bits(bits)
+    }
+
+    fn to_bits(self) -> u32 {
+        f32::to_bits(self)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ReduceOp {
+    Sum,
+    Min,
+    Max,
+    // The index of the first maximum together with its value.
+    ArgMax,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReduceMethod {
+    // A tree reduction in shared memory, works everywhere.
+    SharedMemory,
+    // Reduces within each subgroup first, so only one value per subgroup
+    // goes through shared memory.
+    Subgroup,
+}
+
+impl ReduceMethod {
+    pub fn best(physical_device: &PhysicalDevice) -> Self {
+        let properties = physical_device.properties();
+        let operations = properties.subgroup_supported_operations.unwrap_or_default();
+        let stages = properties.subgroup_supported_stages.unwrap_or_default();
+        if physical_device.api_version() >= Version::V1_1
+            && operations.contains(SubgroupFeatures::BASIC | SubgroupFeatures::ARITHMETIC)
+            && stages.contains(ShaderStages::COMPUTE)
+        {
+            ReduceMethod::Subgroup
+        } else {
+            ReduceMethod::SharedMemory
+        }
+    }
+}
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct Params {
+    count: u32,
+    op: u32,
+    kind: u32,
+    first_pass: u32,
+}
+
+mod shared_memory {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 256, local_size_y = 1, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0) readonly buffer Input {
+                uint data[];
+            } src;
+
+            layout(set = 0, binding = 1) buffer Output {
+                uint data[];
+            } dst;
+
+            layout(push_constant) uniform Params {
+                uint count;
+                uint op;
+                uint kind;
+                uint first_pass;
+            } params;
+
+            const uint OP_SUM = 0;
+            const uint OP_MIN = 1;
+            const uint OP_MAX = 2;
+            const uint OP_ARGMAX = 3;
+
+            const uint KIND_UINT = 0;
+            const uint KIND_INT = 1;
+            const uint KIND_FLOAT = 2;
+
+            const uint ITEMS_PER_THREAD = 4;
+            const uint NO_INDEX = 0xffffffffu;
+
+            shared uint shared_values[256];
+            shared uint shared_indices[256];
+
+            bool less(uint a, uint b) {
+                if (params.kind == KIND_FLOAT) {
+                    return uintBitsToFloat(a) < uintBitsToFloat(b);
+                } else if (params.kind == KIND_INT) {
+                    return int(a) < int(b);
+                }
+                return a < b;
+            }
+
+            uint identity() {
+                if (params.op == OP_SUM) {
+                    return 0u;
+                } else if (params.op == OP_MIN) {
+                    return params.kind == KIND_FLOAT ? 0x7f800000u
+                        : params.kind == KIND_INT ? 0x7fffffffu : 0xffffffffu;
+                }
+                return params.kind == KIND_FLOAT ? 0xff800000u
+                    : params.kind == KIND_INT ? 0x80000000u : 0u;
+            }
+
+            // x is the value, y the index it came from. The index is only
+            // meaningful for argmax.
+            uvec2 combine(uvec2 a, uvec2 b) {
+                if (params.op == OP_SUM) {
+                    // Two's complement makes int and uint addition identical.
+                    uint sum = params.kind == KIND_FLOAT
+                        ? floatBitsToUint(uintBitsToFloat(a.x) + uintBitsToFloat(b.x))
+                        : a.x + b.x;
+                    return uvec2(sum, 0);
+                } else if (params.op == OP_MIN) {
+                    return less(b.x, a.x) ? b : a;
+                } else if (params.op == OP_MAX) {
+                    return less(a.x, b.x) ? b : a;
+                }
+                // Ties go to the lower index so the result is the first maximum.
+                return less(a.x, b.x) || (a.x == b.x && b.y < a.y) ? b : a;
+            }
+
+            uvec2 load(uint i) {
+                if (i >= params.count) {
+                    return uvec2(identity(), NO_INDEX);
+                }
+                // After the first pass argmax carries (value, index) pairs.
+                if (params.op == OP_ARGMAX && params.first_pass == 0) {
+                    return uvec2(src.data[2 * i], src.data[2 * i + 1]);
+                }
+                return uvec2(src.data[i], i);
+            }
+
+            void main() {
+                uint lid = gl_LocalInvocationID.x;
+                uint chunk = gl_WorkGroupSize.x * ITEMS_PER_THREAD;
+
+                // Workgroups stride over the input when there are more chunks
+                // than workgroups.
+                uvec2 acc = uvec2(identity(), NO_INDEX);
+                for (uint base = gl_WorkGroupID.x * chunk; base < params.count; base += gl_NumWorkGroups.x * chunk) {
+                    for (uint k = 0; k < ITEMS_PER_THREAD; k++) {
+                        acc = combine(acc, load(base + k * gl_WorkGroupSize.x + lid));
+                    }
+                }
+
+                shared_values[lid] = acc.x;
+                shared_indices[lid] = acc.y;
+                barrier();
+
+                for (uint stride = gl_WorkGroupSize.x / 2; stride > 0; stride /= 2) {
+                    if (lid < stride) {
+                        acc = combine(acc, uvec2(shared_values[lid + stride], shared_indices[lid + stride]));
+                        shared_values[lid] = acc.x;
+                        shared_indices[lid] = acc.y;
+                    }
+                    barrier();
+                }
+
+                if (lid == 0) {
+                    if (params.op == OP_ARGMAX) {
+                        dst.data[2 * gl_WorkGroupID.x] = acc.x;
+                        dst.data[2 * gl_WorkGroupID.x + 1] = acc.y;
+                    } else {
+                        dst.data[gl_WorkGroupID.x] = acc.x;
+                    }
+                }
+            }
+        ",
+    }
+}
+
+mod subgroup {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        vulkan_version: "1.1",
+        src: r"
+            #version 460
+            #extension GL_KHR_shader_subgroup_basic : enable
+            #extension GL_KHR_shader_subgroup_arithmetic : enable
+
+            layout(local_size_x = 256, local_size_y = 1, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0) readonly buffer Input {
+                uint data[];
+            } src;
+
+            layout(set = 0, binding = 1) buffer Output {
+                uint data[];
+            } dst;
+
+            layout(push_constant) uniform Params {
+                uint count;
+                uint op;
+                uint kind;
+                uint first_pass;
+            } params;
+
+            const uint OP_SUM = 0;
+            const uint OP_MIN = 1;
+            const uint OP_MAX = 2;
+            const uint OP_ARGMAX = 3;
+
+            const uint KIND_UINT = 0;
+            const uint KIND_INT = 1;
+            const uint KIND_FLOAT = 2;
+
+            const uint ITEMS_PER_THREAD = 4;
+            const uint NO_INDEX = 0xffffffffu;
+
+            // One slot per subgroup, sized for the smallest possible subgroup.
+            shared uint shared_values[256];
+            shared uint shared_indices[256];
+
+            bool less(uint a, uint b) {
+                if (params.kind == KIND_FLOAT) {
+                    return uintBitsToFloat(a) < uintBitsToFloat(b);
+                } else if (params.kind == KIND_INT) {
+                    return int(a) < int(b);
+                }
+                return a < b;
+            }
+
+            uint identity() {
+                if (params.op == OP_SUM) {
+                    return 0u;
+                } else if (params.op == OP_MIN) {
+                    return params.kind == KIND_FLOAT ? 0x7f800000u
+                        : params.kind == KIND_INT ? 0x7fffffffu : 0xffffffffu;
+                }
+                return params.kind == KIND_FLOAT ? 0xff800000u
+                    : params.kind == KIND_INT ? 0x80000000u : 0u;
+            }
+
+            uvec2 combine(uvec2 a, uvec2 b) {
+                if (params.op == OP_SUM) {
+                    uint sum = params.kind == KIND_FLOAT
+                        ? floatBitsToUint(uintBitsToFloat(a.x) + uintBitsToFloat(b.x))
+                        : a.x + b.x;
+                    return uvec2(sum, 0);
+                } else if (params.op == OP_MIN) {
+                    return less(b.x, a.x) ? b : a;
+                } else if (params.op == OP_MAX) {
+                    return less(a.x, b.x) ? b : a;
+                }
+                return less(a.x, b.x) || (a.x == b.x && b.y < a.y) ? b : a;
+            }
+
+            uint subgroup_sum(uint v) {
+                return params.kind == KIND_FLOAT
+                    ? floatBitsToUint(subgroupAdd(uintBitsToFloat(v)))
+                    : subgroupAdd(v);
+            }
+
+            uint subgroup_min(uint v) {
+                if (params.kind == KIND_FLOAT) {
+                    return floatBitsToUint(subgroupMin(uintBitsToFloat(v)));
+                } else if (params.kind == KIND_INT) {
+                    return uint(subgroupMin(int(v)));
+                }
+                return subgroupMin(v);
+            }
+
+            uint subgroup_max(uint v) {
+                if (params.kind == KIND_FLOAT) {
+                    return floatBitsToUint(subgroupMax(uintBitsToFloat(v)));
+                } else if (params.kind == KIND_INT) {
+                    return uint(subgroupMax(int(v)));
+                }
+                return subgroupMax(v);
+            }
+
+            // Every invocation of the subgroup gets the combined result.
+            uvec2 subgroup_combine(uvec2 v) {
+                if (params.op == OP_SUM) {
+                    return uvec2(subgroup_sum(v.x), 0);
+                } else if (params.op == OP_MIN) {
+                    return uvec2(subgroup_min(v.x), 0);
+                }
+                uint m = subgroup_max(v.x);
+                if (params.op == OP_MAX) {
+                    return uvec2(m, 0);
+                }
+                return uvec2(m, subgroupMin(v.x == m ? v.y : NO_INDEX));
+            }
+
+            uvec2 load(uint i) {
+                if (i >= params.count) {
+                    return uvec2(identity(), NO_INDEX);
+                }
+                if (params.op == OP_ARGMAX && params.first_pass == 0) {
+                    return uvec2(src.data[2 * i], src.data[2 * i + 1]);
+                }
+                return uvec2(src.data[i], i);
+            }
+
+            void main() {
+                uint chunk = gl_WorkGroupSize.x * ITEMS_PER_THREAD;
+
+                uvec2 acc = uvec2(identity(), NO_INDEX);
+                for (uint base = gl_WorkGroupID.x * chunk; base < params.count; base += gl_NumWorkGroups.x * chunk) {
+                    for (uint k = 0; k < ITEMS_PER_THREAD; k++) {
+                        acc = combine(acc, load(base + k * gl_WorkGroupSize.x + gl_LocalInvocationID.x));
+                    }
+                }
+
+                acc = subgroup_combine(acc);
+                if (subgroupElect()) {
+                    shared_values[gl_SubgroupID] = acc.x;
+                    shared_indices[gl_SubgroupID] = acc.y;
+                }
+                barrier();
+
+                // The first subgroup combines the per-subgroup results. There
+                // can be more of them than it has invocations.
+                if (gl_SubgroupID == 0) {
+                    acc = uvec2(identity(), NO_INDEX);
+                    for (uint i = gl_SubgroupInvocationID; i < gl_NumSubgroups; i += gl_SubgroupSize) {
+                        acc = combine(acc, uvec2(shared_values[i], shared_indices[i]));
+                    }
+                    acc = subgroup_combine(acc);
+
+                    if (subgroupElect()) {
+                        if (params.op == OP_ARGMAX) {
+                            dst.data[2 * gl_WorkGroupID.x] = acc.x;
+                            dst.data[2 * gl_WorkGroupID.x + 1] = acc.y;
+                        } else {
+                            dst.data[gl_WorkGroupID.x] = acc.x;
+                        }
+                    }
+                }
+            }
+        ",
+    }
+}
+
+// Reduces storage buffers to a single value. Each pass shrinks the input to
+// one partial result per workgroup until a single workgroup is left.
+pub struct Reducer {
+    queue: Arc<Queue>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    descriptor_set_allocator: StandardDescriptorSetAllocator,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+    pipeline: Arc<ComputePipeline>,
+    max_workgroups: u32,
+}
+
+impl Reducer {
+    pub fn new(
+        queue: Arc<Queue>,
+        memory_allocator: Arc<StandardMemoryAllocator>,
+        method: ReduceMethod,
+    ) -> Self {
+        let device = queue.device().clone();
+        let shader = match method {
+            ReduceMethod::SharedMemory => shared_memory::load(device.clone()),
+            ReduceMethod::Subgroup => subgroup::load(device.clone()),
+        }
+        .expect("failed to create shader module");
+        let pipeline = util::create_compute_pipeline(device.clone(), shader);
+
+        let max_workgroups = device
+            .physical_device()
+            .properties()
+            .max_compute_work_group_count[0];
+
+        let descriptor_set_allocator =
+            StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+        let command_buffer_allocator = StandardCommandBufferAllocator::new(
+            device.clone(),
+            StandardCommandBufferAllocatorCreateInfo::default(),
+        );
+
+        Reducer {
+            queue,
+            memory_allocator,
+            descriptor_set_allocator,
+            command_buffer_allocator,
+            pipeline,
+            max_workgroups,
+        }
+    }
+
+    // Integer sums wrap around like `wrapping_add`.
+    pub fn sum<T: Element>(&self, input: &Subbuffer<[T]>) -> T {
+        self.reduce(input, ReduceOp::Sum).0
+    }
+
+    pub fn min<T: Element>(&self, input: &Subbuffer<[T]>) -> T {
+        self.reduce(input, ReduceOp::Min).0
+    }
+
+    pub fn max<T: Element>(&self, input: &Subbuffer<[T]>) -> T {
+        self.reduce(input, ReduceOp::Max).0
+    }
+
+    // Returns the index of the first maximum and the maximum itself.
+    pub fn argmax<T: Element>(&self, input: &Subbuffer<[T]>) -> (u32, T) {
+        let (value, index) = self.reduce(input, ReduceOp::ArgMax);
+        (index, value)
+    }
+
+    // `input` needs STORAGE_BUFFER usage. Returns the value and, for argmax,
+    // its index.
+    fn reduce<T: Element>(&self, input: &Subbuffer<[T]>, op: ReduceOp) -> (T, u32) {
+        assert_eq!(size_of::<T>(), 4, "only 32 bit elements are supported");
+        let words_per_result = if op == ReduceOp::ArgMax { 2 } else { 1 };
+
+        let mut builder = util::begin_one_time(&self.command_buffer_allocator, &self.queue);
+        builder
+            .bind_pipeline_compute(self.pipeline.clone())
+            .unwrap();
+
+        let mut src = input.as_bytes().clone();
+        let mut count = input.len() as u32;
+        let mut first_pass = true;
+        let result = loop {
+            let workgroups = count.div_ceil(ITEMS_PER_WORKGROUP).min(self.max_workgroups);
+            let last_pass = workgroups == 1;
+
+            // Only the final result is read on the host.
+            let dst = util::create_storage_buffer::<u32>(
+                &self.memory_allocator,
+                (workgroups * words_per_result) as u64,
+                if last_pass {
+                    MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS
+                } else {
+                    MemoryTypeFilter::PREFER_DEVICE
+                },
+            );
+
+            let descriptor_set = util::create_storage_descriptor_set(
+                &self.pipeline,
+                [src, dst.as_bytes().clone()],
+                &self.descriptor_set_allocator,
+            );
+
+            let params = Params {
+                count,
+                op: op as u32,
+                kind: T::KIND,
+                first_pass: first_pass as u32,
+            };
+
+            builder
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Compute,
+                    self.pipeline.layout().clone(),
+                    0,
+                    descriptor_set,
+                )
+                .unwrap()
+                .push_constants(self.pipeline.layout().clone(), 0, params)
+                .unwrap()
+                .dispatch([workgroups, 1, 1])
+                .unwrap();
+
+            if last_pass {
+                break dst;
+            }
+            src = dst.into_bytes();
+            count = workgroups;
+            first_pass = false;
+        };
+
+        util::submit_and_wait(builder, &self.queue);
+
+        let result = result.read().unwrap();
+        let index = if op == ReduceOp::ArgMax { result[1] } else { 0 };
+        (T::from_bits(result[0]), index)
+    }
+}
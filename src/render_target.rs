@@ -0,0 +1,148 @@
+use std::sync::Arc;
+
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageAspects, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::viewport::{Scissor, Viewport};
+use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass};
+
+// The images and framebuffer for one render pass, sized together. Pipelines
+// only depend on the render pass, so as long as they use a dynamic viewport
+// and scissor they keep working after `resize`.
+pub struct RenderTarget {
+    render_pass: Arc<RenderPass>,
+    allocator: Arc<StandardMemoryAllocator>,
+    extent: [u32; 2],
+    attachments: Vec<Arc<Image>>,
+    framebuffer: Arc<Framebuffer>,
+}
+
+impl RenderTarget {
+    // Creates one image per attachment of `render_pass`, matching its format
+    // and sample count.
+    pub fn new(
+        render_pass: Arc<RenderPass>,
+        allocator: Arc<StandardMemoryAllocator>,
+        extent: [u32; 2],
+    ) -> Self {
+        let (attachments, framebuffer) = create_attachments(&render_pass, &allocator, extent);
+        RenderTarget {
+            render_pass,
+            allocator,
+            extent,
+            attachments,
+            framebuffer,
+        }
+    }
+
+    // Throws away the old images, their contents are lost.
+    pub fn resize(&mut self, extent: [u32; 2]) {
+        if extent == self.extent {
+            return;
+        }
+        let (attachments, framebuffer) =
+            create_attachments(&self.render_pass, &self.allocator, extent);
+        self.extent = extent;
+        self.attachments = attachments;
+        self.framebuffer = framebuffer;
+    }
+
+    pub fn extent(&self) -> [u32; 2] {
+        self.extent
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.extent[0] as f32 / self.extent[1] as f32
+    }
+
+    pub fn attachment(&self, index: usize) -> &Arc<Image> {
+        &self.attachments[index]
+    }
+
+    pub fn framebuffer(&self) -> &Arc<Framebuffer> {
+        &self.framebuffer
+    }
+
+    pub fn viewport(&self) -> Viewport {
+        Viewport {
+            offset: [0.0, 0.0],
+            extent: [self.extent[0] as f32, self.extent[1] as f32],
+            depth_range: 0.0..=1.0,
+        }
+    }
+
+    pub fn scissor(&self) -> Scissor {
+        Scissor {
+            offset: [0, 0],
+            extent: self.extent,
+        }
+    }
+
+    // Covers the whole target. Has to be recorded after binding a pipeline
+    // with a dynamic viewport and scissor, before the first draw.
+    pub fn set_full_viewport<L>(&self, builder: &mut AutoCommandBufferBuilder<L>) {
+        builder
+            .set_viewport(0, [self.viewport()].into_iter().collect())
+            .unwrap()
+            .set_scissor(0, [self.scissor()].into_iter().collect())
+            .unwrap();
+    }
+}
+
+fn create_attachments(
+    render_pass: &Arc<RenderPass>,
+    allocator: &Arc<StandardMemoryAllocator>,
+    extent: [u32; 2],
+) -> (Vec<Arc<Image>>, Arc<Framebuffer>) {
+    let attachments: Vec<_> = render_pass
+        .attachments()
+        .iter()
+        .map(|description| {
+            Image::new(
+                allocator.clone(),
+                ImageCreateInfo {
+                    image_type: ImageType::Dim2d,
+                    format: description.format,
+                    extent: [extent[0], extent[1], 1],
+                    samples: description.samples,
+                    usage: attachment_usage(description.format),
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        })
+        .collect();
+
+    let framebuffer = Framebuffer::new(
+        render_pass.clone(),
+        FramebufferCreateInfo {
+            attachments: attachments
+                .iter()
+                .map(|image| ImageView::new_default(image.clone()).unwrap())
+                .collect(),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    (attachments, framebuffer)
+}
+
+// Color attachments can be copied out and sampled by later passes. Depth is
+// only used during the pass.
+fn attachment_usage(format: Format) -> ImageUsage {
+    if format
+        .aspects()
+        .intersects(ImageAspects::DEPTH | ImageAspects::STENCIL)
+    {
+        ImageUsage::DEPTH_STENCIL_ATTACHMENT
+    } else {
+        ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC | ImageUsage::SAMPLED
+    }
+}
@@ -0,0 +1,515 @@
+use std::sync::Arc;
+
+use vulkano::buffer::{BufferContents, Subbuffer};
+use vulkano::command_buffer::allocator::{
+    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+};
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::device::{DeviceOwned, Queue};
+use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::ComputePipeline;
+
+use crate::reduce::Element;
+use crate::util;
+
+// Must match the workgroup size and ITEMS_PER_THREAD in the shaders.
+const WORKGROUP_SIZE: u32 = 256;
+const ITEMS_PER_WORKGROUP: u32 = WORKGROUP_SIZE * 4;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Predicate<T> {
+    NonZero,
+    Equal(T),
+    NotEqual(T),
+    Less(T),
+    Greater(T),
+}
+
+impl<T: Element> Predicate<T> {
+    // The op code and operand bits for the flag shader.
+    fn encode(self) -> (u32, u32) {
+        match self {
+            Predicate::NonZero => (0, 0),
+            Predicate::Equal(v) => (1, v.to_bits()),
+            Predicate::NotEqual(v) => (2, v.to_bits()),
+            Predicate::Less(v) => (3, v.to_bits()),
+            Predicate::Greater(v) => (4, v.to_bits()),
+        }
+    }
+}
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct ScanParams {
+    count: u32,
+    kind: u32,
+    exclusive: u32,
+}
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct FlagParams {
+    count: u32,
+    kind: u32,
+    op: u32,
+    operand: u32,
+}
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct ScatterParams {
+    count: u32,
+}
+
+// Scans each block of ITEMS_PER_WORKGROUP elements on its own and writes the
+// block's total to `block_sums`.
+mod scan_blocks {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 256, local_size_y = 1, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0) readonly buffer Input {
+                uint data[];
+            } src;
+
+            layout(set = 0, binding = 1) buffer Output {
+                uint data[];
+            } dst;
+
+            layout(set = 0, binding = 2) buffer BlockSums {
+                uint data[];
+            } block_sums;
+
+            layout(push_constant) uniform Params {
+                uint count;
+                uint kind;
+                uint exclusive;
+            } params;
+
+            const uint KIND_FLOAT = 2;
+            const uint ITEMS_PER_THREAD = 4;
+
+            shared uint totals[256];
+
+            // 0 is also the bit pattern of 0.0, so it works as the identity
+            // for every kind.
+            uint add(uint a, uint b) {
+                return params.kind == KIND_FLOAT
+                    ? floatBitsToUint(uintBitsToFloat(a) + uintBitsToFloat(b))
+                    : a + b;
+            }
+
+            void main() {
+                uint chunk = gl_WorkGroupSize.x * ITEMS_PER_THREAD;
+                uint block = gl_WorkGroupID.y * gl_NumWorkGroups.x + gl_WorkGroupID.x;
+                // Whole workgroups past the end return, so the barriers below
+                // are still reached by every invocation that stays.
+                if (block * chunk >= params.count) {
+                    return;
+                }
+                uint lid = gl_LocalInvocationID.x;
+
+                // Every invocation sums its own consecutive elements first.
+                uint base = block * chunk + lid * ITEMS_PER_THREAD;
+                uint values[ITEMS_PER_THREAD];
+                uint total = 0u;
+                for (uint k = 0; k < ITEMS_PER_THREAD; k++) {
+                    values[k] = base + k < params.count ? src.data[base + k] : 0u;
+                    total = add(total, values[k]);
+                }
+
+                // Inclusive Hillis-Steele scan over the per invocation totals.
+                totals[lid] = total;
+                barrier();
+                for (uint offset = 1; offset < gl_WorkGroupSize.x; offset *= 2) {
+                    uint other = lid >= offset ? totals[lid - offset] : 0u;
+                    barrier();
+                    totals[lid] = add(totals[lid], other);
+                    barrier();
+                }
+
+                uint prefix = lid > 0 ? totals[lid - 1] : 0u;
+                for (uint k = 0; k < ITEMS_PER_THREAD; k++) {
+                    uint inclusive = add(prefix, values[k]);
+                    if (base + k < params.count) {
+                        dst.data[base + k] = params.exclusive != 0 ? prefix : inclusive;
+                    }
+                    prefix = inclusive;
+                }
+
+                if (lid == gl_WorkGroupSize.x - 1) {
+                    block_sums.data[block] = totals[lid];
+                }
+            }
+        ",
+    }
+}
+
+// Adds the scanned block sums back onto every element of their block.
+mod add_offsets {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 256, local_size_y = 1, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0) buffer Data {
+                uint data[];
+            } dst;
+
+            layout(set = 0, binding = 1) readonly buffer Offsets {
+                uint data[];
+            } offsets;
+
+            layout(push_constant) uniform Params {
+                uint count;
+                uint kind;
+                uint exclusive;
+            } params;
+
+            const uint KIND_FLOAT = 2;
+            const uint ITEMS_PER_THREAD = 4;
+
+            void main() {
+                uint chunk = gl_WorkGroupSize.x * ITEMS_PER_THREAD;
+                uint block = gl_WorkGroupID.y * gl_NumWorkGroups.x + gl_WorkGroupID.x;
+                if (block * chunk >= params.count) {
+                    return;
+                }
+                uint offset = offsets.data[block];
+
+                for (uint k = 0; k < ITEMS_PER_THREAD; k++) {
+                    uint i = block * chunk + k * gl_WorkGroupSize.x + gl_LocalInvocationID.x;
+                    if (i < params.count) {
+                        dst.data[i] = params.kind == KIND_FLOAT
+                            ? floatBitsToUint(uintBitsToFloat(dst.data[i]) + uintBitsToFloat(offset))
+                            : dst.data[i] + offset;
+                    }
+                }
+            }
+        ",
+    }
+}
+
+// Writes 1 for every element that passes the predicate and 0 otherwise.
+mod flag {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 256, local_size_y = 1, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0) readonly buffer Input {
+                uint data[];
+            } src;
+
+            layout(set = 0, binding = 1) buffer Flags {
+                uint data[];
+            } flags;
+
+            layout(push_constant) uniform Params {
+                uint count;
+                uint kind;
+                uint op;
+                uint operand;
+            } params;
+
+            const uint KIND_INT = 1;
+            const uint KIND_FLOAT = 2;
+
+            const uint OP_NON_ZERO = 0;
+            const uint OP_EQUAL = 1;
+            const uint OP_NOT_EQUAL = 2;
+            const uint OP_LESS = 3;
+            const uint OP_GREATER = 4;
+
+            // -1, 0 or 1 like a three way comparison.
+            int compare(uint a, uint b) {
+                if (params.kind == KIND_FLOAT) {
+                    float x = uintBitsToFloat(a);
+                    float y = uintBitsToFloat(b);
+                    return x < y ? -1 : x > y ? 1 : 0;
+                } else if (params.kind == KIND_INT) {
+                    return int(a) < int(b) ? -1 : int(a) > int(b) ? 1 : 0;
+                }
+                return a < b ? -1 : a > b ? 1 : 0;
+            }
+
+            void main() {
+                uint i = (gl_WorkGroupID.y * gl_NumWorkGroups.x + gl_WorkGroupID.x) * gl_WorkGroupSize.x
+                    + gl_LocalInvocationID.x;
+                if (i >= params.count) {
+                    return;
+                }
+
+                int c = compare(src.data[i], params.op == OP_NON_ZERO ? 0u : params.operand);
+                bool keep = params.op == OP_NON_ZERO || params.op == OP_NOT_EQUAL ? c != 0
+                    : params.op == OP_EQUAL ? c == 0
+                    : params.op == OP_LESS ? c < 0
+                    : c > 0;
+                flags.data[i] = keep ? 1u : 0u;
+            }
+        ",
+    }
+}
+
+// Moves every flagged element to its position from the exclusive scan of the
+// flags. The last invocation also writes how many elements were kept.
+mod scatter {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 256, local_size_y = 1, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0) readonly buffer Input {
+                uint data[];
+            } src;
+
+            layout(set = 0, binding = 1) readonly buffer Flags {
+                uint data[];
+            } flags;
+
+            layout(set = 0, binding = 2) readonly buffer Positions {
+                uint data[];
+            } positions;
+
+            layout(set = 0, binding = 3) buffer Output {
+                uint data[];
+            } dst;
+
+            layout(set = 0, binding = 4) buffer Count {
+                uint value;
+            } kept;
+
+            layout(push_constant) uniform Params {
+                uint count;
+            } params;
+
+            void main() {
+                uint i = (gl_WorkGroupID.y * gl_NumWorkGroups.x + gl_WorkGroupID.x) * gl_WorkGroupSize.x
+                    + gl_LocalInvocationID.x;
+                if (i >= params.count) {
+                    return;
+                }
+
+                if (flags.data[i] != 0) {
+                    dst.data[positions.data[i]] = src.data[i];
+                }
+                if (i == params.count - 1) {
+                    kept.value = positions.data[i] + flags.data[i];
+                }
+            }
+        ",
+    }
+}
+
+// Prefix sums and stream compaction over storage buffers. Scans of more than
+// one block scan the block sums recursively and add them back afterwards.
+pub struct Scanner {
+    queue: Arc<Queue>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    descriptor_set_allocator: StandardDescriptorSetAllocator,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+    scan_blocks: Arc<ComputePipeline>,
+    add_offsets: Arc<ComputePipeline>,
+    flag: Arc<ComputePipeline>,
+    scatter: Arc<ComputePipeline>,
+}
+
+impl Scanner {
+    pub fn new(queue: Arc<Queue>, memory_allocator: Arc<StandardMemoryAllocator>) -> Self {
+        let device = queue.device().clone();
+        let pipeline = |shader| util::create_compute_pipeline(device.clone(), shader);
+        let scan_blocks = pipeline(scan_blocks::load(device.clone()).unwrap());
+        let add_offsets = pipeline(add_offsets::load(device.clone()).unwrap());
+        let flag = pipeline(flag::load(device.clone()).unwrap());
+        let scatter = pipeline(scatter::load(device.clone()).unwrap());
+
+        let descriptor_set_allocator =
+            StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+        let command_buffer_allocator = StandardCommandBufferAllocator::new(
+            device.clone(),
+            StandardCommandBufferAllocatorCreateInfo::default(),
+        );
+
+        Scanner {
+            queue,
+            memory_allocator,
+            descriptor_set_allocator,
+            command_buffer_allocator,
+            scan_blocks,
+            add_offsets,
+            flag,
+            scatter,
+        }
+    }
+
+    // Element i of the result is the sum of elements 0..=i.
+    pub fn inclusive_scan<T: Element>(&self, input: &Subbuffer<[T]>) -> Subbuffer<[T]> {
+        self.scan(input, false)
+    }
+
+    // Element i of the result is the sum of elements 0..i, starting at 0.
+    pub fn exclusive_scan<T: Element>(&self, input: &Subbuffer<[T]>) -> Subbuffer<[T]> {
+        self.scan(input, true)
+    }
+
+    fn scan<T: Element>(&self, input: &Subbuffer<[T]>, exclusive: bool) -> Subbuffer<[T]> {
+        let output = util::create_storage_buffer::<T>(
+            &self.memory_allocator,
+            input.len(),
+            MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+        );
+
+        let mut builder = util::begin_one_time(&self.command_buffer_allocator, &self.queue);
+        self.record_scan(
+            &mut builder,
+            input.as_bytes().clone(),
+            output.as_bytes().clone(),
+            input.len() as u32,
+            T::KIND,
+            exclusive,
+        );
+        util::submit_and_wait(builder, &self.queue);
+
+        output
+    }
+
+    // Keeps the elements that pass `predicate`, in their original order. Only
+    // the first `count` elements of the returned buffer are valid.
+    pub fn compact<T: Element>(
+        &self,
+        input: &Subbuffer<[T]>,
+        predicate: Predicate<T>,
+    ) -> (Subbuffer<[T]>, u32) {
+        let count = input.len() as u32;
+        let flags = util::create_storage_buffer::<u32>(
+            &self.memory_allocator,
+            input.len(),
+            MemoryTypeFilter::PREFER_DEVICE,
+        );
+        let positions = util::create_storage_buffer::<u32>(
+            &self.memory_allocator,
+            input.len(),
+            MemoryTypeFilter::PREFER_DEVICE,
+        );
+        let output = util::create_storage_buffer::<T>(
+            &self.memory_allocator,
+            input.len(),
+            MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+        );
+        let kept = util::create_storage_buffer::<u32>(
+            &self.memory_allocator,
+            1,
+            MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+        );
+
+        let (op, operand) = predicate.encode();
+        let mut builder = util::begin_one_time(&self.command_buffer_allocator, &self.queue);
+        util::dispatch_storage(
+            &mut builder,
+            &self.flag,
+            [input.as_bytes().clone(), flags.as_bytes().clone()],
+            FlagParams {
+                count,
+                kind: T::KIND,
+                op,
+                operand,
+            },
+            count.div_ceil(WORKGROUP_SIZE),
+            &self.descriptor_set_allocator,
+        );
+        self.record_scan(
+            &mut builder,
+            flags.as_bytes().clone(),
+            positions.as_bytes().clone(),
+            count,
+            u32::KIND,
+            true,
+        );
+        util::dispatch_storage(
+            &mut builder,
+            &self.scatter,
+            [
+                input.as_bytes().clone(),
+                flags.into_bytes(),
+                positions.into_bytes(),
+                output.as_bytes().clone(),
+                kept.as_bytes().clone(),
+            ],
+            ScatterParams { count },
+            count.div_ceil(WORKGROUP_SIZE),
+            &self.descriptor_set_allocator,
+        );
+        util::submit_and_wait(builder, &self.queue);
+
+        let kept = kept.read().unwrap()[0];
+        (output, kept)
+    }
+
+    // Records the scan of `count` words of `input` into `output` without
+    // submitting it, so it can be part of a larger command buffer.
+    pub fn record_scan<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        input: Subbuffer<[u8]>,
+        output: Subbuffer<[u8]>,
+        count: u32,
+        kind: u32,
+        exclusive: bool,
+    ) {
+        let blocks = count.div_ceil(ITEMS_PER_WORKGROUP);
+        let block_sums = util::create_storage_buffer::<u32>(
+            &self.memory_allocator,
+            blocks as u64,
+            MemoryTypeFilter::PREFER_DEVICE,
+        );
+        let params = ScanParams {
+            count,
+            kind,
+            exclusive: exclusive as u32,
+        };
+
+        util::dispatch_storage(
+            builder,
+            &self.scan_blocks,
+            [input, output.clone(), block_sums.as_bytes().clone()],
+            params,
+            blocks,
+            &self.descriptor_set_allocator,
+        );
+
+        if blocks > 1 {
+            // Each block needs the sum of all blocks before it.
+            let block_offsets = util::create_storage_buffer::<u32>(
+                &self.memory_allocator,
+                blocks as u64,
+                MemoryTypeFilter::PREFER_DEVICE,
+            );
+            self.record_scan(
+                builder,
+                block_sums.into_bytes(),
+                block_offsets.as_bytes().clone(),
+                blocks,
+                kind,
+                true,
+            );
+            util::dispatch_storage(
+                builder,
+                &self.add_offsets,
+                [output, block_offsets.into_bytes()],
+                params,
+                blocks,
+                &self.descriptor_set_allocator,
+            );
+        }
+    }
+}
@@ -0,0 +1,400 @@
+use std::sync::Arc;
+
+use vulkano::buffer::{BufferContents, Subbuffer};
+use vulkano::command_buffer::allocator::{
+    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::device::{DeviceOwned, Queue};
+use vulkano::memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::ComputePipeline;
+
+use crate::reduce::Element;
+use crate::scan::Scanner;
+use crate::util;
+
+// Must match the shaders.
+const WORKGROUP_SIZE: u32 = 256;
+const ITEMS_PER_WORKGROUP: u32 = WORKGROUP_SIZE * 4;
+const RADIX_BITS: u32 = 4;
+const RADIX: u32 = 1 << RADIX_BITS;
+
+// Keys are sorted as unsigned integers, one or two 32 bit words each with the
+// low word first.
+pub trait RadixKey: BufferContents + Copy + Ord {
+    const WORDS: u32;
+}
+
+impl RadixKey for u32 {
+    const WORDS: u32 = 1;
+}
+
+impl RadixKey for u64 {
+    const WORDS: u32 = 2;
+}
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct Params {
+    count: u32,
+    shift: u32,
+    key_words: u32,
+    has_values: u32,
+    blocks: u32,
+}
+
+// Counts the digits of each block. The counts are stored digit major, so an
+// exclusive scan over all of them gives every (digit, block) pair its first
+// output position.
+mod histogram {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 256, local_size_y = 1, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0) readonly buffer Keys {
+                uint data[];
+            } keys;
+
+            layout(set = 0, binding = 1) buffer Histograms {
+                uint data[];
+            } histograms;
+
+            layout(push_constant) uniform Params {
+                uint count;
+                uint shift;
+                uint key_words;
+                uint has_values;
+                uint blocks;
+            } params;
+
+            const uint ITEMS_PER_THREAD = 4;
+            const uint RADIX = 16;
+
+            shared uint counts[RADIX];
+
+            uint digit_of(uint i) {
+                uint word = params.key_words == 1 ? keys.data[i]
+                    : keys.data[2 * i + params.shift / 32];
+                return (word >> (params.shift % 32)) & (RADIX - 1);
+            }
+
+            void main() {
+                uint chunk = gl_WorkGroupSize.x * ITEMS_PER_THREAD;
+                uint block = gl_WorkGroupID.y * gl_NumWorkGroups.x + gl_WorkGroupID.x;
+                if (block >= params.blocks) {
+                    return;
+                }
+                uint lid = gl_LocalInvocationID.x;
+
+                if (lid < RADIX) {
+                    counts[lid] = 0u;
+                }
+                barrier();
+
+                for (uint k = 0; k < ITEMS_PER_THREAD; k++) {
+                    uint i = block * chunk + k * gl_WorkGroupSize.x + lid;
+                    if (i < params.count) {
+                        atomicAdd(counts[digit_of(i)], 1u);
+                    }
+                }
+                barrier();
+
+                if (lid < RADIX) {
+                    histograms.data[lid * params.blocks + block] = counts[lid];
+                }
+            }
+        ",
+    }
+}
+
+// Moves every key (and value) to its sorted position for the current digit.
+// Order within a digit is kept: blocks come in order from the scan, and inside
+// a block each invocation owns consecutive elements and adds up the counts of
+// the invocations before it.
+mod scatter {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 256, local_size_y = 1, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0) readonly buffer KeysIn {
+                uint data[];
+            } keys_in;
+
+            layout(set = 0, binding = 1) buffer KeysOut {
+                uint data[];
+            } keys_out;
+
+            layout(set = 0, binding = 2) readonly buffer ValuesIn {
+                uint data[];
+            } values_in;
+
+            layout(set = 0, binding = 3) buffer ValuesOut {
+                uint data[];
+            } values_out;
+
+            layout(set = 0, binding = 4) readonly buffer Offsets {
+                uint data[];
+            } offsets;
+
+            layout(push_constant) uniform Params {
+                uint count;
+                uint shift;
+                uint key_words;
+                uint has_values;
+                uint blocks;
+            } params;
+
+            const uint ITEMS_PER_THREAD = 4;
+            const uint RADIX = 16;
+            // Two 16 bit counters per word. A block has at most 1024
+            // elements, so they can't overflow into each other.
+            const uint WORDS = RADIX / 2;
+
+            shared uint prefix[WORDS * 256];
+
+            uint digit_of(uint i) {
+                uint word = params.key_words == 1 ? keys_in.data[i]
+                    : keys_in.data[2 * i + params.shift / 32];
+                return (word >> (params.shift % 32)) & (RADIX - 1);
+            }
+
+            void main() {
+                uint chunk = gl_WorkGroupSize.x * ITEMS_PER_THREAD;
+                uint block = gl_WorkGroupID.y * gl_NumWorkGroups.x + gl_WorkGroupID.x;
+                if (block >= params.blocks) {
+                    return;
+                }
+                uint lid = gl_LocalInvocationID.x;
+                uint base = block * chunk + lid * ITEMS_PER_THREAD;
+
+                // Rank of each element among this invocation's elements with
+                // the same digit.
+                uint counts[WORDS];
+                for (uint w = 0; w < WORDS; w++) {
+                    counts[w] = 0u;
+                }
+                uint digits[ITEMS_PER_THREAD];
+                uint ranks[ITEMS_PER_THREAD];
+                for (uint k = 0; k < ITEMS_PER_THREAD; k++) {
+                    digits[k] = RADIX;
+                    if (base + k < params.count) {
+                        uint d = digit_of(base + k);
+                        uint field = 16 * (d % 2);
+                        digits[k] = d;
+                        ranks[k] = (counts[d / 2] >> field) & 0xffffu;
+                        counts[d / 2] += 1u << field;
+                    }
+                }
+
+                // Inclusive Hillis-Steele scan of the packed counts.
+                for (uint w = 0; w < WORDS; w++) {
+                    prefix[w * 256 + lid] = counts[w];
+                }
+                barrier();
+                for (uint offset = 1; offset < gl_WorkGroupSize.x; offset *= 2) {
+                    uint other[WORDS];
+                    for (uint w = 0; w < WORDS; w++) {
+                        other[w] = lid >= offset ? prefix[w * 256 + lid - offset] : 0u;
+                    }
+                    barrier();
+                    for (uint w = 0; w < WORDS; w++) {
+                        prefix[w * 256 + lid] += other[w];
+                    }
+                    barrier();
+                }
+
+                for (uint k = 0; k < ITEMS_PER_THREAD; k++) {
+                    uint d = digits[k];
+                    if (d == RADIX) {
+                        continue;
+                    }
+                    uint field = 16 * (d % 2);
+                    // Subtracting our own counts makes the scan exclusive.
+                    uint before = ((prefix[(d / 2) * 256 + lid] - counts[d / 2]) >> field) & 0xffffu;
+                    uint dst = offsets.data[d * params.blocks + block] + before + ranks[k];
+                    uint src = base + k;
+
+                    if (params.key_words == 1) {
+                        keys_out.data[dst] = keys_in.data[src];
+                    } else {
+                        keys_out.data[2 * dst] = keys_in.data[2 * src];
+                        keys_out.data[2 * dst + 1] = keys_in.data[2 * src + 1];
+                    }
+                    if (params.has_values != 0) {
+                        values_out.data[dst] = values_in.data[src];
+                    }
+                }
+            }
+        ",
+    }
+}
+
+// Least significant digit radix sort, RADIX_BITS per pass. Each pass builds
+// per block histograms, scans them with `Scanner` and scatters. The passes
+// alternate between the input and a temporary buffer, and since there is an
+// even number of them the result ends up back in the input. The temporary
+// buffers are kept and only grow when a longer input comes along.
+pub struct RadixSorter {
+    queue: Arc<Queue>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    descriptor_set_allocator: StandardDescriptorSetAllocator,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+    scanner: Scanner,
+    histogram: Arc<ComputePipeline>,
+    scatter: Arc<ComputePipeline>,
+    // Bound in place of the values when sorting keys only.
+    no_values: Subbuffer<[u32]>,
+    temp_keys: Option<Subbuffer<[u32]>>,
+    temp_values: Option<Subbuffer<[u32]>>,
+    histograms: Option<Subbuffer<[u32]>>,
+    offsets: Option<Subbuffer<[u32]>>,
+}
+
+impl RadixSorter {
+    pub fn new(queue: Arc<Queue>, memory_allocator: Arc<StandardMemoryAllocator>) -> Self {
+        let device = queue.device().clone();
+        let histogram = util::create_compute_pipeline(
+            device.clone(),
+            histogram::load(device.clone()).expect("failed to create shader module"),
+        );
+        let scatter = util::create_compute_pipeline(
+            device.clone(),
+            scatter::load(device.clone()).expect("failed to create shader module"),
+        );
+
+        let descriptor_set_allocator =
+            StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+        let command_buffer_allocator = StandardCommandBufferAllocator::new(
+            device.clone(),
+            StandardCommandBufferAllocatorCreateInfo::default(),
+        );
+        let no_values =
+            util::create_storage_buffer(&memory_allocator, 2, MemoryTypeFilter::PREFER_DEVICE);
+
+        RadixSorter {
+            scanner: Scanner::new(queue.clone(), memory_allocator.clone()),
+            queue,
+            memory_allocator,
+            descriptor_set_allocator,
+            command_buffer_allocator,
+            histogram,
+            scatter,
+            no_values,
+            temp_keys: None,
+            temp_values: None,
+            histograms: None,
+            offsets: None,
+        }
+    }
+
+    // Sorts `keys` in place. It needs STORAGE_BUFFER usage.
+    pub fn sort<K: RadixKey>(&mut self, keys: &Subbuffer<[K]>) {
+        self.sort_impl(keys, None);
+    }
+
+    // Sorts `keys` in place and applies the same permutation to `values`.
+    // Elements with equal keys keep their order.
+    pub fn sort_by_key<K: RadixKey>(&mut self, keys: &Subbuffer<[K]>, values: &Subbuffer<[u32]>) {
+        assert_eq!(keys.len(), values.len());
+        self.sort_impl(keys, Some(values));
+    }
+
+    fn sort_impl<K: RadixKey>(&mut self, keys: &Subbuffer<[K]>, values: Option<&Subbuffer<[u32]>>) {
+        let count = keys.len() as u32;
+        let blocks = count.div_ceil(ITEMS_PER_WORKGROUP);
+
+        let scratch_len = (blocks * RADIX) as u64;
+        let temp_keys = scratch(
+            &mut self.temp_keys,
+            &self.memory_allocator,
+            keys.len() * K::WORDS as u64,
+        );
+        let has_values = values.is_some();
+        let (values, temp_values) = match values {
+            Some(values) => (
+                values.as_bytes().clone(),
+                scratch(&mut self.temp_values, &self.memory_allocator, values.len()).into_bytes(),
+            ),
+            None => (
+                self.no_values.clone().slice(0..1).into_bytes(),
+                self.no_values.clone().slice(1..2).into_bytes(),
+            ),
+        };
+        let histograms = scratch(&mut self.histograms, &self.memory_allocator, scratch_len);
+        let offsets = scratch(&mut self.offsets, &self.memory_allocator, scratch_len);
+
+        let mut buffers = [
+            (keys.as_bytes().clone(), values),
+            (temp_keys.into_bytes(), temp_values),
+        ];
+
+        let mut builder = util::begin_one_time(&self.command_buffer_allocator, &self.queue);
+
+        for shift in (0..K::WORDS * 32).step_by(RADIX_BITS as usize) {
+            let [(keys_in, values_in), (keys_out, values_out)] = buffers.clone();
+            let params = Params {
+                count,
+                shift,
+                key_words: K::WORDS,
+                has_values: has_values as u32,
+                blocks,
+            };
+            util::dispatch_storage(
+                &mut builder,
+                &self.histogram,
+                [keys_in.clone(), histograms.as_bytes().clone()],
+                params,
+                blocks,
+                &self.descriptor_set_allocator,
+            );
+            self.scanner.record_scan(
+                &mut builder,
+                histograms.as_bytes().clone(),
+                offsets.as_bytes().clone(),
+                blocks * RADIX,
+                u32::KIND,
+                true,
+            );
+            util::dispatch_storage(
+                &mut builder,
+                &self.scatter,
+                [
+                    keys_in,
+                    keys_out,
+                    values_in,
+                    values_out,
+                    offsets.as_bytes().clone(),
+                ],
+                params,
+                blocks,
+                &self.descriptor_set_allocator,
+            );
+            buffers.swap(0, 1);
+        }
+
+        util::submit_and_wait(builder, &self.queue);
+    }
+}
+
+// The first `len` elements of `buffer`, which is replaced first if it is too
+// short.
+fn scratch(
+    buffer: &mut Option<Subbuffer<[u32]>>,
+    allocator: &Arc<StandardMemoryAllocator>,
+    len: u64,
+) -> Subbuffer<[u32]> {
+    if buffer.as_ref().is_none_or(|b| b.len() < len) {
+        *buffer = Some(util::create_storage_buffer(
+            allocator,
+            len,
+            MemoryTypeFilter::PREFER_DEVICE,
+        ));
+    }
+    buffer.clone().unwrap().slice(0..len)
+}
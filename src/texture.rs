@@ -0,0 +1,398 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use image::{ImageBuffer, RgbaImage};
+use vulkano::buffer::{BufferContents, BufferUsage};
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, BlitImageInfo, BufferImageCopy, CopyBufferToImageInfo,
+    CopyImageToBufferInfo, ImageBlit,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::device::physical::PhysicalDevice;
+use vulkano::device::{Device, DeviceOwned, Queue};
+use vulkano::format::{Format, FormatFeatures};
+use vulkano::image::sampler::{
+    Filter, Sampler, SamplerAddressMode, SamplerCreateInfo, SamplerMipmapMode, LOD_CLAMP_NONE,
+};
+use vulkano::image::view::{ImageView, ImageViewCreateInfo};
+use vulkano::image::{
+    mip_level_extent, Image, ImageCreateInfo, ImageSubresourceLayers, ImageSubresourceRange,
+    ImageType, ImageUsage,
+};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::{Pipeline, PipelineBindPoint};
+
+use crate::util;
+
+// Colors painted by hand or taken from photos are stored gamma encoded and
+// should be sampled as sRGB, so the shader sees linear values. Data such as
+// normal or roughness maps is already linear and must be left untouched.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+impl ColorSpace {
+    pub fn rgba8_format(self) -> Format {
+        match self {
+            ColorSpace::Srgb => Format::R8G8B8A8_SRGB,
+            ColorSpace::Linear => Format::R8G8B8A8_UNORM,
+        }
+    }
+}
+
+// Decodes any format the `image` crate understands (PNG, JPEG, ...) and
+// records its upload, see `upload_rgba8`.
+pub fn load<L>(
+    path: impl AsRef<Path>,
+    color_space: ColorSpace,
+    allocator: &Arc<StandardMemoryAllocator>,
+    builder: &mut AutoCommandBufferBuilder<L>,
+) -> Arc<ImageView> {
+    let pixels = image::open(path)
+        .expect("failed to load texture")
+        .to_rgba8();
+    upload_rgba8(&pixels, color_space.rgba8_format(), allocator, builder)
+}
+
+pub fn create_sampler(
+    device: Arc<Device>,
+    filter: Filter,
+    address_mode: SamplerAddressMode,
+) -> Arc<Sampler> {
+    Sampler::new(
+        device,
+        SamplerCreateInfo {
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_mode: match filter {
+                Filter::Nearest => SamplerMipmapMode::Nearest,
+                _ => SamplerMipmapMode::Linear,
+            },
+            address_mode: [address_mode; 3],
+            // The default clamps to level 0, which would leave mip chains
+            // unused.
+            lod: 0.0..=LOD_CLAMP_NONE,
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+// Records the upload of `pixels` into a new sampled image. The returned view
+// is only usable once the command buffer has been executed.
+pub fn upload_rgba8<L>(
+    pixels: &RgbaImage,
+    format: Format,
+    allocator: &Arc<StandardMemoryAllocator>,
+    builder: &mut AutoCommandBufferBuilder<L>,
+) -> Arc<ImageView> {
+    let staging_buffer = util::create_buffer(
+        pixels.as_raw().iter().copied(),
+        allocator,
+        BufferUsage::TRANSFER_SRC,
+        MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+    );
+
+    let image = Image::new(
+        allocator.clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format,
+            extent: [pixels.width(), pixels.height(), 1],
+            usage: ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    builder
+        .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(
+            staging_buffer,
+            image.clone(),
+        ))
+        .unwrap();
+
+    ImageView::new_default(image).unwrap()
+}
+
+// How the smaller mip levels get filled in. Blits are the fast path, but
+// they are only available when the format supports linear filtering of blit
+// sources. The compute fallback only handles R8G8B8A8_UNORM and
+// R8G8B8A8_SRGB.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MipmapMethod {
+    Blit,
+    Compute,
+}
+
+impl MipmapMethod {
+    // None when the format can neither be blitted nor handled by the compute
+    // fallback.
+    pub fn for_format(physical_device: &PhysicalDevice, format: Format) -> Option<Self> {
+        let features = physical_device
+            .format_properties(format)
+            .unwrap()
+            .optimal_tiling_features;
+        if features.contains(
+            FormatFeatures::BLIT_SRC
+                | FormatFeatures::BLIT_DST
+                | FormatFeatures::SAMPLED_IMAGE_FILTER_LINEAR,
+        ) {
+            Some(MipmapMethod::Blit)
+        } else if matches!(format, Format::R8G8B8A8_UNORM | Format::R8G8B8A8_SRGB) {
+            Some(MipmapMethod::Compute)
+        } else {
+            None
+        }
+    }
+}
+
+mod downsample {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0, rgba8) uniform readonly image2D src;
+            layout(set = 0, binding = 1, rgba8) uniform writeonly image2D dst;
+
+            layout(push_constant) uniform Params {
+                uint srgb;
+            } params;
+
+            vec3 to_linear(vec3 c) {
+                return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), step(0.04045, c));
+            }
+
+            vec3 to_srgb(vec3 c) {
+                return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+            }
+
+            vec4 fetch(ivec2 p) {
+                // Clamping handles odd sizes and levels that are already one
+                // texel wide or high.
+                vec4 c = imageLoad(src, min(p, imageSize(src) - 1));
+                if (params.srgb != 0) {
+                    c.rgb = to_linear(c.rgb);
+                }
+                return c;
+            }
+
+            void main() {
+                ivec2 p = ivec2(gl_GlobalInvocationID.xy);
+                if (any(greaterThanEqual(p, imageSize(dst)))) {
+                    return;
+                }
+
+                ivec2 s = p * 2;
+                vec4 c = (fetch(s) + fetch(s + ivec2(1, 0)) + fetch(s + ivec2(0, 1)) + fetch(s + ivec2(1, 1))) * 0.25;
+                if (params.srgb != 0) {
+                    c.rgb = to_srgb(c.rgb);
+                }
+                imageStore(dst, p, c);
+            }
+        ",
+    }
+}
+
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct DownsampleParams {
+    srgb: u32,
+}
+
+// Like `upload_rgba8`, but with a full mip chain that is generated on the GPU
+// right after the copy.
+pub fn upload_rgba8_mipmapped<L>(
+    pixels: &RgbaImage,
+    format: Format,
+    method: MipmapMethod,
+    allocator: &Arc<StandardMemoryAllocator>,
+    builder: &mut AutoCommandBufferBuilder<L>,
+) -> Arc<ImageView> {
+    let staging_buffer = util::create_buffer(
+        pixels.as_raw().iter().copied(),
+        allocator,
+        BufferUsage::TRANSFER_SRC,
+        MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+    );
+
+    // TRANSFER_SRC is for the blits and for reading levels back.
+    let mut usage = ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED;
+    if method == MipmapMethod::Compute {
+        usage |= ImageUsage::STORAGE;
+    }
+    let image =
+        util::create_mipmapped_image(allocator, format, [pixels.width(), pixels.height()], usage);
+
+    builder
+        .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(
+            staging_buffer,
+            image.clone(),
+        ))
+        .unwrap();
+    generate_mipmaps(&image, method, builder);
+
+    // The image may have been created with extended usage, so the view has to
+    // ask for sampling only.
+    ImageView::new(
+        image.clone(),
+        ImageViewCreateInfo {
+            usage: ImageUsage::SAMPLED,
+            ..ImageViewCreateInfo::from_image(&image)
+        },
+    )
+    .unwrap()
+}
+
+// Fills mip levels 1.. from level 0, each level downsampled from the one
+// before it. `method` has to suit the format, see `MipmapMethod::for_format`.
+pub fn generate_mipmaps<L>(
+    image: &Arc<Image>,
+    method: MipmapMethod,
+    builder: &mut AutoCommandBufferBuilder<L>,
+) {
+    match method {
+        MipmapMethod::Blit => blit_mipmaps(image, builder),
+        MipmapMethod::Compute => compute_mipmaps(image, builder),
+    }
+}
+
+fn blit_mipmaps<L>(image: &Arc<Image>, builder: &mut AutoCommandBufferBuilder<L>) {
+    for level in 1..image.mip_levels() {
+        let src_extent = mip_level_extent(image.extent(), level - 1).unwrap();
+        let dst_extent = mip_level_extent(image.extent(), level).unwrap();
+
+        builder
+            .blit_image(BlitImageInfo {
+                regions: [ImageBlit {
+                    src_subresource: ImageSubresourceLayers {
+                        mip_level: level - 1,
+                        ..image.subresource_layers()
+                    },
+                    src_offsets: [[0; 3], src_extent],
+                    dst_subresource: ImageSubresourceLayers {
+                        mip_level: level,
+                        ..image.subresource_layers()
+                    },
+                    dst_offsets: [[0; 3], dst_extent],
+                    ..Default::default()
+                }]
+                .into(),
+                filter: Filter::Linear,
+                ..BlitImageInfo::images(image.clone(), image.clone())
+            })
+            .unwrap();
+    }
+}
+
+fn compute_mipmaps<L>(image: &Arc<Image>, builder: &mut AutoCommandBufferBuilder<L>) {
+    let srgb = match image.format() {
+        Format::R8G8B8A8_UNORM => 0,
+        Format::R8G8B8A8_SRGB => 1,
+        format => panic!("no compute mipmap fallback for {format:?}"),
+    };
+
+    let device = image.device().clone();
+    let shader = downsample::load(device.clone()).expect("failed to create shader module");
+    let pipeline = util::create_compute_pipeline(device.clone(), shader);
+    let descriptor_set_allocator = StandardDescriptorSetAllocator::new(device, Default::default());
+
+    // Both formats are read and written through UNORM views, the shader does
+    // the sRGB conversion itself.
+    let level_view = |level: u32| {
+        ImageView::new(
+            image.clone(),
+            ImageViewCreateInfo {
+                format: Format::R8G8B8A8_UNORM,
+                subresource_range: ImageSubresourceRange {
+                    mip_levels: level..level + 1,
+                    ..image.subresource_range()
+                },
+                usage: ImageUsage::STORAGE,
+                ..ImageViewCreateInfo::from_image(image)
+            },
+        )
+        .unwrap()
+    };
+
+    builder
+        .bind_pipeline_compute(pipeline.clone())
+        .unwrap()
+        .push_constants(pipeline.layout().clone(), 0, DownsampleParams { srgb })
+        .unwrap();
+
+    for level in 1..image.mip_levels() {
+        let extent = mip_level_extent(image.extent(), level).unwrap();
+        let set = PersistentDescriptorSet::new(
+            &descriptor_set_allocator,
+            pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::image_view(0, level_view(level - 1)),
+                WriteDescriptorSet::image_view(1, level_view(level)),
+            ],
+            [],
+        )
+        .unwrap();
+
+        builder
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                pipeline.layout().clone(),
+                0,
+                set,
+            )
+            .unwrap()
+            .dispatch([extent[0].div_ceil(8), extent[1].div_ceil(8), 1])
+            .unwrap();
+    }
+}
+
+// Copies a single mip level of an RGBA8 image back to the host.
+pub fn read_mip_level(
+    image: &Arc<Image>,
+    level: u32,
+    allocator: &Arc<StandardMemoryAllocator>,
+    command_buffer_allocator: &StandardCommandBufferAllocator,
+    queue: &Arc<Queue>,
+) -> RgbaImage {
+    assert_eq!(image.format().block_size(), 4, "expected an RGBA8 image");
+    let extent = mip_level_extent(image.extent(), level).expect("mip level out of range");
+
+    let buf = util::create_buffer(
+        (0..extent[0] * extent[1] * 4).map(|_| 0u8),
+        allocator,
+        BufferUsage::TRANSFER_DST,
+        MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+    );
+
+    let mut builder = util::begin_one_time(command_buffer_allocator, queue);
+    builder
+        .copy_image_to_buffer(CopyImageToBufferInfo {
+            regions: [BufferImageCopy {
+                image_subresource: ImageSubresourceLayers {
+                    mip_level: level,
+                    ..image.subresource_layers()
+                },
+                image_extent: extent,
+                ..Default::default()
+            }]
+            .into(),
+            ..CopyImageToBufferInfo::image_buffer(image.clone(), buf.clone())
+        })
+        .unwrap();
+    util::submit_and_wait(builder, queue);
+
+    let buffer_content = buf.read().unwrap();
+    ImageBuffer::from_raw(extent[0], extent[1], buffer_content.to_vec()).unwrap()
+}
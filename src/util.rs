@@ -2,21 +2,33 @@ use std::sync::Arc;
 
 use vulkano::{
     buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        PrimaryAutoCommandBuffer,
+    },
     descriptor_set::{
         allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
     },
-    device::{Device, DeviceCreateInfo, Queue, QueueCreateInfo, QueueFlags},
-    format::Format,
-    image::{Image, ImageCreateInfo, ImageType, ImageUsage},
+    device::{
+        physical::PhysicalDevice, Device, DeviceCreateInfo, DeviceOwned, Queue, QueueCreateInfo,
+        QueueFlags,
+    },
+    format::{Format, FormatFeatures},
+    image::{
+        max_mip_levels, Image, ImageCreateFlags, ImageCreateInfo, ImageType, ImageUsage,
+        SampleCount,
+    },
     instance::{Instance, InstanceCreateFlags, InstanceCreateInfo},
     memory::allocator::{
         AllocationCreateInfo, FreeListAllocator, GenericMemoryAllocator, MemoryTypeFilter,
     },
     pipeline::{
         compute::ComputePipelineCreateInfo, layout::PipelineDescriptorSetLayoutCreateInfo,
-        ComputePipeline, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo,
+        ComputePipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo,
     },
     shader::ShaderModule,
+    sync::{self, GpuFuture},
     VulkanLibrary,
 };
 
@@ -99,6 +111,16 @@ where
     .expect("failed to create buffer")
 }
 
+// xorshift32, good enough for test data.
+pub fn random_words(count: usize, mut state: u32) -> impl Iterator<Item = u32> {
+    (0..count).map(move |_| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state
+    })
+}
+
 pub fn create_image(
     allocator: &Arc<GenericMemoryAllocator<FreeListAllocator>>,
     usage: ImageUsage,
@@ -121,10 +143,194 @@ pub fn create_image(
     .unwrap()
 }
 
+// A plain 2D image of any format, e.g. for render targets other than the
+// RGBA8 output of `create_image`.
+pub fn create_render_target(
+    allocator: &Arc<GenericMemoryAllocator<FreeListAllocator>>,
+    format: Format,
+    extent: [u32; 2],
+    usage: ImageUsage,
+) -> Arc<Image> {
+    Image::new(
+        allocator.clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format,
+            extent: [extent[0], extent[1], 1],
+            usage,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+// Allocates the full mip chain, down to a single texel.
+pub fn create_mipmapped_image(
+    allocator: &Arc<GenericMemoryAllocator<FreeListAllocator>>,
+    format: Format,
+    extent: [u32; 2],
+    usage: ImageUsage,
+) -> Arc<Image> {
+    let format_features = allocator
+        .device()
+        .physical_device()
+        .format_properties(format)
+        .unwrap()
+        .optimal_tiling_features;
+    // sRGB formats usually can't be used as storage images. They can still be
+    // written through a view with the matching UNORM format, as long as the
+    // image allows views of other formats and usages.
+    let flags = if usage.intersects(ImageUsage::STORAGE)
+        && !format_features.intersects(FormatFeatures::STORAGE_IMAGE)
+    {
+        ImageCreateFlags::MUTABLE_FORMAT | ImageCreateFlags::EXTENDED_USAGE
+    } else {
+        ImageCreateFlags::empty()
+    };
+
+    Image::new(
+        allocator.clone(),
+        ImageCreateInfo {
+            flags,
+            image_type: ImageType::Dim2d,
+            format,
+            extent: [extent[0], extent[1], 1],
+            mip_levels: max_mip_levels([extent[0], extent[1], 1]),
+            usage,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+// Candidates in order of preference. D32_SFLOAT is widely supported on
+// desktop, D24_UNORM_S8_UINT is the common fallback elsewhere.
+const DEPTH_FORMATS: [Format; 4] = [
+    Format::D32_SFLOAT,
+    Format::D32_SFLOAT_S8_UINT,
+    Format::D24_UNORM_S8_UINT,
+    Format::D16_UNORM,
+];
+
+pub fn find_depth_format(physical_device: &PhysicalDevice) -> Format {
+    DEPTH_FORMATS
+        .into_iter()
+        .find(|&format| {
+            physical_device
+                .format_properties(format)
+                .unwrap()
+                .optimal_tiling_features
+                .contains(FormatFeatures::DEPTH_STENCIL_ATTACHMENT)
+        })
+        .expect("no supported depth format")
+}
+
+pub fn create_depth_image(
+    allocator: &Arc<GenericMemoryAllocator<FreeListAllocator>>,
+    format: Format,
+    extent: [u32; 2],
+) -> Arc<Image> {
+    Image::new(
+        allocator.clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format,
+            extent: [extent[0], extent[1], 1],
+            usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+// Returns the highest sample count the device supports for both color and
+// depth attachments that does not exceed `requested`.
+pub fn choose_sample_count(physical_device: &PhysicalDevice, requested: u32) -> SampleCount {
+    let requested = SampleCount::try_from(requested)
+        .expect("sample count must be a power of two between 1 and 64");
+    let properties = physical_device.properties();
+    let supported = properties
+        .framebuffer_color_sample_counts
+        .intersection(properties.framebuffer_depth_sample_counts);
+
+    let sample_count = [
+        SampleCount::Sample64,
+        SampleCount::Sample32,
+        SampleCount::Sample16,
+        SampleCount::Sample8,
+        SampleCount::Sample4,
+        SampleCount::Sample2,
+        SampleCount::Sample1,
+    ]
+    .into_iter()
+    .find(|&count| u32::from(count) <= u32::from(requested) && supported.contains_enum(count))
+    .unwrap();
+
+    if sample_count != requested {
+        println!(
+            "{}x MSAA is not supported, falling back to {}x",
+            u32::from(requested),
+            u32::from(sample_count)
+        );
+    }
+    sample_count
+}
+
+pub fn create_multisampled_image(
+    allocator: &Arc<GenericMemoryAllocator<FreeListAllocator>>,
+    format: Format,
+    extent: [u32; 2],
+    samples: SampleCount,
+) -> Arc<Image> {
+    Image::new(
+        allocator.clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format,
+            extent: [extent[0], extent[1], 1],
+            samples,
+            // Only the resolved image is ever read, so the multisampled one
+            // never needs to leave tile memory on GPUs that support it.
+            usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
 pub fn create_descriptor_set<T>(
     compute_pipeline: &ComputePipeline,
     data_buffer: &Subbuffer<[T]>,
     descriptor_set_allocator: &StandardDescriptorSetAllocator,
+) -> Arc<PersistentDescriptorSet> {
+    create_storage_descriptor_set(
+        compute_pipeline,
+        [data_buffer.as_bytes().clone()],
+        descriptor_set_allocator,
+    )
+}
+
+// Binds each buffer to the binding matching its position, all in set 0.
+pub fn create_storage_descriptor_set(
+    compute_pipeline: &ComputePipeline,
+    buffers: impl IntoIterator<Item = Subbuffer<[u8]>>,
+    descriptor_set_allocator: &StandardDescriptorSetAllocator,
 ) -> Arc<PersistentDescriptorSet> {
     let pipeline_layout = compute_pipeline.layout();
     let descriptor_set_layouts = pipeline_layout.set_layouts();
@@ -136,12 +342,102 @@ pub fn create_descriptor_set<T>(
     PersistentDescriptorSet::new(
         descriptor_set_allocator,
         descriptor_set_layout.clone(),
-        [WriteDescriptorSet::buffer(0, data_buffer.clone())], // 0 is the binding
+        buffers
+            .into_iter()
+            .enumerate()
+            .map(|(binding, buffer)| WriteDescriptorSet::buffer(binding as u32, buffer)),
         [],
     )
     .unwrap()
 }
 
+// A primary command buffer for `submit_and_wait`.
+pub fn begin_one_time(
+    command_buffer_allocator: &StandardCommandBufferAllocator,
+    queue: &Arc<Queue>,
+) -> AutoCommandBufferBuilder<PrimaryAutoCommandBuffer> {
+    AutoCommandBufferBuilder::primary(
+        command_buffer_allocator,
+        queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap()
+}
+
+// Builds and submits the commands, then blocks until the GPU is done.
+pub fn submit_and_wait(
+    builder: AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    queue: &Arc<Queue>,
+) {
+    let command_buffer = builder.build().unwrap();
+    sync::now(queue.device().clone())
+        .then_execute(queue.clone(), command_buffer)
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap()
+        .wait(None)
+        .unwrap();
+}
+
+// Binds `buffers` like `create_storage_descriptor_set` and dispatches
+// `workgroups` one dimensional workgroups. Counts past the device limit spill
+// into y, so shaders have to use
+// `gl_WorkGroupID.y * gl_NumWorkGroups.x + gl_WorkGroupID.x` as the index and
+// skip the ones past the end.
+pub fn dispatch_storage<L, P: BufferContents>(
+    builder: &mut AutoCommandBufferBuilder<L>,
+    compute_pipeline: &Arc<ComputePipeline>,
+    buffers: impl IntoIterator<Item = Subbuffer<[u8]>>,
+    push_constants: P,
+    workgroups: u32,
+    descriptor_set_allocator: &StandardDescriptorSetAllocator,
+) {
+    let descriptor_set =
+        create_storage_descriptor_set(compute_pipeline, buffers, descriptor_set_allocator);
+    let max_x = compute_pipeline
+        .device()
+        .physical_device()
+        .properties()
+        .max_compute_work_group_count[0];
+    let x = workgroups.clamp(1, max_x);
+
+    builder
+        .bind_pipeline_compute(compute_pipeline.clone())
+        .unwrap()
+        .bind_descriptor_sets(
+            PipelineBindPoint::Compute,
+            compute_pipeline.layout().clone(),
+            0,
+            descriptor_set,
+        )
+        .unwrap()
+        .push_constants(compute_pipeline.layout().clone(), 0, push_constants)
+        .unwrap()
+        .dispatch([x, workgroups.div_ceil(x).max(1), 1])
+        .unwrap();
+}
+
+// An uninitialized buffer for compute shaders to write into.
+pub fn create_storage_buffer<T: BufferContents>(
+    allocator: &Arc<GenericMemoryAllocator<FreeListAllocator>>,
+    len: u64,
+    type_filter: MemoryTypeFilter,
+) -> Subbuffer<[T]> {
+    Buffer::new_slice(
+        allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: type_filter,
+            ..Default::default()
+        },
+        len,
+    )
+    .expect("failed to create buffer")
+}
+
 pub fn create_compute_pipeline(
     device: Arc<Device>,
     shader: Arc<ShaderModule>,
@@ -0,0 +1,327 @@
+use std::sync::Arc;
+
+use vulkano::command_buffer::allocator::{
+    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CommandBufferExecFuture, CommandBufferUsage, PrimaryAutoCommandBuffer,
+};
+use vulkano::device::physical::PhysicalDevice;
+use vulkano::device::{
+    Device, DeviceCreateInfo, DeviceExtensions, Queue, QueueCreateInfo, QueueFlags,
+};
+use vulkano::format::{Format, FormatFeatures, NumericFormat};
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageUsage};
+use vulkano::instance::{Instance, InstanceCreateFlags, InstanceCreateInfo};
+use vulkano::swapchain::{
+    self, PresentFuture, Surface, Swapchain, SwapchainAcquireFuture, SwapchainCreateInfo,
+    SwapchainPresentInfo,
+};
+use vulkano::sync::future::{FenceSignalFuture, JoinFuture};
+use vulkano::sync::{self, GpuFuture};
+use vulkano::{Validated, VulkanError, VulkanLibrary};
+use winit::event_loop::EventLoop;
+use winit::window::{Window, WindowBuilder};
+
+type FrameFuture = FenceSignalFuture<
+    PresentFuture<
+        CommandBufferExecFuture<
+            JoinFuture<Box<dyn GpuFuture + Send + Sync>, SwapchainAcquireFuture>,
+        >,
+    >,
+>;
+
+// Owns a window and its swapchain. The device is created here as well, since
+// its queue has to be able to present to the window's surface.
+pub struct Presenter {
+    window: Arc<Window>,
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+    swapchain: Arc<Swapchain>,
+    images: Vec<Arc<ImageView>>,
+    recreate_swapchain: bool,
+    // One fence per swapchain image. Before an image is rendered to again we
+    // wait for the frame that last used it, so at most `images.len()` frames
+    // are in flight.
+    fences: Vec<Option<Arc<FrameFuture>>>,
+    previous_fence: usize,
+}
+
+impl Presenter {
+    pub fn new(event_loop: &EventLoop<()>, title: &str) -> Self {
+        let library = VulkanLibrary::new().expect("no local Vulkan library/DLL");
+        let instance = Instance::new(
+            library,
+            InstanceCreateInfo {
+                flags: InstanceCreateFlags::ENUMERATE_PORTABILITY,
+                enabled_extensions: Surface::required_extensions(event_loop),
+                ..Default::default()
+            },
+        )
+        .expect("failed to create instance");
+
+        let window = Arc::new(
+            WindowBuilder::new()
+                .with_title(title)
+                .build(event_loop)
+                .expect("failed to create window"),
+        );
+        let surface = Surface::from_window(instance.clone(), window.clone()).unwrap();
+
+        let device_extensions = DeviceExtensions {
+            khr_swapchain: true,
+            ..DeviceExtensions::empty()
+        };
+        let (physical_device, queue_family_index) = instance
+            .enumerate_physical_devices()
+            .expect("could not enumerate devices")
+            .filter(|p| p.supported_extensions().contains(&device_extensions))
+            .find_map(|p| {
+                p.queue_family_properties()
+                    .iter()
+                    .enumerate()
+                    .position(|(i, q)| {
+                        q.queue_flags
+                            .contains(QueueFlags::GRAPHICS | QueueFlags::COMPUTE)
+                            && p.surface_support(i as u32, &surface).unwrap_or(false)
+                    })
+                    .map(|i| (p.clone(), i as u32))
+            })
+            .expect("no device can present to the window");
+
+        let (device, mut queues) = Device::new(
+            physical_device.clone(),
+            DeviceCreateInfo {
+                queue_create_infos: vec![QueueCreateInfo {
+                    queue_family_index,
+                    ..Default::default()
+                }],
+                enabled_extensions: device_extensions,
+                ..Default::default()
+            },
+        )
+        .expect("failed to create device");
+        let queue = queues.next().unwrap();
+
+        let capabilities = physical_device
+            .surface_capabilities(&surface, Default::default())
+            .unwrap();
+        let (image_format, image_color_space) = choose_surface_format(&physical_device, &surface);
+
+        // Blitting into the swapchain images needs TRANSFER_DST, which not
+        // every surface supports. `can_blit` tells whether it was granted.
+        let image_usage = ImageUsage::COLOR_ATTACHMENT
+            | (capabilities.supported_usage_flags & ImageUsage::TRANSFER_DST);
+
+        let (swapchain, images) = Swapchain::new(
+            device.clone(),
+            surface,
+            SwapchainCreateInfo {
+                // One more than the minimum so acquiring doesn't have to wait
+                // for the presentation engine.
+                min_image_count: match capabilities.max_image_count {
+                    Some(max) => (capabilities.min_image_count + 1).min(max),
+                    None => capabilities.min_image_count + 1,
+                },
+                image_format,
+                image_color_space,
+                image_extent: window.inner_size().into(),
+                image_usage,
+                composite_alpha: capabilities
+                    .supported_composite_alpha
+                    .into_iter()
+                    .next()
+                    .unwrap(),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let command_buffer_allocator = StandardCommandBufferAllocator::new(
+            device.clone(),
+            StandardCommandBufferAllocatorCreateInfo::default(),
+        );
+
+        let fences = vec![None; images.len()];
+        Presenter {
+            window,
+            device,
+            queue,
+            command_buffer_allocator,
+            swapchain,
+            images: create_views(images),
+            recreate_swapchain: false,
+            fences,
+            previous_fence: 0,
+        }
+    }
+
+    pub fn window(&self) -> &Arc<Window> {
+        &self.window
+    }
+
+    pub fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &Arc<Queue> {
+        &self.queue
+    }
+
+    pub fn format(&self) -> Format {
+        self.swapchain.image_format()
+    }
+
+    pub fn extent(&self) -> [u32; 2] {
+        self.swapchain.image_extent()
+    }
+
+    // Whether the swapchain images can be the destination of a blit.
+    pub fn can_blit(&self) -> bool {
+        self.swapchain
+            .image_usage()
+            .intersects(ImageUsage::TRANSFER_DST)
+    }
+
+    // Call on `WindowEvent::Resized`. The swapchain is recreated before the
+    // next frame.
+    pub fn resized(&mut self) {
+        self.recreate_swapchain = true;
+    }
+
+    // Acquires a swapchain image, lets `record` fill it and presents it.
+    // Returns false if no frame was submitted. The image may have a different
+    // extent than in the previous frame, so `record` should check size
+    // dependent resources against it.
+    pub fn draw_frame(
+        &mut self,
+        record: impl FnOnce(&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, &Arc<ImageView>),
+    ) -> bool {
+        // A minimized window has a zero sized surface, which can't have a
+        // swapchain.
+        let window_extent: [u32; 2] = self.window.inner_size().into();
+        if window_extent.contains(&0) {
+            return false;
+        }
+
+        if self.recreate_swapchain {
+            self.recreate(window_extent);
+        }
+
+        let (image_index, suboptimal, acquire_future) =
+            match swapchain::acquire_next_image(self.swapchain.clone(), None)
+                .map_err(Validated::unwrap)
+            {
+                Ok(r) => r,
+                Err(VulkanError::OutOfDate) => {
+                    self.recreate_swapchain = true;
+                    return false;
+                }
+                Err(e) => panic!("failed to acquire next image: {e}"),
+            };
+        // Still presentable, but the surface changed in a way the swapchain
+        // doesn't match anymore.
+        if suboptimal {
+            self.recreate_swapchain = true;
+        }
+
+        let image_index_usize = image_index as usize;
+        if let Some(fence) = &self.fences[image_index_usize] {
+            fence.wait(None).unwrap();
+        }
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        record(&mut builder, &self.images[image_index_usize]);
+        let command_buffer = builder.build().unwrap();
+
+        // Chaining onto the previous frame orders this submission after it
+        // without blocking the CPU.
+        let previous_future = match self.fences[self.previous_fence].clone() {
+            Some(fence) => fence.boxed_send_sync(),
+            None => sync::now(self.device.clone()).boxed_send_sync(),
+        };
+
+        let future = previous_future
+            .join(acquire_future)
+            .then_execute(self.queue.clone(), command_buffer)
+            .unwrap()
+            .then_swapchain_present(
+                self.queue.clone(),
+                SwapchainPresentInfo::swapchain_image_index(self.swapchain.clone(), image_index),
+            )
+            .then_signal_fence_and_flush()
+            .map_err(Validated::unwrap);
+
+        self.previous_fence = image_index_usize;
+        match future {
+            Ok(future) => {
+                self.fences[image_index_usize] = Some(Arc::new(future));
+                true
+            }
+            Err(VulkanError::OutOfDate) => {
+                self.fences[image_index_usize] = None;
+                self.recreate_swapchain = true;
+                false
+            }
+            Err(e) => panic!("failed to present frame: {e}"),
+        }
+    }
+
+    fn recreate(&mut self, extent: [u32; 2]) {
+        let (swapchain, images) = self
+            .swapchain
+            .recreate(SwapchainCreateInfo {
+                image_extent: extent,
+                ..self.swapchain.create_info()
+            })
+            .expect("failed to recreate swapchain");
+
+        self.swapchain = swapchain;
+        self.images = create_views(images);
+        self.fences.resize(self.images.len(), None);
+        self.previous_fence = self.previous_fence.min(self.images.len() - 1);
+        self.recreate_swapchain = false;
+    }
+}
+
+// Prefers a UNORM format so colors are presented the same way the lessons
+// write them to PNG. Lessons blit into the swapchain images, so the format
+// needs to support that.
+fn choose_surface_format(
+    physical_device: &Arc<PhysicalDevice>,
+    surface: &Surface,
+) -> (Format, swapchain::ColorSpace) {
+    let formats: Vec<_> = physical_device
+        .surface_formats(surface, Default::default())
+        .unwrap()
+        .into_iter()
+        .filter(|(format, _)| {
+            physical_device
+                .format_properties(*format)
+                .unwrap()
+                .optimal_tiling_features
+                .contains(FormatFeatures::BLIT_DST | FormatFeatures::COLOR_ATTACHMENT)
+        })
+        .collect();
+
+    formats
+        .iter()
+        .find(|(format, _)| format.numeric_format_color() == Some(NumericFormat::UNORM))
+        .or(formats.first())
+        .copied()
+        .expect("no usable surface format")
+}
+
+fn create_views(images: Vec<Arc<Image>>) -> Vec<Arc<ImageView>> {
+    images
+        .into_iter()
+        .map(|image| ImageView::new_default(image).unwrap())
+        .collect()
+}